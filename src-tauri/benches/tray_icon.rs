@@ -0,0 +1,92 @@
+//! Tray icon rendering: the cached renderer against the old draw-everything path, over a
+//! minute of ticks and over state changes. Run with `cargo bench --bench tray_icon`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+#[path = "../src/tray_generator.rs"]
+#[allow(dead_code)]
+mod tray_generator;
+
+#[path = "tray_icon/legacy.rs"]
+mod legacy;
+
+use tray_generator::{IconState, Theme, TrayIconSpec};
+
+/// A minute of tracking as the tray loop sees it: the clock and the ring move every second.
+fn ticks() -> Vec<(String, f32)> {
+    (0..60)
+        .map(|s| (format!("1:23:{:02}", s), 0.4 + s as f32 / 3600.0))
+        .collect()
+}
+
+const STATES: [IconState; 5] = [
+    IconState::Idle,
+    IconState::Tracking,
+    IconState::Paused,
+    IconState::OnBreak,
+    IconState::LimitReached,
+];
+
+fn tracking((text, progress): &(String, f32)) -> TrayIconSpec<'_> {
+    TrayIconSpec {
+        text,
+        state: IconState::Tracking,
+        progress: Some(*progress),
+        theme: Theme::Light,
+    }
+}
+
+fn tick(c: &mut Criterion) {
+    let ticks = ticks();
+
+    let mut group = c.benchmark_group("tray_icon/minute_of_ticks");
+    group.bench_function("legacy", |b| {
+        b.iter(|| {
+            for t in &ticks {
+                black_box(legacy::generate_tray_icon(&tracking(t)));
+            }
+        })
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            for t in &ticks {
+                black_box(tray_generator::generate_tray_icon(&tracking(t)));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn state_change(c: &mut Criterion) {
+    // Every call misses the logo cache, the renderer's worst case
+    let specs: Vec<_> = STATES
+        .iter()
+        .zip([Theme::Light, Theme::Dark].iter().cycle())
+        .map(|(&state, &theme)| TrayIconSpec {
+            text: "1:23:45",
+            state,
+            progress: Some(0.5),
+            theme,
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("tray_icon/state_changes");
+    group.bench_function("legacy", |b| {
+        b.iter(|| {
+            for spec in &specs {
+                black_box(legacy::generate_tray_icon(spec));
+            }
+        })
+    });
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            for spec in &specs {
+                black_box(tray_generator::generate_tray_icon(spec));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tick, state_change);
+criterion_main!(benches);
@@ -0,0 +1,293 @@
+// The tray renderer as it was before caching: every call decodes the font and logo and
+// draws the whole icon at 4x before downscaling. Kept as the bench baseline.
+
+use super::tray_generator::{IconState, Theme, TrayIconSpec};
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use image::imageops::{resize, FilterType};
+use image::{ImageBuffer, Rgba};
+use imageproc::drawing::{draw_filled_circle_mut, draw_text_mut, text_size};
+use std::f32::consts::PI;
+use tauri::image::Image;
+
+#[cfg(target_os = "windows")]
+const BASE_HEIGHT: u32 = 64;
+
+#[cfg(not(target_os = "windows"))]
+const BASE_HEIGHT: u32 = 40;
+
+const FONT_DATA: &[u8] = include_bytes!("../../src/Roboto-Regular.ttf");
+const ICON_DATA: &[u8] = include_bytes!("../../icons/32x32.png");
+
+struct Palette {
+    background: Rgba<u8>,
+    text: Rgba<u8>,
+    /// Badge and progress arc; `None` for states without a badge.
+    accent: Option<Rgba<u8>>,
+    /// The unfilled part of the progress ring.
+    track: Rgba<u8>,
+}
+
+fn palette(state: IconState, theme: Theme) -> Palette {
+    let (background, text, track) = match theme {
+        Theme::Light => (
+            Rgba([255, 255, 255, 255]),
+            Rgba([10, 10, 10, 255]),
+            Rgba([220, 220, 220, 255]),
+        ),
+        Theme::Dark => (
+            Rgba([38, 38, 42, 255]),
+            Rgba([240, 240, 240, 255]),
+            Rgba([80, 80, 86, 255]),
+        ),
+    };
+    let accent = match state {
+        IconState::LoggedOut | IconState::Idle => None,
+        IconState::Tracking => Some(Rgba([34, 197, 94, 255])),
+        IconState::Paused => Some(Rgba([245, 158, 11, 255])),
+        IconState::OnBreak => Some(Rgba([59, 130, 246, 255])),
+        IconState::IdlePrompt => Some(Rgba([168, 85, 247, 255])),
+        IconState::LimitReached => Some(Rgba([239, 68, 68, 255])),
+    };
+    // Blocked is the one state worth shouting about
+    let text = match (state, accent) {
+        (IconState::LimitReached, Some(red)) => red,
+        _ => text,
+    };
+    Palette {
+        background,
+        text,
+        accent,
+        track,
+    }
+}
+
+pub fn generate_tray_icon(spec: &TrayIconSpec) -> Option<Image<'static>> {
+    let text = spec.text;
+    let palette = palette(spec.state, spec.theme);
+    let font = FontRef::try_from_slice(FONT_DATA).expect("Error constructing Font");
+
+    // We want a high-res render to ensure smooth text and rounded corners
+    let scale_factor = 4; // Render at 4x resolution
+
+    // "Bigger" text
+    #[cfg(target_os = "windows")]
+    let base_text_size = 42.0;
+
+    #[cfg(not(target_os = "windows"))]
+    let base_text_size = 32.0;
+    let render_scale = PxScale {
+        x: base_text_size * scale_factor as f32,
+        y: base_text_size * scale_factor as f32,
+    };
+
+    let scaled_font = font.as_scaled(render_scale);
+    let ascent = scaled_font.ascent();
+    let (text_w, _) = text_size(render_scale, &font, text);
+
+    // "Less packed" -> More padding
+    let base_icon_size = BASE_HEIGHT; // New size to match base_height
+    let render_icon_size = base_icon_size * scale_factor;
+
+    #[cfg(target_os = "windows")]
+    let base_padding_h = 32;
+
+    #[cfg(not(target_os = "windows"))]
+    let base_padding_h = 24;
+    let render_padding_h = base_padding_h * scale_factor;
+
+    // "Bigger" overall -> Taller height
+    let base_height = BASE_HEIGHT;
+    let render_height = base_height * scale_factor;
+
+    let render_width = render_icon_size + render_padding_h + text_w + render_padding_h;
+
+    let mut canvas = ImageBuffer::from_pixel(render_width, render_height, palette.background);
+
+    // 1. Draw Icon (inset to make room for the progress ring when there is one)
+    let progress = spec.progress.map(|p| p.clamp(0.0, 1.0));
+    let logo_size = if progress.is_some() {
+        render_icon_size * 5 / 8
+    } else {
+        render_icon_size
+    };
+    let logo_offset = ((render_icon_size - logo_size) / 2) as i64;
+    if let Ok(icon_img) = image::load_from_memory(ICON_DATA) {
+        let icon_resized = resize(
+            &icon_img.to_rgba8(),
+            logo_size,
+            logo_size,
+            FilterType::Lanczos3,
+        );
+        image::imageops::overlay(&mut canvas, &icon_resized, logo_offset, logo_offset);
+    }
+
+    // Progress ring and state badge, both around the logo
+    let center = render_icon_size as f32 / 2.0;
+    if let Some(progress) = progress {
+        let outer = center - scale_factor as f32;
+        let thickness = render_icon_size as f32 * 0.11;
+        let fill = palette.accent.unwrap_or(palette.text);
+        draw_arc(&mut canvas, center, outer, thickness, 1.0, palette.track);
+        draw_arc(&mut canvas, center, outer, thickness, progress, fill);
+    }
+    if let Some(accent) = palette.accent {
+        let radius = (render_icon_size as f32 * 0.16) as i32;
+        let at = (
+            render_icon_size as i32 - radius - scale_factor as i32,
+            render_icon_size as i32 - radius - scale_factor as i32,
+        );
+        // Ringed in the background color so it reads against the logo
+        draw_filled_circle_mut(
+            &mut canvas,
+            at,
+            radius + 2 * scale_factor as i32,
+            palette.background,
+        );
+        draw_filled_circle_mut(&mut canvas, at, radius, accent);
+    }
+
+    // 2. Centering Logic (Adjusted)
+    let text_x = (render_icon_size + render_padding_h) as i32;
+
+    // OPTICAL ADJUSTMENT:
+    // We take the center of the canvas and subtract half the ascent,
+    // then subtract a bit more (15% of render_height) to push it "up".
+    let optical_offset = (render_height as f32 * 0.10) as i32;
+    let text_y = ((render_height as f32 / 2.0 - ascent / 2.0) as i32) - optical_offset;
+
+    let text_color = palette.text;
+
+    // 3. Draw Thicker Text (Increased thickness multiplier)
+    let thickness = (0.1 * scale_factor as f32) as i32;
+    for dx in 0..=thickness {
+        for dy in 0..=thickness {
+            draw_text_mut(
+                &mut canvas,
+                text_color,
+                text_x + dx,
+                text_y + dy,
+                render_scale,
+                &font,
+                text,
+            );
+        }
+    }
+
+    // 4. Downscale & Corners
+    let target_width = render_width / scale_factor;
+    let target_height = render_height / scale_factor;
+    let mut small = resize(&canvas, target_width, target_height, FilterType::Lanczos3);
+
+    #[cfg(target_os = "windows")]
+    apply_rounded_corners(&mut small, 8.0);
+
+    #[cfg(not(target_os = "windows"))]
+    apply_rounded_corners(&mut small, 4.0);
+
+    let raw_pixels = small.into_raw();
+    Some(Image::new_owned(raw_pixels, target_width, target_height))
+}
+
+/// Paints a ring segment centered in the logo square, clockwise from 12 o'clock, covering
+/// `fraction` of the full circle.
+fn draw_arc(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    center: f32,
+    outer_radius: f32,
+    thickness: f32,
+    fraction: f32,
+    color: Rgba<u8>,
+) {
+    if fraction <= 0.0 {
+        return;
+    }
+    let inner_radius = outer_radius - thickness;
+    let sweep = fraction * 2.0 * PI;
+    let extent = (center + outer_radius).ceil() as u32;
+
+    for y in 0..extent.min(img.height()) {
+        for x in 0..extent.min(img.width()) {
+            let dx = x as f32 + 0.5 - center;
+            let dy = y as f32 + 0.5 - center;
+            let dist = (dx * dx + dy * dy).sqrt();
+            if dist < inner_radius || dist > outer_radius {
+                continue;
+            }
+            // Angle measured clockwise from straight up, in 0..2π
+            let angle = dx.atan2(-dy).rem_euclid(2.0 * PI);
+            if angle <= sweep {
+                img.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+fn apply_rounded_corners(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, radius: f32) {
+    let (w, h) = img.dimensions();
+    let width = w as f32;
+    let height = h as f32;
+
+    // We iterate over all pixels, but logic only applies to corners
+    for y in 0..h {
+        for x in 0..w {
+            let px = x as f32 + 0.5; // pixel center x
+            let py = y as f32 + 0.5; // pixel center y
+
+            // Vector from the nearest corner center
+            let dx;
+            let dy;
+
+            if x < radius as u32 && y < radius as u32 {
+                // Top-Left
+                dx = px - radius;
+                dy = py - radius;
+            } else if x >= (w - radius as u32) && y < radius as u32 {
+                // Top-Right
+                dx = px - (width - radius);
+                dy = py - radius;
+            } else if x < radius as u32 && y >= (h - radius as u32) {
+                // Bottom-Left
+                dx = px - radius;
+                dy = py - (height - radius);
+            } else if x >= (w - radius as u32) && y >= (h - radius as u32) {
+                // Bottom-Right
+                dx = px - (width - radius);
+                dy = py - (height - radius);
+            } else {
+                continue; // Not in a corner region
+            }
+
+            // Distance from corner center
+            let dist_sq = dx * dx + dy * dy;
+
+            // Optimization: if fully inside circle (dist < radius-1), do nothing
+            // if fully outside (dist > radius), make transparent
+            // if on edge, blend
+
+            // We are solving for opacity.
+            // The corner is formed by the circle. Pixels outside the circle (dist > radius) should be transparent.
+            // Pixels inside (dist < radius) should be consistent with image.
+
+            // Let's optimize calculation: avoid sqrt if obvious
+            if dist_sq > (radius + 1.0) * (radius + 1.0) {
+                img.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+                continue;
+            }
+
+            let dist = dist_sq.sqrt();
+
+            // Alpha factor: 0.0 (fully outside) to 1.0 (fully inside)
+            // Smooth transition around `radius`
+            // dist = radius + 0.5 -> alpha = 0
+            // dist = radius - 0.5 -> alpha = 1
+
+            let alpha_factor = (radius + 0.5 - dist).clamp(0.0, 1.0);
+
+            if alpha_factor < 1.0 {
+                let p = img.get_pixel(x, y);
+                let new_alpha = (p[3] as f32 * alpha_factor) as u8;
+                img.put_pixel(x, y, Rgba([p[0], p[1], p[2], new_alpha]));
+            }
+        }
+    }
+}
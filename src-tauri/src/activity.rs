@@ -1,5 +1,7 @@
 use crate::db;
 use crate::idle::IdleState;
+use crate::models::{CaptureContext, PrivacySettings};
+use crate::redaction;
 use crate::AppState;
 use active_win_pos_rs::get_active_window;
 use rusqlite::Connection;
@@ -67,6 +69,27 @@ pub fn get_browser_url(app_name: &str) -> Option<String> {
     None
 }
 
+/// The foreground window and input counts to store with a screenshot. The counts are
+/// only read; commit them with `IdleState::commit_capture_counts` once the screenshot is
+/// stored. Denylisted windows are left out, since the screenshot itself blurs them.
+pub fn capture_context(state: &IdleState, privacy: &PrivacySettings) -> CaptureContext {
+    let (keyboard_events, mouse_events) = state.capture_counts();
+    let mut context = CaptureContext {
+        keyboard_events,
+        mouse_events,
+        ..Default::default()
+    };
+
+    if let Ok(window) = get_active_window() {
+        if !redaction::is_denied(privacy, &window.app_name, &window.title) {
+            context.url = get_browser_url(&window.app_name);
+            context.app_name = Some(window.app_name);
+            context.window_title = Some(window.title);
+        }
+    }
+    context
+}
+
 pub fn start_activity_loop<R: Runtime>(app: AppHandle<R>, state: Arc<IdleState>) {
     // Ensure only one loop runs
     if state
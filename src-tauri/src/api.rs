@@ -1,156 +1,283 @@
 use crate::db;
+use crate::environment::ApiEnvironment;
+use crate::error::WatchtowerError;
+use crate::models::User;
 use crate::AppState;
 use rusqlite::Connection;
 use serde_json::json;
+use std::path::{Path, PathBuf};
 use tauri::{async_runtime, AppHandle, Emitter, Manager, Runtime};
 
-pub const BASE_URL: &str = "https://watchtower.staging-api.nykon.cloud/v1";
+/// Refresh this long before the access token expires.
+const REFRESH_MARGIN_MS: i64 = 2 * 60 * 1000;
+
+pub fn environment<R: Runtime>(app: &AppHandle<R>) -> ApiEnvironment {
+    app.state::<AppState>().environment.lock().unwrap().clone()
+}
+
+/// The environment together with its database. The environment lock is held while
+/// reading the path, matching the order `set_api_environment` swaps them in.
+pub fn environment_and_db_path<R: Runtime>(app: &AppHandle<R>) -> (ApiEnvironment, PathBuf) {
+    let state = app.state::<AppState>();
+    let env = state.environment.lock().unwrap();
+    let db_path = state.db_path.lock().unwrap().clone();
+    (env.clone(), db_path)
+}
+
+/// Fails if the app has switched environments since `expected` was read. Callers that
+/// upload rows from the local database check this first, so data recorded against one
+/// backend is never sent to another.
+pub fn ensure_environment<R: Runtime>(
+    app: &AppHandle<R>,
+    expected: &ApiEnvironment,
+) -> Result<(), WatchtowerError> {
+    if environment(app) == *expected {
+        Ok(())
+    } else {
+        Err(WatchtowerError::EnvironmentChanged)
+    }
+}
 
 pub async fn request<R: Runtime, T: serde::Serialize>(
     app: &AppHandle<R>,
     method: reqwest::Method,
     endpoint: &str,
     payload: Option<&T>,
-) -> Result<reqwest::Response, String> {
-    let app_handle = app.clone();
+) -> Result<reqwest::Response, WatchtowerError> {
+    send(app, method, endpoint, |req| match payload {
+        Some(p) => Ok(req.json(p)),
+        None => Ok(req),
+    })
+    .await
+}
+
+/// POSTs a multipart form. `build_form` is called again if the request has to be retried
+/// after a token refresh, since a form (and any file stream in it) can only be sent once.
+pub async fn request_multipart<R: Runtime, F>(
+    app: &AppHandle<R>,
+    endpoint: &str,
+    build_form: F,
+) -> Result<reqwest::Response, WatchtowerError>
+where
+    F: Fn() -> Result<reqwest::multipart::Form, WatchtowerError>,
+{
+    send(app, reqwest::Method::POST, endpoint, |req| {
+        Ok(req.multipart(build_form()?))
+    })
+    .await
+}
+
+async fn send<R: Runtime, F>(
+    app: &AppHandle<R>,
+    method: reqwest::Method,
+    endpoint: &str,
+    attach_body: F,
+) -> Result<reqwest::Response, WatchtowerError>
+where
+    F: Fn(reqwest::RequestBuilder) -> Result<reqwest::RequestBuilder, WatchtowerError>,
+{
     let state = app.state::<AppState>();
     let client = &state.client;
-    let url = format!("{}{}", BASE_URL, endpoint);
+    let (environment, db_path) = environment_and_db_path(app);
+    let url = format!("{}{}", environment.base_url, endpoint);
 
     // 1. Get current user for token
-    let db_path = state.db_path.lock().unwrap().clone();
-    let user = async_runtime::spawn_blocking(move || {
-        if let Ok(conn) = Connection::open(&db_path) {
-            db::get_user(&conn).ok().flatten()
-        } else {
-            None
-        }
-    })
-    .await
-    .map_err(|e| e.to_string())?
-    .ok_or("User not found")?;
+    let user = load_user(&db_path)
+        .await?
+        .ok_or(WatchtowerError::NotAuthenticated)?;
 
+    // 2. Refresh ahead of expiry so parallel requests don't all hit a 401 at once
     let mut token = user.token.clone();
+    let now = chrono::Utc::now().timestamp_millis();
+    if user
+        .token_expires
+        .is_some_and(|exp| exp - REFRESH_MARGIN_MS <= now)
+    {
+        println!(
+            "API: Access token about to expire, refreshing before {}",
+            endpoint
+        );
+        token = refresh_token(app, &environment, &db_path, &token).await?;
+    }
 
-    // 2. Initial Attempt
     let build_request = |t: &str| {
-        let mut req = client
+        let req = client
             .request(method.clone(), &url)
             .header("Authorization", format!("Bearer {}", t))
             .header("x-app-source", "desktop");
 
-        if let Some(p) = payload {
-            req = req.json(p);
-        }
-        req
+        attach_body(req)
     };
 
-    let mut response = build_request(&token)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+    let mut response = build_request(&token)?.send().await?;
 
-    // 3. Handle 401 Unauthorized
+    // 3. Handle 401 Unauthorized (token revoked, or expiry unknown)
     if response.status() == reqwest::StatusCode::UNAUTHORIZED {
         println!(
             "API: 401 Unauthorized for {}. Attempting token refresh...",
             endpoint
         );
-
-        if let Some(rt) = user.refresh_token.as_ref() {
-            let refresh_url = format!("{}/auth/refresh-tokens", BASE_URL);
-            let refresh_res = client
-                .post(&refresh_url)
-                .json(&json!({ "refreshToken": rt }))
-                .send()
-                .await;
-
-            match refresh_res {
-                Ok(u_res) if u_res.status().is_success() => {
-                    if let Ok(json_body) = u_res.json::<serde_json::Value>().await {
-                        // Correct structure is json_body.credentials.access.token
-                        let creds = json_body
-                            .get("credentials")
-                            .or_else(|| json_body.get("tokens"))
-                            .unwrap_or(&json_body);
-
-                        let new_access = creds
-                            .get("access")
-                            .and_then(|a| a.get("token"))
-                            .and_then(|t| t.as_str())
-                            .or_else(|| creds.get("token").and_then(|t| t.as_str()));
-
-                        let new_refresh = creds
-                            .get("refresh")
-                            .and_then(|r| r.get("token"))
-                            .and_then(|t| t.as_str());
-
-                        if let Some(new_token) = new_access {
-                            println!("API: Token refreshed successfully.");
-                            token = new_token.to_string();
-
-                            // Update DB with new token
-                            let new_token_db = token.clone();
-                            let new_refresh_db = new_refresh.map(|s| s.to_string());
-                            let db_path_update = state.db_path.lock().unwrap().clone();
-                            let uuid = user.uuid.clone();
-
-                            let _ = async_runtime::spawn_blocking(move || {
-                                if let Ok(conn) = Connection::open(&db_path_update) {
-                                    if let Some(rt) = new_refresh_db {
-                                        let _ = conn.execute(
-                                            "UPDATE users SET token = ?1, refresh_token = ?2 WHERE uuid = ?3",
-                                            [new_token_db, rt, uuid],
-                                        );
-                                    } else {
-                                        let _ = conn.execute(
-                                            "UPDATE users SET token = ?1 WHERE uuid = ?2",
-                                            [new_token_db, uuid],
-                                        );
-                                    }
-                                }
-                            }).await;
-
-                            // Retry original request
-                            response = build_request(&token)
-                                .send()
-                                .await
-                                .map_err(|e| e.to_string())?;
-                        } else {
-                            println!("API: Token refresh response body: {:?}", json_body);
-                            return logout_and_fail(
-                                &app_handle,
-                                "Token refresh response missing token".to_string(),
-                            )
-                            .await;
-                        }
-                    } else {
-                        return logout_and_fail(
-                            &app_handle,
-                            "Failed to parse token refresh response".to_string(),
-                        )
-                        .await;
-                    }
-                }
-                _ => {
-                    return logout_and_fail(&app_handle, "Token refresh failed".to_string()).await;
-                }
-            }
-        } else {
-            return logout_and_fail(&app_handle, "No refresh token available".to_string()).await;
-        }
+        token = refresh_token(app, &environment, &db_path, &token).await?;
+        response = build_request(&token)?.send().await?;
     }
 
     Ok(response)
 }
 
-async fn logout_and_fail<R: Runtime>(
+async fn load_user(db_path: &Path) -> Result<Option<User>, WatchtowerError> {
+    let db_path = db_path.to_path_buf();
+    let user = async_runtime::spawn_blocking(move || {
+        let conn = Connection::open(&db_path)?;
+        db::get_user(&conn)
+    })
+    .await??;
+    Ok(user)
+}
+
+/// Replaces `stale_token` with a fresh access token. Refreshes are single-flight: callers
+/// queue on `AppState::token_refresh`, and whoever gets in after a refresh has finished
+/// just picks up the token it stored instead of rotating the refresh token again.
+async fn refresh_token<R: Runtime>(
     app: &AppHandle<R>,
-    error: String,
-) -> Result<reqwest::Response, String> {
-    println!("API Logout: {}", error);
+    environment: &ApiEnvironment,
+    db_path: &Path,
+    stale_token: &str,
+) -> Result<String, WatchtowerError> {
     let state = app.state::<AppState>();
-    let db_path = state.db_path.lock().unwrap().clone();
+    let _guard = state.token_refresh.lock().await;
+
+    let user = load_user(db_path)
+        .await?
+        .ok_or(WatchtowerError::NotAuthenticated)?;
+    if user.token != stale_token {
+        println!("API: Token already refreshed by another request.");
+        return Ok(user.token);
+    }
+
+    let rt = match user.refresh_token.as_ref() {
+        Some(rt) => rt,
+        None => {
+            return logout_and_fail(
+                app,
+                environment,
+                db_path,
+                "No refresh token available".to_string(),
+            )
+            .await
+        }
+    };
+
+    let refresh_url = format!("{}/auth/refresh-tokens", environment.base_url);
+    let u_res = state
+        .client
+        .post(&refresh_url)
+        .json(&json!({ "refreshToken": rt }))
+        .send()
+        .await?; // Offline is not a reason to log out; keep the session for the next try
+
+    if !u_res.status().is_success() {
+        return logout_and_fail(
+            app,
+            environment,
+            db_path,
+            format!("Token refresh failed with status {}", u_res.status()),
+        )
+        .await;
+    }
+
+    let json_body = match u_res.json::<serde_json::Value>().await {
+        Ok(body) => body,
+        Err(_) => {
+            return logout_and_fail(
+                app,
+                environment,
+                db_path,
+                "Failed to parse token refresh response".to_string(),
+            )
+            .await
+        }
+    };
+
+    // Correct structure is json_body.credentials.access.token
+    let creds = json_body
+        .get("credentials")
+        .or_else(|| json_body.get("tokens"))
+        .unwrap_or(&json_body);
+
+    let access = creds.get("access");
+    let new_access = access
+        .and_then(|a| a.get("token"))
+        .and_then(|t| t.as_str())
+        .or_else(|| creds.get("token").and_then(|t| t.as_str()));
+    let new_expires = access.and_then(|a| a.get("expires")).and_then(parse_expiry);
+
+    let new_refresh = creds
+        .get("refresh")
+        .and_then(|r| r.get("token"))
+        .and_then(|t| t.as_str())
+        .map(|s| s.to_string());
+
+    let new_token = match new_access {
+        Some(t) => t.to_string(),
+        None => {
+            println!("API: Token refresh response body: {:?}", json_body);
+            return logout_and_fail(
+                app,
+                environment,
+                db_path,
+                "Token refresh response missing token".to_string(),
+            )
+            .await;
+        }
+    };
+    println!("API: Token refreshed successfully.");
+
+    // Update DB with new token (still under the refresh lock)
+    let db_path_update = db_path.to_path_buf();
+    let new_token_db = new_token.clone();
+    async_runtime::spawn_blocking(move || {
+        let conn = Connection::open(&db_path_update)?;
+        db::update_user_tokens(
+            &conn,
+            &user.uuid,
+            &new_token_db,
+            new_refresh.as_deref(),
+            new_expires,
+        )
+    })
+    .await??;
+
+    Ok(new_token)
+}
+
+/// Reads an `expires` field, sent as an ISO 8601 string or epoch ms.
+pub fn parse_expiry(value: &serde_json::Value) -> Option<i64> {
+    match value {
+        serde_json::Value::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .map(|dt| dt.timestamp_millis()),
+        serde_json::Value::Number(n) => n.as_i64(),
+        _ => None,
+    }
+}
+
+/// Signs the user out of the database the failed request was made against. If the app
+/// has switched environments since, the current one is left signed in.
+async fn logout_and_fail<R: Runtime, T>(
+    app: &AppHandle<R>,
+    environment: &ApiEnvironment,
+    db_path: &Path,
+    reason: String,
+) -> Result<T, WatchtowerError> {
+    if ensure_environment(app, environment).is_err() {
+        println!(
+            "API: {} for {}, which is no longer in use",
+            reason, environment.name
+        );
+        return Err(WatchtowerError::AuthExpired { reason });
+    }
+    println!("API Logout: {}", reason);
+    let db_path = db_path.to_path_buf();
 
     let _ = async_runtime::spawn_blocking(move || {
         if let Ok(conn) = Connection::open(&db_path) {
@@ -162,5 +289,5 @@ async fn logout_and_fail<R: Runtime>(
     crate::update_tray(app, false, "");
     let _ = app.emit("logout-user", ());
 
-    Err(error)
+    Err(WatchtowerError::AuthExpired { reason })
 }
@@ -0,0 +1,172 @@
+use crate::api;
+use crate::db;
+use crate::error::WatchtowerError;
+use crate::models::{Project, User};
+use crate::AppState;
+use rusqlite::Connection;
+use serde::Deserialize;
+use serde_json::json;
+use std::path::PathBuf;
+use tauri::{async_runtime, AppHandle, Emitter, Manager, Runtime};
+
+// Account Flow
+//
+// Login, project refresh and logout against the API. Tokens are only ever held here and
+// in the credential store (the database keeps a reference to them, see `credentials`);
+// the webview gets the user back without them (see `models::User`).
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    account: Account,
+    credentials: Credentials,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    id: String,
+    name: String,
+    email: String,
+}
+
+#[derive(Deserialize)]
+struct Credentials {
+    access: TokenInfo,
+    refresh: TokenInfo,
+}
+
+#[derive(Deserialize)]
+struct TokenInfo {
+    token: String,
+    expires: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ProjectsResponse {
+    results: Vec<Project>,
+}
+
+fn db_path<R: Runtime>(app: &AppHandle<R>) -> PathBuf {
+    api::environment_and_db_path(app).1
+}
+
+async fn with_db<R: Runtime, T, F>(app: &AppHandle<R>, op: F) -> Result<T, WatchtowerError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+{
+    let db_path = db_path(app);
+    let result = async_runtime::spawn_blocking(move || {
+        let mut conn = Connection::open(&db_path)?;
+        op(&mut conn)
+    })
+    .await??;
+    Ok(result)
+}
+
+/// Signs in, stores the user and fetches their projects. Any previous user is replaced.
+pub async fn login<R: Runtime>(
+    app: &AppHandle<R>,
+    email: &str,
+    password: &str,
+) -> Result<User, WatchtowerError> {
+    let state = app.state::<AppState>();
+    let url = format!("{}/auth/login", api::environment(app).base_url);
+
+    let response = state
+        .client
+        .post(&url)
+        .header("x-app-source", "desktop")
+        .json(&json!({ "email": email, "password": password }))
+        .send()
+        .await?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::BAD_REQUEST {
+        return Err(WatchtowerError::invalid_input("Invalid email or password"));
+    }
+    let body: LoginResponse = response.error_for_status()?.json().await?;
+
+    let user = User {
+        uuid: body.account.id,
+        name: body.account.name,
+        email: body.account.email,
+        token: body.credentials.access.token,
+        refresh_token: Some(body.credentials.refresh.token),
+        token_expires: body
+            .credentials
+            .access
+            .expires
+            .as_ref()
+            .and_then(api::parse_expiry),
+        projects: Vec::new(),
+        current_project_id: None,
+    };
+
+    // Saved before fetching projects, which goes through `api::request` and its token
+    let stored = user.clone();
+    with_db(app, move |conn| db::save_user(conn, &stored)).await?;
+    println!("Auth: Logged in as {}", user.email);
+
+    if let Err(e) = refresh_projects(app).await {
+        // A login that can't see its projects is useless; don't leave it half done
+        let _ = with_db(app, |conn| db::clear_user(conn)).await;
+        return Err(e);
+    }
+
+    with_db(app, |conn| db::get_user(conn))
+        .await?
+        .ok_or(WatchtowerError::NotAuthenticated)
+}
+
+/// Fetches the user's projects (limits and weekly totals included), stores them and
+/// emits `projects-updated`.
+pub async fn refresh_projects<R: Runtime>(
+    app: &AppHandle<R>,
+) -> Result<Vec<Project>, WatchtowerError> {
+    let environment = api::environment(app);
+    // Everything synced before the request is in the weekly totals it returns. Syncs that
+    // land while it is in flight may or may not be; counting them twice is the safe side.
+    let synced = with_db(app, |conn| db::get_synced_times(conn)).await?;
+
+    let endpoint = "/desktop/projects?projectType=watchtower";
+    let response = api::request::<R, ()>(app, reqwest::Method::GET, endpoint, None)
+        .await?
+        .error_for_status()?;
+    let projects = response.json::<ProjectsResponse>().await?.results;
+
+    // Another backend's projects don't belong in this database
+    api::ensure_environment(app, &environment)?;
+    let fetched = projects.clone();
+    with_db(app, move |conn| {
+        if db::get_user(conn)?.is_some() {
+            db::replace_projects(conn, &fetched)?;
+            db::set_baseline_times(conn, &synced)?;
+        }
+        Ok(())
+    })
+    .await?;
+
+    println!("Auth: Refreshed {} projects", projects.len());
+    let _ = app.emit("projects-updated", &projects);
+    Ok(projects)
+}
+
+/// Revokes the refresh token on the server, then forgets the user locally. Revocation is
+/// best effort: being offline must not keep someone logged in.
+pub async fn logout<R: Runtime>(app: &AppHandle<R>) -> Result<(), WatchtowerError> {
+    let user = with_db(app, |conn| db::get_user(conn)).await?;
+
+    if let Some(refresh_token) = user.and_then(|u| u.refresh_token) {
+        let payload = json!({ "refreshToken": refresh_token });
+        match api::request(app, reqwest::Method::POST, "/auth/logout", Some(&payload)).await {
+            Ok(response) if response.status().is_success() => {
+                println!("Auth: Refresh token revoked")
+            }
+            Ok(response) => eprintln!("Auth: Revocation failed with status {}", response.status()),
+            Err(e) => eprintln!("Auth: Revocation failed: {}", e),
+        }
+    }
+
+    with_db(app, |conn| db::clear_user(conn)).await?;
+    Ok(())
+}
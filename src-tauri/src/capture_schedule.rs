@@ -0,0 +1,331 @@
+use crate::db;
+use crate::error::WatchtowerError;
+use crate::models::{CaptureSchedule, Project};
+use rand::Rng;
+use rusqlite::Connection;
+use std::collections::VecDeque;
+
+// Capture Schedule
+//
+// When the capture loop takes its next screenshot. A fixed schedule is a plain interval;
+// a random one splits each window into `per_window` equal slots and picks one moment in
+// every slot, so the count per window is exact but the moments can't be predicted. The
+// RNG is a type parameter so a seeded one reproduces a schedule.
+
+/// Local capture schedule (JSON), used when the project doesn't set one.
+pub const CAPTURE_SCHEDULE_SETTING: &str = "capture_schedule";
+
+pub struct Scheduler<R: Rng> {
+    rng: R,
+    /// Planned capture times (epoch seconds) left in the current window, earliest first.
+    planned: VecDeque<u64>,
+    window_end: u64,
+    /// The schedule `planned` was drawn from; a different one discards the plan.
+    planned_for: Option<CaptureSchedule>,
+}
+
+impl<R: Rng> Scheduler<R> {
+    pub fn new(rng: R) -> Self {
+        Scheduler {
+            rng,
+            planned: VecDeque::new(),
+            window_end: 0,
+            planned_for: None,
+        }
+    }
+
+    /// The first capture time from `now` on, in epoch seconds. A moment drawn at the very
+    /// start of a window is `now` itself and is kept, so every window gets its full count.
+    pub fn next_capture(&mut self, schedule: &CaptureSchedule, now: u64) -> u64 {
+        if self.planned_for.as_ref() != Some(schedule) {
+            self.planned.clear();
+            self.window_end = now;
+            self.planned_for = Some(*schedule);
+        }
+
+        match *schedule {
+            CaptureSchedule::Fixed { interval_seconds } => now + interval_seconds as u64,
+            CaptureSchedule::Random {
+                per_window,
+                window_minutes,
+            } => loop {
+                // Moments that passed while the machine slept or capture was off are dropped
+                match self.planned.pop_front() {
+                    Some(at) if at >= now => return at,
+                    Some(_) => continue,
+                    None => {
+                        // Windows follow on from each other; after a long gap start afresh
+                        let start = self.window_end.max(now);
+                        self.plan_window(start, per_window, window_minutes);
+                    }
+                }
+            },
+        }
+    }
+
+    fn plan_window(&mut self, start: u64, per_window: u32, window_minutes: u32) {
+        let window = window_minutes as u64 * 60;
+        let slot = window / per_window.max(1) as u64;
+        for i in 0..per_window as u64 {
+            let slot_start = start + i * slot;
+            self.planned
+                .push_back(slot_start + self.rng.gen_range(0..slot.max(1)));
+        }
+        self.window_end = start + window;
+    }
+}
+
+/// How much tracked time one screenshot stands for under `schedule`.
+pub fn seconds_per_capture(schedule: &CaptureSchedule) -> u32 {
+    match *schedule {
+        CaptureSchedule::Fixed { interval_seconds } => interval_seconds,
+        CaptureSchedule::Random {
+            per_window,
+            window_minutes,
+        } => window_minutes * 60 / per_window.max(1),
+    }
+}
+
+pub fn capture_schedule(conn: &Connection, project: Option<&Project>) -> CaptureSchedule {
+    project
+        .and_then(|p| p.capture_schedule)
+        .or_else(|| {
+            db::get_setting(conn, CAPTURE_SCHEDULE_SETTING)
+                .ok()
+                .flatten()
+                .and_then(|json| serde_json::from_str(&json).ok())
+        })
+        .filter(|schedule| validate_capture_schedule(schedule).is_ok())
+        .unwrap_or_default()
+}
+
+/// The loop wakes every 10 seconds, so nothing denser than one capture per 30 seconds
+/// is accepted, and nothing sparser than one an hour.
+pub fn validate_capture_schedule(schedule: &CaptureSchedule) -> Result<(), WatchtowerError> {
+    match *schedule {
+        CaptureSchedule::Fixed { interval_seconds } if !(30..=3600).contains(&interval_seconds) => {
+            Err(WatchtowerError::invalid_input(
+                "Screenshot interval must be between 30 seconds and 1 hour",
+            ))
+        }
+        CaptureSchedule::Random {
+            per_window,
+            window_minutes,
+        } => {
+            if !(1..=60).contains(&window_minutes) {
+                Err(WatchtowerError::invalid_input(
+                    "Screenshot window must be between 1 and 60 minutes",
+                ))
+            } else if per_window == 0 || window_minutes * 60 / per_window < 30 {
+                Err(WatchtowerError::invalid_input(
+                    "Screenshots per window must leave at least 30 seconds between them",
+                ))
+            } else {
+                Ok(())
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const NOW: u64 = 1_760_000_000;
+    const RANDOM: CaptureSchedule = CaptureSchedule::Random {
+        per_window: 4,
+        window_minutes: 10,
+    };
+
+    /// The next `count` captures, each asked for at the moment the previous one was taken.
+    fn captures(
+        scheduler: &mut Scheduler<StdRng>,
+        schedule: &CaptureSchedule,
+        count: usize,
+    ) -> Vec<u64> {
+        let mut now = NOW;
+        (0..count)
+            .map(|_| {
+                now = scheduler.next_capture(schedule, now);
+                now
+            })
+            .collect()
+    }
+
+    fn settings_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run(&mut conn).unwrap();
+        conn
+    }
+
+    fn server_project(capture_schedule: serde_json::Value) -> Project {
+        serde_json::from_value(serde_json::json!({
+            "id": "project-1",
+            "name": "Project",
+            "weeklyLimitHours": null,
+            "dailyLimitHours": null,
+            "screenshotsEnabled": true,
+            "totalHoursThisWeek": null,
+            "captureSchedule": capture_schedule,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn random_schedule_is_reproducible_from_a_seed() {
+        let mut scheduler = Scheduler::new(StdRng::seed_from_u64(42));
+        let offsets: Vec<u64> = captures(&mut scheduler, &RANDOM, 8)
+            .iter()
+            .map(|at| at - NOW)
+            .collect();
+        assert_eq!(offsets, vec![81, 245, 305, 512, 727, 750, 921, 1128]);
+    }
+
+    #[test]
+    fn every_capture_lands_in_its_own_slot() {
+        let window = 10 * 60;
+        let slot = window / 4;
+        for seed in 0..200 {
+            let mut scheduler = Scheduler::new(StdRng::seed_from_u64(seed));
+            for (k, at) in captures(&mut scheduler, &RANDOM, 12)
+                .into_iter()
+                .enumerate()
+            {
+                let slot_start = NOW + (k as u64 / 4) * window + (k as u64 % 4) * slot;
+                assert!(
+                    (slot_start..slot_start + slot).contains(&at),
+                    "seed {}: capture {} at +{} is outside +{}..+{}",
+                    seed,
+                    k,
+                    at - NOW,
+                    slot_start - NOW,
+                    slot_start + slot - NOW
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn moments_missed_while_asleep_are_dropped() {
+        let mut scheduler = Scheduler::new(StdRng::seed_from_u64(7));
+        scheduler.next_capture(&RANDOM, NOW);
+        // Wake up an hour later: the rest of the old window is gone, a new one starts now
+        let woke = NOW + 3600;
+        let at = scheduler.next_capture(&RANDOM, woke);
+        assert!((woke..woke + 150).contains(&at), "+{}", at - woke);
+    }
+
+    #[test]
+    fn fixed_schedule_is_a_plain_interval() {
+        let fixed = CaptureSchedule::Fixed {
+            interval_seconds: 300,
+        };
+        let mut scheduler = Scheduler::new(StdRng::seed_from_u64(1));
+        assert_eq!(
+            captures(&mut scheduler, &fixed, 3),
+            vec![NOW + 300, NOW + 600, NOW + 900]
+        );
+    }
+
+    #[test]
+    fn changing_the_schedule_discards_the_plan() {
+        let mut scheduler = Scheduler::new(StdRng::seed_from_u64(3));
+        scheduler.next_capture(&RANDOM, NOW);
+        let fixed = CaptureSchedule::Fixed {
+            interval_seconds: 60,
+        };
+        assert_eq!(scheduler.next_capture(&fixed, NOW + 10), NOW + 70);
+        // Back to random: planned afresh from the moment it was switched back
+        let at = scheduler.next_capture(&RANDOM, NOW + 20);
+        assert!((NOW + 20..NOW + 20 + 150).contains(&at), "+{}", at - NOW);
+    }
+
+    #[test]
+    fn server_schedule_overrides_the_local_one() {
+        let conn = settings_db();
+        db::set_setting(
+            &conn,
+            CAPTURE_SCHEDULE_SETTING,
+            r#"{"mode":"fixed","intervalSeconds":600}"#,
+        )
+        .unwrap();
+
+        let project = server_project(serde_json::json!({"mode": "fixed", "intervalSeconds": 90}));
+        let schedule = capture_schedule(&conn, Some(&project));
+        assert_eq!(
+            schedule,
+            CaptureSchedule::Fixed {
+                interval_seconds: 90
+            }
+        );
+        assert_eq!(
+            Scheduler::new(StdRng::seed_from_u64(0)).next_capture(&schedule, NOW),
+            NOW + 90
+        );
+
+        let project = server_project(serde_json::json!({
+            "mode": "random", "perWindow": 2, "windowMinutes": 5
+        }));
+        assert_eq!(
+            capture_schedule(&conn, Some(&project)),
+            CaptureSchedule::Random {
+                per_window: 2,
+                window_minutes: 5
+            }
+        );
+    }
+
+    #[test]
+    fn local_schedule_applies_when_the_server_sets_none() {
+        let conn = settings_db();
+        let project = server_project(serde_json::Value::Null);
+        assert_eq!(
+            capture_schedule(&conn, Some(&project)),
+            CaptureSchedule::default()
+        );
+
+        db::set_setting(
+            &conn,
+            CAPTURE_SCHEDULE_SETTING,
+            r#"{"mode":"fixed","intervalSeconds":600}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            capture_schedule(&conn, Some(&project)),
+            CaptureSchedule::Fixed {
+                interval_seconds: 600
+            }
+        );
+        assert_eq!(
+            capture_schedule(&conn, None),
+            CaptureSchedule::Fixed {
+                interval_seconds: 600
+            }
+        );
+    }
+
+    #[test]
+    fn out_of_range_schedule_falls_back_to_the_default() {
+        let conn = settings_db();
+        let project = server_project(serde_json::json!({"mode": "fixed", "intervalSeconds": 5}));
+        assert_eq!(
+            capture_schedule(&conn, Some(&project)),
+            CaptureSchedule::default()
+        );
+    }
+
+    #[test]
+    fn each_capture_stands_for_its_share_of_the_window() {
+        assert_eq!(seconds_per_capture(&RANDOM), 150);
+        assert_eq!(
+            seconds_per_capture(&CaptureSchedule::Fixed {
+                interval_seconds: 90
+            }),
+            90
+        );
+    }
+}
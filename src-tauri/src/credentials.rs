@@ -0,0 +1,641 @@
+use crate::error::WatchtowerError;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+// Credential Store
+//
+// Access and refresh tokens are kept out of the database. `users.credential_ref` only
+// holds a reference such as `secret-service:<id>`; the tokens live in the OS secret
+// service (Secret Service over D-Bus on Linux, Keychain on macOS, Credential Manager on
+// Windows). Where none is reachable they go to a file encrypted with a key kept outside
+// the app data dir (the config dir on Linux, the local app data dir on Windows), so a copy
+// of the data dir alone is not enough to take over the account. macOS has no separate
+// per-user location, so there the key sits beside the file and the encryption only
+// obfuscates the tokens; the Keychain is always reachable on macOS, so the file is only
+// used there when forced.
+//
+// `WATCHTOWER_CREDENTIAL_STORE=secret-service|file` forces a backend, e.g. to run against
+// a mock secret-service daemon on a private session bus.
+
+const SERVICE: &str = "cloud.nykon.watchtower";
+pub const BACKEND_VAR: &str = "WATCHTOWER_CREDENTIAL_STORE";
+
+const SECRET_SERVICE: &str = "secret-service";
+const FILE: &str = "file";
+const NONCE_LEN: usize = 12;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Credentials {
+    pub token: String,
+    pub refresh_token: Option<String>,
+}
+
+pub trait CredentialStore: Send + Sync {
+    /// Prefix used in references, e.g. `secret-service`.
+    fn kind(&self) -> &'static str;
+    /// Whether the store answers at all; new credentials go elsewhere if not.
+    fn is_available(&self) -> bool {
+        true
+    }
+    fn load(&self, id: &str) -> Result<Option<Credentials>, WatchtowerError>;
+    fn save(&self, id: &str, credentials: &Credentials) -> Result<(), WatchtowerError>;
+    fn delete(&self, id: &str) -> Result<(), WatchtowerError>;
+}
+
+fn encode(credentials: &Credentials) -> Result<String, WatchtowerError> {
+    serde_json::to_string(credentials).map_err(|e| WatchtowerError::storage(e.to_string()))
+}
+
+fn decode(json: &str) -> Result<Credentials, WatchtowerError> {
+    serde_json::from_str(json).map_err(|e| WatchtowerError::storage(e.to_string()))
+}
+
+/// The platform secret service, through the `keyring` crate.
+pub struct SecretServiceStore;
+
+impl SecretServiceStore {
+    fn entry(id: &str) -> Result<keyring::Entry, WatchtowerError> {
+        keyring::Entry::new(SERVICE, id).map_err(|e| WatchtowerError::storage(e.to_string()))
+    }
+}
+
+impl CredentialStore for SecretServiceStore {
+    fn kind(&self) -> &'static str {
+        SECRET_SERVICE
+    }
+
+    /// No D-Bus session, no daemon, a locked keychain, ...
+    fn is_available(&self) -> bool {
+        let entry = match Self::entry("availability-probe") {
+            Ok(entry) => entry,
+            Err(_) => return false,
+        };
+        match entry.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => true,
+            Err(e) => {
+                println!("Credentials: Secret service unavailable: {}", e);
+                false
+            }
+        }
+    }
+
+    fn load(&self, id: &str) -> Result<Option<Credentials>, WatchtowerError> {
+        match Self::entry(id)?.get_password() {
+            Ok(json) => decode(&json).map(Some),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(WatchtowerError::storage(e.to_string())),
+        }
+    }
+
+    fn save(&self, id: &str, credentials: &Credentials) -> Result<(), WatchtowerError> {
+        Self::entry(id)?
+            .set_password(&encode(credentials)?)
+            .map_err(|e| WatchtowerError::storage(e.to_string()))
+    }
+
+    fn delete(&self, id: &str) -> Result<(), WatchtowerError> {
+        match Self::entry(id)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(WatchtowerError::storage(e.to_string())),
+        }
+    }
+}
+
+/// All entries in one ChaCha20-Poly1305 encrypted file: a random nonce followed by the
+/// ciphertext of a JSON map from id to credentials.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    key_path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl EncryptedFileStore {
+    pub fn new(path: PathBuf, key_path: PathBuf) -> Self {
+        Self {
+            path,
+            key_path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn cipher(&self) -> Result<ChaCha20Poly1305, WatchtowerError> {
+        let key = match fs::read(&self.key_path) {
+            Ok(key) if key.len() == 32 => key,
+            Ok(_) => return Err(WatchtowerError::storage("Credential key file is corrupt")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut key = vec![0u8; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                write_private(&self.key_path, &key)?;
+                key
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    fn read_all(&self) -> Result<HashMap<String, Credentials>, WatchtowerError> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        if data.len() < NONCE_LEN {
+            return Err(WatchtowerError::storage("Credential file is truncated"));
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher()?
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| WatchtowerError::storage("Credential file could not be decrypted"))?;
+        serde_json::from_slice(&plaintext).map_err(|e| WatchtowerError::storage(e.to_string()))
+    }
+
+    fn write_all(&self, entries: &HashMap<String, Credentials>) -> Result<(), WatchtowerError> {
+        let plaintext =
+            serde_json::to_vec(entries).map_err(|e| WatchtowerError::storage(e.to_string()))?;
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher()?
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| WatchtowerError::storage("Failed to encrypt credentials"))?;
+
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&ciphertext);
+        write_private(&self.path, &data)
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn kind(&self) -> &'static str {
+        FILE
+    }
+
+    fn load(&self, id: &str) -> Result<Option<Credentials>, WatchtowerError> {
+        let _guard = self.lock.lock().unwrap();
+        Ok(self.read_all()?.remove(id))
+    }
+
+    fn save(&self, id: &str, credentials: &Credentials) -> Result<(), WatchtowerError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entries = self.read_all()?;
+        entries.insert(id.to_string(), credentials.clone());
+        self.write_all(&entries)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), WatchtowerError> {
+        let _guard = self.lock.lock().unwrap();
+        let mut entries = self.read_all()?;
+        if entries.remove(id).is_some() {
+            self.write_all(&entries)?;
+        }
+        Ok(())
+    }
+}
+
+/// `credentials.enc` → `credentials.enc.tmp`, so files sharing a stem don't share one.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Writes via a temp file, readable by the current user only.
+fn write_private(path: &Path, data: &[u8]) -> Result<(), WatchtowerError> {
+    use std::io::Write;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_path = temp_path(path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&tmp_path)?.write_all(data)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+struct Stores {
+    preferred: &'static str,
+    secret_service: Box<dyn CredentialStore>,
+    file: EncryptedFileStore,
+    /// `db::get_user` runs every tick of the tray loop; keep it off D-Bus.
+    cache: Mutex<HashMap<String, Credentials>>,
+}
+
+impl Stores {
+    /// New credentials go to the secret service unless `forced` names a backend or the
+    /// secret service doesn't answer. Existing references keep using their own store.
+    fn new(
+        secret_service: Box<dyn CredentialStore>,
+        file: EncryptedFileStore,
+        forced: Option<&str>,
+    ) -> Self {
+        let preferred = match forced {
+            Some(SECRET_SERVICE) => SECRET_SERVICE,
+            Some(FILE) => FILE,
+            _ if secret_service.is_available() => SECRET_SERVICE,
+            _ => FILE,
+        };
+        Self {
+            preferred,
+            secret_service,
+            file,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, kind: &str) -> Result<&dyn CredentialStore, WatchtowerError> {
+        match kind {
+            SECRET_SERVICE => Ok(self.secret_service.as_ref()),
+            FILE => Ok(&self.file),
+            _ => Err(WatchtowerError::storage(format!(
+                "Unknown credential store {:?}",
+                kind
+            ))),
+        }
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<String, WatchtowerError> {
+        let store = self.get(self.preferred)?;
+        let reference = format!("{}:{}", store.kind(), uuid::Uuid::new_v4());
+        self.update(&reference, credentials)?;
+        Ok(reference)
+    }
+
+    fn update(&self, reference: &str, credentials: &Credentials) -> Result<(), WatchtowerError> {
+        let (kind, id) = parse_reference(reference)?;
+        self.get(kind)?.save(id, credentials)?;
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(reference.to_string(), credentials.clone());
+        Ok(())
+    }
+
+    fn load(&self, reference: &str) -> Result<Option<Credentials>, WatchtowerError> {
+        if let Some(cached) = self.cache.lock().unwrap().get(reference) {
+            return Ok(Some(cached.clone()));
+        }
+
+        let (kind, id) = parse_reference(reference)?;
+        let loaded = self.get(kind)?.load(id)?;
+        if let Some(credentials) = &loaded {
+            self.cache
+                .lock()
+                .unwrap()
+                .insert(reference.to_string(), credentials.clone());
+        }
+        Ok(loaded)
+    }
+
+    fn delete(&self, reference: &str) -> Result<(), WatchtowerError> {
+        self.cache.lock().unwrap().remove(reference);
+        let (kind, id) = parse_reference(reference)?;
+        self.get(kind)?.delete(id)
+    }
+}
+
+static STORES: OnceLock<Stores> = OnceLock::new();
+
+/// Picks the backend for new credentials. Called once during setup, before any database
+/// is opened. `key_dir` holds the file store's key and should be outside `app_data_dir`.
+pub fn init(app_data_dir: &Path, key_dir: &Path) {
+    let forced = std::env::var(BACKEND_VAR).ok();
+    let stores = Stores::new(
+        Box::new(SecretServiceStore),
+        EncryptedFileStore::new(
+            app_data_dir.join("credentials.enc"),
+            key_dir.join("credentials.key"),
+        ),
+        forced.as_deref(),
+    );
+    println!("Credentials: Storing tokens in {}", stores.preferred);
+    if stores.preferred == FILE && key_dir == app_data_dir {
+        eprintln!("Credentials: Key is kept with the data; tokens are obfuscated, not protected");
+    }
+    let _ = STORES.set(stores);
+}
+
+fn stores() -> Result<&'static Stores, WatchtowerError> {
+    STORES.get().ok_or(WatchtowerError::Internal {
+        message: "Credential store not initialised".to_string(),
+    })
+}
+
+fn parse_reference(reference: &str) -> Result<(&str, &str), WatchtowerError> {
+    reference.split_once(':').ok_or_else(|| {
+        WatchtowerError::storage(format!("Bad credential reference {:?}", reference))
+    })
+}
+
+/// Stores new credentials and returns the reference to keep in the database.
+pub fn save(credentials: &Credentials) -> Result<String, WatchtowerError> {
+    stores()?.save(credentials)
+}
+
+/// Replaces the credentials behind an existing reference.
+pub fn update(reference: &str, credentials: &Credentials) -> Result<(), WatchtowerError> {
+    stores()?.update(reference, credentials)
+}
+
+pub fn load(reference: &str) -> Result<Option<Credentials>, WatchtowerError> {
+    stores()?.load(reference)
+}
+
+pub fn delete(reference: &str) -> Result<(), WatchtowerError> {
+    stores()?.delete(reference)
+}
+
+/// Moves tokens that older releases stored in `users` into the credential store.
+pub fn move_plaintext_tokens(conn: &Connection) -> Result<usize, WatchtowerError> {
+    let mut stmt = conn.prepare(
+        "SELECT uuid, token, refresh_token FROM users WHERE credential_ref IS NULL AND token != ''",
+    )?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Credentials {
+                    token: row.get(1)?,
+                    refresh_token: row.get::<_, Option<String>>(2)?.filter(|s| !s.is_empty()),
+                },
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (uuid, credentials) in &rows {
+        let reference = save(credentials)?;
+        conn.execute(
+            "UPDATE users SET credential_ref = ?1, token = '', refresh_token = NULL WHERE uuid = ?2",
+            (&reference, uuid),
+        )?;
+    }
+    if !rows.is_empty() {
+        println!("Credentials: Moved tokens out of the database");
+    }
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(token: &str) -> Credentials {
+        Credentials {
+            token: token.to_string(),
+            refresh_token: Some(format!("{}-refresh", token)),
+        }
+    }
+
+    fn file_store(dir: &Path) -> EncryptedFileStore {
+        EncryptedFileStore::new(
+            dir.join("data/credentials.enc"),
+            dir.join("config/credentials.key"),
+        )
+    }
+
+    #[test]
+    fn file_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = file_store(dir.path());
+
+        assert!(store.load("a").unwrap().is_none());
+        store.save("a", &credentials("one")).unwrap();
+        store.save("b", &credentials("two")).unwrap();
+        store.save("a", &credentials("three")).unwrap();
+
+        // A fresh instance reads what the first one wrote
+        let reopened = file_store(dir.path());
+        assert_eq!(reopened.load("a").unwrap().unwrap().token, "three");
+        assert_eq!(
+            reopened
+                .load("b")
+                .unwrap()
+                .unwrap()
+                .refresh_token
+                .as_deref(),
+            Some("two-refresh")
+        );
+
+        reopened.delete("a").unwrap();
+        reopened.delete("a").unwrap();
+        assert!(store.load("a").unwrap().is_none());
+        assert_eq!(store.load("b").unwrap().unwrap().token, "two");
+    }
+
+    #[test]
+    fn file_store_keeps_tokens_out_of_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = file_store(dir.path());
+        store.save("a", &credentials("plain-token")).unwrap();
+
+        let data = fs::read(dir.path().join("data/credentials.enc")).unwrap();
+        assert!(!data
+            .windows(b"plain-token".len())
+            .any(|w| w == b"plain-token"));
+    }
+
+    #[test]
+    fn file_store_needs_its_key() {
+        let dir = tempfile::tempdir().unwrap();
+        file_store(dir.path())
+            .save("a", &credentials("one"))
+            .unwrap();
+
+        // The data dir alone, with a different key, doesn't decrypt
+        let other_key = EncryptedFileStore::new(
+            dir.path().join("data/credentials.enc"),
+            dir.path().join("elsewhere/credentials.key"),
+        );
+        assert!(other_key.load("a").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_store_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        file_store(dir.path())
+            .save("a", &credentials("one"))
+            .unwrap();
+        for file in ["data/credentials.enc", "config/credentials.key"] {
+            let mode = fs::metadata(dir.path().join(file))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", file);
+        }
+    }
+
+    #[test]
+    fn temp_files_are_named_after_the_whole_file_name() {
+        let dir = Path::new("/config");
+        let key = temp_path(&dir.join("credentials.key"));
+        let data = temp_path(&dir.join("credentials.enc"));
+        assert_eq!(key, dir.join("credentials.key.tmp"));
+        assert_ne!(key, data);
+    }
+
+    #[test]
+    fn file_store_shares_a_dir_with_its_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = EncryptedFileStore::new(
+            dir.path().join("credentials.enc"),
+            dir.path().join("credentials.key"),
+        );
+        store.save("a", &credentials("one")).unwrap();
+        assert_eq!(store.load("a").unwrap().unwrap().token, "one");
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["credentials.enc", "credentials.key"]);
+    }
+
+    /// Stands in for the OS secret service.
+    struct MockSecretService {
+        available: bool,
+        entries: Mutex<HashMap<String, Credentials>>,
+    }
+
+    impl MockSecretService {
+        fn new(available: bool) -> Box<Self> {
+            Box::new(Self {
+                available,
+                entries: Mutex::new(HashMap::new()),
+            })
+        }
+
+        fn check(&self) -> Result<(), WatchtowerError> {
+            match self.available {
+                true => Ok(()),
+                false => Err(WatchtowerError::storage("No D-Bus session")),
+            }
+        }
+    }
+
+    impl CredentialStore for MockSecretService {
+        fn kind(&self) -> &'static str {
+            SECRET_SERVICE
+        }
+
+        fn is_available(&self) -> bool {
+            self.available
+        }
+
+        fn load(&self, id: &str) -> Result<Option<Credentials>, WatchtowerError> {
+            self.check()?;
+            Ok(self.entries.lock().unwrap().get(id).cloned())
+        }
+
+        fn save(&self, id: &str, credentials: &Credentials) -> Result<(), WatchtowerError> {
+            self.check()?;
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(id.to_string(), credentials.clone());
+            Ok(())
+        }
+
+        fn delete(&self, id: &str) -> Result<(), WatchtowerError> {
+            self.check()?;
+            self.entries.lock().unwrap().remove(id);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn secret_service_is_preferred_when_it_answers() {
+        let dir = tempfile::tempdir().unwrap();
+        let stores = Stores::new(MockSecretService::new(true), file_store(dir.path()), None);
+        let reference = stores.save(&credentials("one")).unwrap();
+        assert!(reference.starts_with("secret-service:"));
+        assert!(!dir.path().join("data/credentials.enc").exists());
+
+        // Read back from the store, not just the cache
+        stores.cache.lock().unwrap().clear();
+        assert_eq!(stores.load(&reference).unwrap().unwrap().token, "one");
+        stores.delete(&reference).unwrap();
+        assert!(stores.load(&reference).unwrap().is_none());
+    }
+
+    #[test]
+    fn unavailable_secret_service_falls_back_to_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let stores = Stores::new(MockSecretService::new(false), file_store(dir.path()), None);
+        let reference = stores.save(&credentials("one")).unwrap();
+        assert!(reference.starts_with("file:"));
+
+        stores.cache.lock().unwrap().clear();
+        assert_eq!(stores.load(&reference).unwrap().unwrap().token, "one");
+        // References made while it was up still go to the secret service, and fail
+        assert!(stores.load("secret-service:old").is_err());
+    }
+
+    #[test]
+    fn forced_backend_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let stores = Stores::new(
+            MockSecretService::new(true),
+            file_store(dir.path()),
+            Some(FILE),
+        );
+        assert_eq!(stores.preferred, FILE);
+        let stores = Stores::new(
+            MockSecretService::new(false),
+            file_store(dir.path()),
+            Some(SECRET_SERVICE),
+        );
+        assert_eq!(stores.preferred, SECRET_SERVICE);
+        let stores = Stores::new(
+            MockSecretService::new(true),
+            file_store(dir.path()),
+            Some("other"),
+        );
+        assert_eq!(stores.preferred, SECRET_SERVICE);
+    }
+
+    /// Runs against whatever secret service answers on the session bus, so it is ignored
+    /// by default. Use a throwaway bus with a test provider, e.g.:
+    ///
+    /// ```sh
+    /// dbus-run-session -- sh -c 'echo -n test | gnome-keyring-daemon --unlock --components=secrets >/dev/null \
+    ///     && cargo test secret_service -- --ignored'
+    /// ```
+    #[test]
+    #[ignore = "needs a secret service on the session bus"]
+    fn secret_service_round_trip() {
+        let store = SecretServiceStore;
+        assert!(store.is_available(), "no secret service on the session bus");
+        let id = format!("test-{}", uuid::Uuid::new_v4());
+
+        assert!(store.load(&id).unwrap().is_none());
+        store.save(&id, &credentials("one")).unwrap();
+        assert_eq!(store.load(&id).unwrap().unwrap().token, "one");
+
+        store.save(&id, &credentials("two")).unwrap();
+        let loaded = store.load(&id).unwrap().unwrap();
+        assert_eq!(loaded.token, "two");
+        assert_eq!(loaded.refresh_token.as_deref(), Some("two-refresh"));
+
+        store.delete(&id).unwrap();
+        assert!(store.load(&id).unwrap().is_none());
+        // Deleting what isn't there is not an error
+        store.delete(&id).unwrap();
+    }
+}
@@ -1,190 +1,158 @@
 use rusqlite::Connection;
-use std::path::PathBuf;
-use crate::models::{User, Project};
-
-struct DbColumn {
-    name: &'static str,
-    def: &'static str,
-    type_affinity: &'static str,
-}
-
-struct DbTable {
-    name: &'static str,
-    columns: &'static [DbColumn],
-    constraints: Option<&'static str>,
-}
-
-const SCHEMA: &[DbTable] = &[
-    DbTable {
-        name: "users",
-        columns: &[
-            DbColumn { name: "uuid", def: "TEXT PRIMARY KEY", type_affinity: "TEXT" },
-            DbColumn { name: "name", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-            DbColumn { name: "email", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-            DbColumn { name: "token", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-            DbColumn { name: "refresh_token", def: "TEXT", type_affinity: "TEXT" },
-            DbColumn { name: "current_project_id", def: "TEXT", type_affinity: "TEXT" },
-        ],
-        constraints: None,
-    },
-    DbTable {
-        name: "projects",
-        columns: &[
-            DbColumn { name: "id", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-            DbColumn { name: "name", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-            DbColumn { name: "weekly_limit_hours", def: "REAL", type_affinity: "REAL" },
-            DbColumn { name: "daily_limit_hours", def: "REAL", type_affinity: "REAL" },
-            DbColumn { name: "screenshots_enabled", def: "INTEGER DEFAULT 1", type_affinity: "INTEGER" },
-            DbColumn { name: "total_hours_this_week", def: "REAL", type_affinity: "REAL" },
-        ],
-        constraints: Some("PRIMARY KEY (id)"),
-    },
-    DbTable {
-        name: "sessions",
-        columns: &[
-            DbColumn { name: "id", def: "INTEGER PRIMARY KEY AUTOINCREMENT", type_affinity: "INTEGER" },
-            DbColumn { name: "uuid", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-            DbColumn { name: "project_id", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-            DbColumn { name: "project_type", def: "TEXT DEFAULT 'Project'", type_affinity: "TEXT" },
-            DbColumn { name: "duration_minutes", def: "INTEGER DEFAULT 0", type_affinity: "INTEGER" },
-            DbColumn { name: "target_name", def: "TEXT", type_affinity: "TEXT" },
-            DbColumn { name: "start_time", def: "INTEGER NOT NULL", type_affinity: "INTEGER" },
-            DbColumn { name: "end_time", def: "INTEGER", type_affinity: "INTEGER" },
-            DbColumn { name: "is_active", def: "INTEGER DEFAULT 0", type_affinity: "INTEGER" },
-            DbColumn { name: "idle_seconds", def: "INTEGER DEFAULT 0", type_affinity: "INTEGER" },
-            DbColumn { name: "deducted_seconds", def: "INTEGER DEFAULT 0", type_affinity: "INTEGER" },
-            DbColumn { name: "keyboard_events", def: "INTEGER DEFAULT 0", type_affinity: "INTEGER" },
-            DbColumn { name: "mouse_events", def: "INTEGER DEFAULT 0", type_affinity: "INTEGER" },
-            DbColumn { name: "status", def: "TEXT DEFAULT 'pending'", type_affinity: "TEXT" },
-        ],
-        constraints: None,
-    },
-    DbTable {
-        name: "pending_screenshots",
-        columns: &[
-             DbColumn { name: "id", def: "INTEGER PRIMARY KEY AUTOINCREMENT", type_affinity: "INTEGER" },
-             DbColumn { name: "session_uuid", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-             DbColumn { name: "project_id", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-             DbColumn { name: "timestamp", def: "INTEGER NOT NULL", type_affinity: "INTEGER" },
-             DbColumn { name: "image_data", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-        ],
-        constraints: None,
-    },
-    DbTable {
-        name: "activity_logs",
-        columns: &[
-             DbColumn { name: "id", def: "INTEGER PRIMARY KEY AUTOINCREMENT", type_affinity: "INTEGER" },
-             DbColumn { name: "session_uuid", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-             DbColumn { name: "project_id", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-             DbColumn { name: "timestamp", def: "INTEGER NOT NULL", type_affinity: "INTEGER" },
-             DbColumn { name: "app_name", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-             DbColumn { name: "window_title", def: "TEXT NOT NULL", type_affinity: "TEXT" },
-             DbColumn { name: "url", def: "TEXT", type_affinity: "TEXT" },
-        ],
-        constraints: None,
-    },
-];
-
-pub fn init_db(path: &PathBuf) -> Result<Connection, rusqlite::Error> {
-    let conn = Connection::open(path)?;
-    
-    // Disable foreign keys temporarily to allow dropping tables out of order if needed
-    conn.execute("PRAGMA foreign_keys = OFF", [])?;
+use std::path::Path;
+use crate::credentials::{self, Credentials};
+use crate::error::WatchtowerError;
+use crate::migrations::{self, MigrationError};
+use crate::models::{CaptureContext, CaptureGroup, MonitorInfo, PendingScreenshot, Project, User};
+use crate::screenshot_store::StoredScreenshot;
 
-    for table in SCHEMA {
-        let mut needs_recreation = false;
+pub fn init_db(path: &Path) -> Result<Connection, MigrationError> {
+    let mut conn = Connection::open(path)?;
+    migrations::run(&mut conn)?;
+    Ok(conn)
+}
 
-        // Check if table exists and get columns
-        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table.name))?;
-        let existing_columns: Vec<(String, String)> = stmt.query_map([], |row| {
-            Ok((row.get(1)?, row.get(2)?)) // name, type
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
+/// Credential store failures are carried through rusqlite's error type so the `db` API
+/// keeps one error type; `WatchtowerError::from` unwraps them again.
+fn credential_error(e: WatchtowerError) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(Box::new(e))
+}
 
-        if existing_columns.is_empty() {
-             needs_recreation = true;
-        } else {
-             // Basic Check: Count match?
-             if existing_columns.len() != table.columns.len() {
-                 needs_recreation = true;
-             } else {
-                 // Detailed Check: Names and Types match?
-                 for col in table.columns {
-                     let match_found = existing_columns.iter().any(|(ex_name, ex_type)| {
-                         ex_name == col.name && ex_type.eq_ignore_ascii_case(col.type_affinity)
-                     });
-                     if !match_found {
-                         needs_recreation = true;
-                         break;
-                     }
-                 }
-             }
+fn credential_refs(conn: &Connection) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT credential_ref FROM users WHERE credential_ref IS NOT NULL")?;
+    let refs = stmt.query_map([], |row| row.get(0))?.collect();
+    refs
+}
+
+pub fn save_user(conn: &mut Connection, user: &User) -> Result<(), rusqlite::Error> {
+    let credentials = Credentials {
+        token: user.token.clone(),
+        refresh_token: user.refresh_token.clone(),
+    };
+
+    // Re-saving the same user keeps its entry in the credential store
+    let existing_ref: Option<String> = conn
+        .query_row("SELECT credential_ref FROM users WHERE uuid = ?1", [&user.uuid], |row| row.get(0))
+        .ok()
+        .flatten();
+    // Tokens are written first so the row never points at missing ones; if the database
+    // write then fails, the store is put back the way it was
+    let (credential_ref, previous) = match existing_ref {
+        Some(reference) => {
+            let previous = credentials::load(&reference).map_err(credential_error)?;
+            credentials::update(&reference, &credentials).map_err(credential_error)?;
+            (reference, previous)
         }
+        None => (credentials::save(&credentials).map_err(credential_error)?, None),
+    };
 
-        if needs_recreation {
-            conn.execute(&format!("DROP TABLE IF EXISTS {}", table.name), [])?;
-            
-            let cols_sql: Vec<String> = table.columns.iter()
-                .map(|c| format!("{} {}", c.name, c.def))
-                .collect();
-            
-            let create_sql = format!(
-                "CREATE TABLE {} ({}{})", 
-                table.name, 
-                cols_sql.join(", "),
-                table.constraints.map(|c| format!(", {}", c)).unwrap_or_default()
-            );
-            
-            conn.execute(&create_sql, [])?;
+    let replaced_refs = match replace_user(conn, user, &credential_ref) {
+        Ok(replaced_refs) => replaced_refs,
+        Err(e) => {
+            let undo = match previous {
+                Some(previous) => credentials::update(&credential_ref, &previous),
+                None => credentials::delete(&credential_ref),
+            };
+            if let Err(undo_err) = undo {
+                eprintln!("DB: Failed to roll back credentials: {}", undo_err);
+            }
+            return Err(e);
         }
-    }
-    
-    // Re-enable foreign keys
-    conn.execute("PRAGMA foreign_keys = ON", [])?;
+    };
 
-    Ok(conn)
+    for reference in replaced_refs {
+        if let Err(e) = credentials::delete(&reference) {
+            eprintln!("DB: Failed to remove old credentials: {}", e);
+        }
+    }
+    Ok(())
 }
 
-pub fn save_user(conn: &mut Connection, user: &User) -> Result<(), rusqlite::Error> {
+/// Replaces the stored user and projects in one transaction. Returns the credential
+/// references the previous rows held, other than `credential_ref`.
+fn replace_user(conn: &mut Connection, user: &User, credential_ref: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let replaced_refs: Vec<String> = credential_refs(conn)?
+        .into_iter()
+        .filter(|r| r != credential_ref)
+        .collect();
+
     let tx = conn.transaction()?;
 
     // Clear existing data (single user mode)
     tx.execute("DELETE FROM projects", [])?;
     tx.execute("DELETE FROM users", [])?;
 
-    // Insert user
+    // Insert user (tokens live in the credential store; only the reference is kept)
     tx.execute(
-        "INSERT INTO users (uuid, name, email, token, refresh_token, current_project_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        [
+        "INSERT INTO users (uuid, name, email, token, refresh_token, current_project_id, token_expires, credential_ref) VALUES (?1, ?2, ?3, '', NULL, ?4, ?5, ?6)",
+        (
             &user.uuid,
             &user.name,
             &user.email,
-            &user.token,
-            user.refresh_token.as_deref().unwrap_or_default(),
-            user.current_project_id.as_deref().unwrap_or_default(), 
-        ],
+            user.current_project_id.as_deref().unwrap_or_default(),
+            user.token_expires,
+            credential_ref,
+        ),
     )?;
-     
-     // Insert projects
-     for project in &user.projects {
-         tx.execute(
-             "INSERT INTO projects (id, name, weekly_limit_hours, daily_limit_hours, screenshots_enabled, total_hours_this_week) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-             (
-                 &project.id, 
-                 &project.name,
-                 project.weekly_limit_hours,
-                 project.daily_limit_hours,
-                 project.screenshots_enabled as i32,
-                 project.total_hours_this_week,
-             ),
-         )?;
-     }
+
+    insert_projects(&tx, &user.projects)?;
+
+    tx.commit()?;
+    Ok(replaced_refs)
+}
+
+fn insert_projects(conn: &Connection, projects: &[Project]) -> Result<(), rusqlite::Error> {
+    for project in projects {
+        conn.execute(
+            "INSERT INTO projects (id, name, weekly_limit_hours, daily_limit_hours, screenshots_enabled, total_hours_this_week, chunk_policy, monitor_layout, capture_schedule, blur_level, encoding_profile) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            (
+                &project.id,
+                &project.name,
+                project.weekly_limit_hours,
+                project.daily_limit_hours,
+                project.screenshots_enabled as i32,
+                project.total_hours_this_week,
+                project.chunk_policy.and_then(|policy| serde_json::to_string(&policy).ok()),
+                project.monitor_layout.and_then(|layout| serde_json::to_string(&layout).ok()),
+                project.capture_schedule.and_then(|schedule| serde_json::to_string(&schedule).ok()),
+                project.blur_level.and_then(|level| serde_json::to_string(&level).ok()),
+                project.encoding_profile.and_then(|profile| serde_json::to_string(&profile).ok()),
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+/// Replaces the project list without touching the user row. If the selected project is
+/// gone, the first remaining one is selected instead.
+pub fn replace_projects(conn: &Connection, projects: &[Project]) -> Result<(), rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM projects", [])?;
+    insert_projects(&tx, projects)?;
+
+    let current: Option<String> = tx
+        .query_row("SELECT current_project_id FROM users LIMIT 1", [], |row| row.get(0))
+        .ok()
+        .flatten()
+        .filter(|id: &String| !id.is_empty());
+    let still_exists = current
+        .as_ref()
+        .is_some_and(|id| projects.iter().any(|p| &p.id == id));
+    if !still_exists {
+        let first = projects.first().map(|p| p.id.as_str()).unwrap_or_default();
+        tx.execute("UPDATE users SET current_project_id = ?1", [first])?;
+    }
 
     tx.commit()?;
     Ok(())
 }
 
 pub fn clear_user(conn: &Connection) -> Result<(), rusqlite::Error> {
+    for reference in credential_refs(conn)? {
+        if let Err(e) = credentials::delete(&reference) {
+            eprintln!("DB: Failed to remove credentials: {}", e);
+        }
+    }
     conn.execute("DELETE FROM projects", [])?;
     conn.execute("DELETE FROM users", [])?;
     conn.execute("DELETE FROM sessions", [])?;
@@ -199,101 +167,95 @@ pub fn set_current_project(conn: &Connection, project_id: &str) -> Result<(), ru
     Ok(())
 }
 
+/// Moves a running timer to another project: the active session is closed, the project
+/// selected and a new session (and work run) opened on it, all or nothing.
+pub fn switch_running_project(conn: &Connection, project_id: &str) -> Result<(), rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    stop_all_active_sessions(&tx)?;
+    set_current_project(&tx, project_id)?;
+    start_session(&tx, project_id, "Project", 0, None)?;
+    tx.commit()?;
+    Ok(())
+}
+
 // Session Management
 
-use crate::models::Session;
+use crate::models::{PauseInterval, Session};
 // Database module
 
-use chrono::Local;
+use crate::week;
+use chrono::{DateTime, Local, TimeZone, Weekday};
 use uuid::Uuid;
 
+const SESSION_COLUMNS: &str = "id, uuid, project_id, project_type, start_time, end_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events, duration_minutes, target_name, parent_run_uuid, paused_seconds, paused_at";
+
+fn session_from_row(row: &rusqlite::Row) -> Result<Session, rusqlite::Error> {
+    Ok(Session {
+        id: Some(row.get(0)?),
+        uuid: row.get(1)?,
+        project_id: row.get(2)?,
+        project_type: row.get(3)?,
+        start_time: row.get(4)?,
+        end_time: row.get(5)?,
+        is_active: row.get(6)?,
+        idle_seconds: row.get(7)?,
+        deducted_seconds: row.get(8)?,
+        status: row.get(9)?,
+        keyboard_events: row.get(10)?,
+        mouse_events: row.get(11)?,
+        duration_minutes: row.get(12)?,
+        target_name: row.get(13)?,
+        parent_run_uuid: row.get(14)?,
+        paused_seconds: row.get(15)?,
+        paused_at: row.get(16)?,
+    })
+}
+
 pub fn get_session_by_uuid(conn: &Connection, uuid: &str) -> Result<Option<Session>, rusqlite::Error> {
-    let mut stmt = conn.prepare(
-        "SELECT id, uuid, project_id, project_type, start_time, end_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events, duration_minutes, target_name 
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}
          FROM sessions 
-         WHERE uuid = ?1"
-    )?;
+         WHERE uuid = ?1",
+        SESSION_COLUMNS
+    ))?;
     
     let mut rows = stmt.query([uuid])?;
     if let Some(row) = rows.next()? {
-        Ok(Some(Session {
-            id: Some(row.get(0)?),
-            uuid: row.get(1)?,
-            project_id: row.get(2)?,
-            project_type: row.get(3)?,
-            start_time: row.get(4)?,
-            end_time: row.get(5)?,
-            is_active: row.get(6)?,
-            idle_seconds: row.get(7)?,
-            deducted_seconds: row.get(8)?,
-            status: row.get(9)?,
-            keyboard_events: row.get(10)?,
-            mouse_events: row.get(11)?,
-            duration_minutes: row.get(12)?,
-            target_name: row.get(13)?,
-        }))
+        Ok(Some(session_from_row(row)?))
     } else {
         Ok(None)
     }
 }
 
 pub fn get_active_session(conn: &Connection, project_id: &str) -> Result<Option<Session>, rusqlite::Error> {
-    let mut stmt = conn.prepare(
-        "SELECT id, uuid, project_id, project_type, start_time, end_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events, duration_minutes, target_name 
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}
          FROM sessions 
          WHERE project_id = ?1 AND is_active = 1 
-         LIMIT 1"
-    )?;
+         LIMIT 1",
+        SESSION_COLUMNS
+    ))?;
     
     let mut rows = stmt.query([project_id])?;
     if let Some(row) = rows.next()? {
-        Ok(Some(Session {
-            id: Some(row.get(0)?),
-            uuid: row.get(1)?,
-            project_id: row.get(2)?,
-            project_type: row.get(3)?,
-            start_time: row.get(4)?,
-            end_time: row.get(5)?,
-            is_active: row.get(6)?,
-            idle_seconds: row.get(7)?,
-            deducted_seconds: row.get(8)?,
-            status: row.get(9)?,
-            keyboard_events: row.get(10)?,
-            mouse_events: row.get(11)?,
-            duration_minutes: row.get(12)?,
-            target_name: row.get(13)?,
-        }))
+        Ok(Some(session_from_row(row)?))
     } else {
         Ok(None)
     }
 }
 
 pub fn get_global_active_session(conn: &Connection) -> Result<Option<Session>, rusqlite::Error> {
-    let mut stmt = conn.prepare(
-        "SELECT id, uuid, project_id, project_type, start_time, end_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events, duration_minutes, target_name 
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}
          FROM sessions 
          WHERE is_active = 1 
-         LIMIT 1"
-    )?;
+         LIMIT 1",
+        SESSION_COLUMNS
+    ))?;
     
     let mut rows = stmt.query([])?;
     if let Some(row) = rows.next()? {
-        Ok(Some(Session {
-            id: Some(row.get(0)?),
-            uuid: row.get(1)?,
-            project_id: row.get(2)?,
-            project_type: row.get(3)?,
-            start_time: row.get(4)?,
-            end_time: row.get(5)?,
-            is_active: row.get(6)?,
-            idle_seconds: row.get(7)?,
-            deducted_seconds: row.get(8)?,
-            status: row.get(9)?,
-            keyboard_events: row.get(10)?,
-            mouse_events: row.get(11)?,
-            duration_minutes: row.get(12)?,
-            target_name: row.get(13)?,
-        }))
+        Ok(Some(session_from_row(row)?))
     } else {
         Ok(None)
     }
@@ -304,26 +266,113 @@ pub fn start_session(conn: &Connection, project_id: &str, project_type: &str, du
     let uuid = Uuid::new_v4().to_string();
     println!("DB: Starting session for project {}, type {}, duration {}, uuid {}", project_id, project_type, duration_minutes, uuid);
     conn.execute(
-        "INSERT INTO sessions (uuid, project_id, project_type, duration_minutes, target_name, start_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events) VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1, 0, 0, 'pending', 0, 0)",
+        "INSERT INTO sessions (uuid, project_id, project_type, duration_minutes, target_name, start_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events, parent_run_uuid) VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1, 0, 0, 'pending', 0, 0, ?1)",
         (uuid, project_id, project_type, duration_minutes, target_name, start_time),
     )?;
     Ok(())
 }
 
-pub fn stop_session(conn: &Connection, project_id: &str) -> Result<(), rusqlite::Error> {
-    let end_time = Local::now().timestamp_millis();
-    println!("DB: Stopping session for project {}", project_id);
-    let updated = conn.execute(
-        "UPDATE sessions SET is_active = 0, end_time = ?1 WHERE project_id = ?2 AND is_active = 1",
-        (end_time, project_id),
+/// Ends a running session and opens the next chunk of the same work run, in one
+/// transaction so the run never has a gap or two active chunks.
+pub fn split_session(conn: &Connection, session_uuid: &str) -> Result<(), rusqlite::Error> {
+    let now = Local::now().timestamp_millis();
+    let tx = conn.unchecked_transaction()?;
+    let (project_id, project_type, parent_run_uuid): (String, String, Option<String>) = tx.query_row(
+        "SELECT project_id, project_type, parent_run_uuid FROM sessions WHERE uuid = ?1 AND is_active = 1",
+        [session_uuid],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let parent_run_uuid = parent_run_uuid.unwrap_or_else(|| session_uuid.to_string());
+
+    tx.execute(
+        "UPDATE sessions SET is_active = 0, end_time = ?1 WHERE uuid = ?2",
+        (now, session_uuid),
+    )?;
+    let uuid = Uuid::new_v4().to_string();
+    tx.execute(
+        "INSERT INTO sessions (uuid, project_id, project_type, duration_minutes, target_name, start_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events, parent_run_uuid) VALUES (?1, ?2, ?3, 0, NULL, ?4, 1, 0, 0, 'pending', 0, 0, ?5)",
+        (&uuid, &project_id, &project_type, now, &parent_run_uuid),
+    )?;
+    tx.commit()?;
+
+    println!("DB: Split session {} into {} (run {})", session_uuid, uuid, parent_run_uuid);
+    Ok(())
+}
+
+/// Pauses a running session. It stays active, but the time until `resume_session` is
+/// not tracked.
+pub fn pause_session(conn: &Connection, session_uuid: &str) -> Result<(), rusqlite::Error> {
+    let now = Local::now().timestamp_millis();
+    let tx = conn.unchecked_transaction()?;
+    let updated = tx.execute(
+        "UPDATE sessions SET paused_at = ?1 WHERE uuid = ?2 AND is_active = 1 AND paused_at IS NULL",
+        (now, session_uuid),
+    )?;
+    if updated > 0 {
+        tx.execute(
+            "INSERT INTO session_pauses (session_uuid, start_time) VALUES (?1, ?2)",
+            (session_uuid, now),
+        )?;
+    }
+    tx.commit()?;
+    println!("DB: Paused session {}", session_uuid);
+    Ok(())
+}
+
+pub fn resume_session(conn: &Connection, session_uuid: &str) -> Result<(), rusqlite::Error> {
+    let now = Local::now().timestamp_millis();
+    let tx = conn.unchecked_transaction()?;
+    end_pause(&tx, session_uuid, now)?;
+    // The heartbeat was frozen during the pause; carry on from now
+    tx.execute("UPDATE sessions SET end_time = ?1 WHERE uuid = ?2", (now, session_uuid))?;
+    tx.commit()?;
+    println!("DB: Resumed session {}", session_uuid);
+    Ok(())
+}
+
+/// Ends the session's open pause, if any, at `at`.
+fn end_pause(conn: &Connection, session_uuid: &str, at: i64) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE sessions
+         SET paused_seconds = paused_seconds + MAX(0, ?1 - paused_at) / 1000, paused_at = NULL
+         WHERE uuid = ?2 AND paused_at IS NOT NULL",
+        (at, session_uuid),
+    )?;
+    conn.execute(
+        "UPDATE session_pauses SET end_time = MAX(start_time, ?1) WHERE session_uuid = ?2 AND end_time IS NULL",
+        (at, session_uuid),
     )?;
-    println!("DB: Stopped {} sessions", updated);
+    Ok(())
+}
+
+pub fn get_session_pauses(conn: &Connection, session_uuid: &str) -> Result<Vec<PauseInterval>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT start_time, end_time FROM session_pauses WHERE session_uuid = ?1 ORDER BY start_time",
+    )?;
+    let rows = stmt.query_map([session_uuid], |row| {
+        Ok(PauseInterval {
+            start_time: row.get(0)?,
+            end_time: row.get(1)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn delete_session_pauses(conn: &Connection, session_uuid: &str) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM session_pauses WHERE session_uuid = ?1", [session_uuid])?;
     Ok(())
 }
 
 pub fn stop_all_active_sessions(conn: &Connection) -> Result<(), rusqlite::Error> {
     let end_time = Local::now().timestamp_millis();
     println!("DB: Stopping all active sessions");
+    let paused: Vec<String> = conn
+        .prepare("SELECT uuid FROM sessions WHERE is_active = 1 AND paused_at IS NOT NULL")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    for uuid in paused {
+        end_pause(conn, &uuid, end_time)?;
+    }
     let updated = conn.execute(
         "UPDATE sessions SET is_active = 0, end_time = ?1 WHERE is_active = 1",
         (end_time,),
@@ -332,11 +381,49 @@ pub fn stop_all_active_sessions(conn: &Connection) -> Result<(), rusqlite::Error
     Ok(())
 }
 
+/// Closes sessions left active by a crash or forced quit. Each one is ended at its last
+/// heartbeat (the `end_time` written every second) instead of the restart time, and
+/// unsynced ones are flagged `recovered`. Returns the sessions as they were closed.
+pub fn recover_orphaned_sessions(conn: &Connection) -> Result<Vec<Session>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}
+         FROM sessions
+         WHERE is_active = 1",
+        SESSION_COLUMNS
+    ))?;
+    let orphans = stmt
+        .query_map([], session_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tx = conn.unchecked_transaction()?;
+    let mut recovered = Vec::new();
+    for mut session in orphans {
+        let end_time = session.end_time.unwrap_or(session.start_time).max(session.start_time);
+        if session.status == "pending" {
+            session.status = "recovered".to_string();
+        }
+        if session.paused_at.is_some() {
+            end_pause(&tx, &session.uuid, end_time)?;
+        }
+        tx.execute(
+            "UPDATE sessions SET is_active = 0, end_time = ?1, status = ?2 WHERE id = ?3",
+            (end_time, &session.status, session.id),
+        )?;
+        session.is_active = false;
+        session.end_time = Some(end_time);
+        recovered.push(session);
+    }
+    tx.commit()?;
+
+    println!("DB: Recovered {} orphaned sessions", recovered.len());
+    Ok(recovered)
+}
+
 pub fn create_imported_session(conn: &Connection, session: &crate::models::SyncSession) -> Result<(), rusqlite::Error> {
     println!("DB: Importing session {}", session.uuid);
     conn.execute(
-        "INSERT INTO sessions (uuid, project_id, project_type, duration_minutes, target_name, start_time, end_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, 0, 0)",
+        "INSERT INTO sessions (uuid, project_id, project_type, duration_minutes, target_name, start_time, end_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events, synced_seconds, parent_run_uuid) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, 0, 0, ?12, COALESCE(?13, ?1))",
         (   
             &session.uuid, 
             &session.project_id, 
@@ -348,7 +435,9 @@ pub fn create_imported_session(conn: &Connection, session: &crate::models::SyncS
             session.is_active,
             session.idle_seconds,
             session.deducted_seconds,
-            "done"
+            "done",
+            tracked_seconds(session.start_time, session.end_time, session.deducted_seconds),
+            &session.parent_run_uuid,
         ),
     )?;
     Ok(())
@@ -358,7 +447,7 @@ pub fn update_imported_session(conn: &Connection, session: &crate::models::SyncS
     println!("DB: Updating imported session {}", session.uuid);
     conn.execute(
         "UPDATE sessions 
-         SET start_time = ?1, end_time = ?2, is_active = ?3, idle_seconds = ?4, deducted_seconds = ?5, project_type = ?6, duration_minutes = ?7, target_name = ?8, status = 'done'
+         SET start_time = ?1, end_time = ?2, is_active = ?3, idle_seconds = ?4, deducted_seconds = ?5, project_type = ?6, duration_minutes = ?7, target_name = ?8, status = 'done', synced_seconds = ?10, parent_run_uuid = COALESCE(?11, parent_run_uuid)
          WHERE uuid = ?9",
         (
             session.start_time,
@@ -369,7 +458,9 @@ pub fn update_imported_session(conn: &Connection, session: &crate::models::SyncS
             &session.project_type,
             session.duration_minutes,
             &session.target_name,
-            &session.uuid
+            &session.uuid,
+            tracked_seconds(session.start_time, session.end_time, session.deducted_seconds),
+            &session.parent_run_uuid,
         ),
     )?;
     Ok(())
@@ -391,61 +482,143 @@ pub fn get_today_total_time(conn: &Connection, project_id: &str) -> Result<u64,
     // Get start of today (local time)
     let now = Local::now();
     let start_of_day = now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_local_timezone(Local).unwrap().timestamp_millis();
-    
-    // Sum duration of closed sessions
-    // Duration = (end_time - start_time) / 1000 - deducted_seconds
-    // Filter by start_time >= start_of_day
+
+    let mut total_seconds: u64 = 0;
+    for (tracked, _) in project_session_times(conn, project_id, start_of_day, now.timestamp_millis())? {
+        total_seconds += tracked as u64;
+    }
+    Ok(total_seconds)
+}
+
+/// Seconds tracked locally on a project since the start of the week containing `now`.
+pub fn get_week_total_time<Tz: TimeZone>(conn: &Connection, project_id: &str, week_start: Weekday, now: &DateTime<Tz>) -> Result<u64, rusqlite::Error> {
+    let start_of_week = week::start_of_week(now, week_start);
+
+    let mut total_seconds: u64 = 0;
+    for (tracked, _) in project_session_times(conn, project_id, start_of_week, now.timestamp_millis())? {
+        total_seconds += tracked as u64;
+    }
+    Ok(total_seconds)
+}
+
+/// The part of this week's local time already included in the project's
+/// `total_hours_this_week`, as of the last project fetch.
+pub fn get_week_baseline_time<Tz: TimeZone>(conn: &Connection, project_id: &str, week_start: Weekday, now: &DateTime<Tz>) -> Result<u64, rusqlite::Error> {
+    let start_of_week = week::start_of_week(now, week_start);
+
+    let mut total_seconds: u64 = 0;
+    for (tracked, baseline) in project_session_times(conn, project_id, start_of_week, now.timestamp_millis())? {
+        total_seconds += baseline.clamp(0, tracked) as u64;
+    }
+    Ok(total_seconds)
+}
+
+/// (tracked seconds, baseline seconds) of each project session started since `since_ms`.
+/// Tracked time is the session length minus deductions and pauses; active sessions run
+/// until `now_ms`. A session counts wholly toward the day or week it started in: chunks
+/// end at midnight, so all that crosses over is a paused stretch or the last tick.
+fn project_session_times(conn: &Connection, project_id: &str, since_ms: i64, now_ms: i64) -> Result<Vec<(i64, i64)>, rusqlite::Error> {
     let mut stmt = conn.prepare(
-        "SELECT start_time, end_time, is_active, deducted_seconds 
-         FROM sessions 
+        "SELECT start_time, end_time, is_active, deducted_seconds, baseline_seconds, paused_seconds, paused_at
+         FROM sessions
          WHERE project_id = ?1 AND project_type = 'Project' AND start_time >= ?2"
     )?;
-    
-    let current_ts_millis = now.timestamp_millis();
-    let mut total_seconds: u64 = 0;
-    
-    let rows = stmt.query_map([project_id, &start_of_day.to_string()], |row| {
+
+    let rows = stmt.query_map((project_id, since_ms), |row| {
         let start: i64 = row.get(0)?;
         let end: Option<i64> = row.get(1)?;
         let active: bool = row.get(2)?;
         let deducted: i64 = row.get(3)?;
-        Ok((start, end, active, deducted))
-    })?;
+        let baseline: i64 = row.get(4)?;
+        let mut paused: i64 = row.get(5)?;
+        let paused_at: Option<i64> = row.get(6)?;
 
-    for r in rows {
-        let (start, end, active, deducted) = r?;
-        let mut session_duration_millis: i64 = 0;
-
-        if active {
-             if current_ts_millis > start {
-                 session_duration_millis = current_ts_millis - start;
-             }
-        } else {
-             if let Some(e) = end {
-                 if e > start {
-                     session_duration_millis = e - start;
-                 }
-             }
+        let end = if active { Some(now_ms) } else { end };
+        if let (true, Some(paused_at)) = (active, paused_at) {
+            paused += (now_ms - paused_at).max(0) / 1000;
         }
+        Ok((tracked_seconds(start, end, deducted + paused), baseline))
+    })?;
+    rows.collect()
+}
 
-        // Convert millis to seconds
-        let mut session_duration_seconds = session_duration_millis / 1000;
+/// Records how much of a session the server was just sent. Finished sessions are done.
+/// `session` is the snapshot that was sent; if the row has changed since (say review
+/// deducted time while the request was in flight), it is left pending for the next sync.
+pub fn mark_session_synced(conn: &Connection, session: &Session) -> Result<(), rusqlite::Error> {
+    let synced_seconds = tracked_seconds(
+        session.start_time,
+        session.end_time,
+        session.deducted_seconds + session.paused_seconds,
+    );
+    conn.execute(
+        "UPDATE sessions SET synced_seconds = ?1, status = CASE WHEN ?3 THEN 'done' ELSE status END
+         WHERE uuid = ?2 AND deducted_seconds = ?4 AND paused_seconds = ?5 AND IFNULL(end_time, 0) = ?6",
+        (synced_seconds, &session.uuid, !session.is_active, session.deducted_seconds, session.paused_seconds, session.end_time.unwrap_or(0)),
+    )?;
+    Ok(())
+}
 
-        // Subtract deducted seconds (ensure we don't go negative)
-        session_duration_seconds = session_duration_seconds - deducted;
-        if session_duration_seconds < 0 {
-            session_duration_seconds = 0;
-        }
+/// Session length minus deductions (idle and paused time), as the server counts it.
+fn tracked_seconds(start_time: i64, end_time: Option<i64>, deducted_seconds: i64) -> i64 {
+    let duration_millis = end_time.map(|end| end - start_time).unwrap_or(0).max(0);
+    (duration_millis / 1000 - deducted_seconds).max(0)
+}
+
+/// Session time the server had been sent by now, keyed by uuid. Taken right before
+/// fetching projects so the weekly totals that come back can be matched against it.
+pub fn get_synced_times(conn: &Connection) -> Result<Vec<(String, i64)>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT uuid, synced_seconds FROM sessions WHERE synced_seconds > baseline_seconds",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/// When the stored weekly totals were fetched (epoch ms), kept in settings.
+pub const WEEKLY_TOTALS_FETCHED_SETTING: &str = "weekly_totals_fetched_at";
 
-        total_seconds += session_duration_seconds as u64;
+/// Marks session time as included in freshly fetched weekly totals.
+pub fn set_baseline_times(conn: &Connection, synced: &[(String, i64)]) -> Result<(), rusqlite::Error> {
+    let tx = conn.unchecked_transaction()?;
+    set_setting(&tx, WEEKLY_TOTALS_FETCHED_SETTING, &Local::now().timestamp_millis().to_string())?;
+    for (uuid, seconds) in synced {
+        tx.execute(
+            "UPDATE sessions SET baseline_seconds = MAX(baseline_seconds, ?1) WHERE uuid = ?2",
+            (seconds, uuid),
+        )?;
     }
+    tx.commit()?;
+    Ok(())
+}
 
-    Ok(total_seconds)
+
+// Settings
+
+pub fn get_setting(conn: &Connection, key: &str) -> Result<Option<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT value FROM settings WHERE key = ?1")?;
+    let mut rows = stmt.query([key])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn set_setting(conn: &Connection, key: &str, value: &str) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        [key, value],
+    )?;
+    Ok(())
 }
 
+pub fn delete_setting(conn: &Connection, key: &str) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM settings WHERE key = ?1", [key])?;
+    Ok(())
+}
 
 pub fn get_user(conn: &Connection) -> Result<Option<User>, rusqlite::Error> {
-    let mut stmt = conn.prepare("SELECT uuid, name, email, token, current_project_id, refresh_token FROM users LIMIT 1")?;
+    let mut stmt = conn.prepare("SELECT uuid, name, email, credential_ref, current_project_id, token_expires FROM users LIMIT 1")?;
     
     let mut user_iter = stmt.query_map([], |row| {
         let uuid: String = row.get(0)?;
@@ -458,11 +631,42 @@ pub fn get_user(conn: &Connection) -> Result<Option<User>, rusqlite::Error> {
     Ok(None)
 }
 
-pub fn save_pending_screenshot(conn: &Connection, session_uuid: &str, project_id: &str, image_data: &str) -> Result<(), rusqlite::Error> {
+/// Stores a refreshed access token. The refresh token is only replaced when the server
+/// rotated it.
+pub fn update_user_tokens(conn: &Connection, uuid: &str, token: &str, refresh_token: Option<&str>, token_expires: Option<i64>) -> Result<(), rusqlite::Error> {
+    let reference: Option<String> = conn.query_row(
+        "SELECT credential_ref FROM users WHERE uuid = ?1",
+        [uuid],
+        |row| row.get(0),
+    )?;
+    let reference = reference.ok_or_else(|| credential_error(WatchtowerError::NotAuthenticated))?;
+
+    let previous = credentials::load(&reference).map_err(credential_error)?;
+    let credentials = Credentials {
+        token: token.to_string(),
+        refresh_token: refresh_token
+            .map(|s| s.to_string())
+            .or_else(|| previous.and_then(|c| c.refresh_token)),
+    };
+    credentials::update(&reference, &credentials).map_err(credential_error)?;
+
+    conn.execute(
+        "UPDATE users SET token_expires = ?1 WHERE uuid = ?2",
+        (token_expires, uuid),
+    )?;
+    Ok(())
+}
+
+/// Records a capture. With `unchanged`, `stored` is the earlier image it repeats and the
+/// row is only a marker; its size is recorded as zero since nothing new is stored.
+#[allow(clippy::too_many_arguments)]
+pub fn save_pending_screenshot(conn: &Connection, session_uuid: &str, project_id: &str, stored: &StoredScreenshot, monitors: &[MonitorInfo], perceptual_hash: u64, unchanged: bool, context: &CaptureContext, group: &CaptureGroup) -> Result<(), rusqlite::Error> {
     let timestamp = Local::now().timestamp_millis();
+    let monitors = serde_json::to_string(monitors).ok();
+    let size = if unchanged { 0 } else { stored.size };
     conn.execute(
-        "INSERT INTO pending_screenshots (session_uuid, project_id, timestamp, image_data) VALUES (?1, ?2, ?3, ?4)",
-        (session_uuid, project_id, timestamp, image_data),
+        "INSERT INTO pending_screenshots (session_uuid, project_id, timestamp, file_path, file_size, content_hash, monitors, perceptual_hash, unchanged, app_name, window_title, url, keyboard_events, mouse_events, capture_group, capture_seconds) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        (session_uuid, project_id, timestamp, &stored.file_name, size, &stored.hash, monitors, format!("{:016x}", perceptual_hash), unchanged, &context.app_name, &context.window_title, &context.url, context.keyboard_events, context.mouse_events, &group.id, group.seconds),
     )?;
     Ok(())
 }
@@ -499,16 +703,42 @@ pub fn delete_activity_logs_for_session(conn: &Connection, session_uuid: &str) -
     Ok(())
 }
 
-pub fn get_pending_screenshots(conn: &Connection) -> Result<Vec<(i64, String, String, i64, String)>, rusqlite::Error> {
-    let mut stmt = conn.prepare("SELECT id, session_uuid, project_id, timestamp, image_data FROM pending_screenshots")?;
-    let rows = stmt.query_map([], |row| {
-        Ok((
-            row.get(0)?,
-            row.get(1)?,
-            row.get(2)?,
-            row.get(3)?,
-            row.get(4)?,
-        ))
+pub fn get_pending_screenshots(conn: &Connection) -> Result<Vec<PendingScreenshot>, rusqlite::Error> {
+    get_pending_screenshots_where(conn, "1 = 1", [])
+}
+
+fn get_pending_screenshots_where<P: rusqlite::Params>(conn: &Connection, filter: &str, params: P) -> Result<Vec<PendingScreenshot>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, session_uuid, project_id, timestamp, file_path, file_size, content_hash, monitors, perceptual_hash, unchanged, 
+                app_name, window_title, url, keyboard_events, mouse_events 
+         FROM pending_screenshots 
+         WHERE file_path IS NOT NULL AND {} 
+         ORDER BY timestamp",
+        filter
+    ))?;
+    let rows = stmt.query_map(params, |row| {
+        Ok(PendingScreenshot {
+            id: row.get(0)?,
+            session_uuid: row.get(1)?,
+            project_id: row.get(2)?,
+            timestamp: row.get(3)?,
+            file_path: row.get(4)?,
+            file_size: row.get::<_, Option<i64>>(5)?.unwrap_or_default(),
+            content_hash: row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+            monitors: row
+                .get::<_, Option<String>>(7)?
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default(),
+            perceptual_hash: row.get(8)?,
+            unchanged: row.get::<_, Option<bool>>(9)?.unwrap_or(false),
+            context: CaptureContext {
+                app_name: row.get(10)?,
+                window_title: row.get(11)?,
+                url: row.get(12)?,
+                keyboard_events: row.get::<_, Option<i64>>(13)?.unwrap_or_default(),
+                mouse_events: row.get::<_, Option<i64>>(14)?.unwrap_or_default(),
+            },
+        })
     })?;
     
     let mut result = Vec::new();
@@ -518,9 +748,123 @@ pub fn get_pending_screenshots(conn: &Connection) -> Result<Vec<(i64, String, St
     Ok(result)
 }
 
+/// Pending screenshots whose retry deferral (if any) has elapsed and that were taken
+/// before `taken_before_ms`, i.e. are out of the review window. Unchanged markers wait
+/// until the image they repeat has been uploaded, so the server knows its hash.
+pub fn get_uploadable_screenshots(conn: &Connection, now_ms: i64, taken_before_ms: i64) -> Result<Vec<PendingScreenshot>, rusqlite::Error> {
+    get_pending_screenshots_where(
+        conn,
+        "next_attempt_at <= ?1 AND timestamp <= ?2 AND uploading = 0
+         AND NOT (unchanged = 1 AND EXISTS (
+             SELECT 1 FROM pending_screenshots original
+             WHERE original.content_hash = pending_screenshots.content_hash AND IFNULL(original.unchanged, 0) = 0
+         ))",
+        [now_ms, taken_before_ms],
+    )
+}
+
+/// Drops the unchanged markers repeating an image deleted on review; they would otherwise
+/// send the server the picture the user rejected. Returns how many were dropped.
+pub fn delete_unchanged_markers(conn: &Connection, content_hash: &str) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        "DELETE FROM pending_screenshots WHERE content_hash = ?1 AND unchanged = 1",
+        [content_hash],
+    )
+}
+
+/// Marks a screenshot as being uploaded, or reviewed, so the other can't take it too.
+/// Returns false if it is already claimed or gone.
+pub fn claim_screenshot(conn: &Connection, id: i64) -> Result<bool, rusqlite::Error> {
+    let claimed = conn.execute(
+        "UPDATE pending_screenshots SET uploading = 1 WHERE id = ?1 AND uploading = 0",
+        [id],
+    )?;
+    Ok(claimed == 1)
+}
+
+pub fn release_screenshot_claim(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute("UPDATE pending_screenshots SET uploading = 0 WHERE id = ?1", [id])?;
+    Ok(())
+}
+
+/// Drops claims left by a pass that was cut short. Only for startup, before any pass runs.
+pub fn release_screenshot_claims(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    conn.execute("UPDATE pending_screenshots SET uploading = 0 WHERE uploading = 1", [])
+}
+
+/// Records a failed upload, releases its claim and pushes the next attempt out
+/// exponentially: one minute after the first failure, doubling up to `max_delay_ms`.
+pub fn defer_screenshot(conn: &Connection, id: i64, error: &str, now_ms: i64, max_delay_ms: i64) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE pending_screenshots 
+         SET next_attempt_at = ?1 + MIN(?2, 60000 << MIN(attempts, 16)), 
+             attempts = attempts + 1, 
+             last_error = ?3, 
+             uploading = 0 
+         WHERE id = ?4",
+        (now_ms, max_delay_ms, error, id),
+    )?;
+    Ok(())
+}
+
+pub fn count_deferred_screenshots(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    conn.query_row("SELECT COUNT(*) FROM pending_screenshots WHERE attempts > 0", [], |row| row.get(0))
+}
+
+pub fn count_pending_screenshots(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    conn.query_row("SELECT COUNT(*) FROM pending_screenshots", [], |row| row.get(0))
+}
+
+/// Deletes a pending screenshot row and returns the file it pointed at, if any.
+pub fn delete_pending_screenshot(conn: &Connection, id: i64) -> Result<Option<String>, rusqlite::Error> {
+    let file_path: Option<String> = conn
+        .query_row("SELECT file_path FROM pending_screenshots WHERE id = ?1", [id], |row| row.get(0))
+        .unwrap_or(None);
+    conn.execute("DELETE FROM pending_screenshots WHERE id = ?1", [id])?;
+    Ok(file_path)
+}
+
+pub fn count_screenshots_with_file(conn: &Connection, file_path: &str) -> Result<i64, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM pending_screenshots WHERE file_path = ?1",
+        [file_path],
+        |row| row.get(0),
+    )
+}
+
+pub fn get_screenshot_file_paths(conn: &Connection) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT DISTINCT file_path FROM pending_screenshots WHERE file_path IS NOT NULL")?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+
+// Rows written before the file store keep their image inline as base64.
+
+pub fn get_inline_screenshot_ids(conn: &Connection) -> Result<Vec<i64>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT id FROM pending_screenshots WHERE image_data IS NOT NULL")?;
+    let rows = stmt.query_map([], |row| row.get(0))?;
+    rows.collect()
+}
+
+pub fn get_inline_screenshot_data(conn: &Connection, id: i64) -> Result<String, rusqlite::Error> {
+    conn.query_row(
+        "SELECT image_data FROM pending_screenshots WHERE id = ?1",
+        [id],
+        |row| row.get(0),
+    )
+}
+
+pub fn set_screenshot_file(conn: &Connection, id: i64, stored: &StoredScreenshot) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE pending_screenshots SET file_path = ?1, file_size = ?2, content_hash = ?3, image_data = NULL WHERE id = ?4",
+        (&stored.file_name, stored.size, &stored.hash, id),
+    )?;
+    Ok(())
+}
+
 
 fn api_user_from_row(row: &rusqlite::Row, conn: &Connection, uuid: String) -> Result<User, rusqlite::Error> {
-    let mut projects_stmt = conn.prepare("SELECT id, name, weekly_limit_hours, daily_limit_hours, screenshots_enabled, total_hours_this_week FROM projects")?;
+    let mut projects_stmt = conn.prepare("SELECT id, name, weekly_limit_hours, daily_limit_hours, screenshots_enabled, total_hours_this_week, chunk_policy, monitor_layout, capture_schedule, blur_level, encoding_profile FROM projects")?;
     let projects = projects_stmt.query_map([], |p_row| {
         Ok(Project {
             id: p_row.get(0)?,
@@ -529,48 +873,103 @@ fn api_user_from_row(row: &rusqlite::Row, conn: &Connection, uuid: String) -> Re
             daily_limit_hours: p_row.get(3)?,
             screenshots_enabled: p_row.get::<_, i32>(4)? != 0,
             total_hours_this_week: p_row.get(5)?,
+            chunk_policy: p_row
+                .get::<_, Option<String>>(6)?
+                .and_then(|json| serde_json::from_str(&json).ok()),
+            monitor_layout: p_row
+                .get::<_, Option<String>>(7)?
+                .and_then(|json| serde_json::from_str(&json).ok()),
+            capture_schedule: p_row
+                .get::<_, Option<String>>(8)?
+                .and_then(|json| serde_json::from_str(&json).ok()),
+            blur_level: p_row
+                .get::<_, Option<String>>(9)?
+                .and_then(|json| serde_json::from_str(&json).ok()),
+            encoding_profile: p_row
+                .get::<_, Option<String>>(10)?
+                .and_then(|json| serde_json::from_str(&json).ok()),
         })
     })?.collect::<Result<Vec<_>, _>>()?;
     
     let current_project_id: Option<String> = row.get(4).ok().filter(|s: &String| !s.is_empty());
-    let refresh_token: Option<String> = row.get(5).ok().filter(|s: &String| !s.is_empty());
+
+    // A missing entry (e.g. removed from the keyring by hand) leaves the user without
+    // tokens; the next request then fails auth and logs out cleanly.
+    let credential_ref: Option<String> = row.get(3)?;
+    let credentials = match credential_ref {
+        Some(reference) => credentials::load(&reference).map_err(credential_error)?,
+        None => None,
+    };
+    let (token, refresh_token) = match credentials {
+        Some(c) => (c.token, c.refresh_token),
+        None => (String::new(), None),
+    };
 
     Ok(User {
         uuid: uuid,
         name: row.get(1)?,
         email: row.get(2)?,
-        token: row.get(3)?,
+        token,
         refresh_token,
+        token_expires: row.get(5)?,
         current_project_id,
         projects,
     })
 }
 
+/// Takes up to `seconds` off a session's tracked time and queues it for sync again, so
+/// the server gets the new total even if it already had the session. A session that
+/// hasn't synced yet keeps its status, `recovered` included. Returns the seconds actually
+/// deducted; tracked time never goes below zero.
+pub fn deduct_session_time(conn: &Connection, session_uuid: &str, seconds: i64) -> Result<i64, rusqlite::Error> {
+    let Some(session) = get_session_by_uuid(conn, session_uuid)? else {
+        return Ok(0);
+    };
+    let tracked = tracked_seconds(
+        session.start_time,
+        session.end_time,
+        session.deducted_seconds + session.paused_seconds,
+    );
+    let deducted = seconds.clamp(0, tracked);
+    conn.execute(
+        "UPDATE sessions SET deducted_seconds = deducted_seconds + ?1, status = CASE WHEN status = 'done' THEN 'pending' ELSE status END WHERE uuid = ?2",
+        (deducted, session_uuid),
+    )?;
+    Ok(deducted)
+}
+
+/// The tracked time a pending screenshot's capture stood for, claimed once per capture:
+/// the whole group's share is zeroed, so deleting a second monitor image of the same
+/// capture deducts nothing. `None` for rows taken before captures were grouped.
+pub fn take_capture_seconds(conn: &Connection, id: i64) -> Result<Option<i64>, rusqlite::Error> {
+    let (group, seconds): (Option<String>, Option<i64>) = match conn.query_row(
+        "SELECT capture_group, capture_seconds FROM pending_screenshots WHERE id = ?1",
+        [id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ) {
+        Ok(row) => row,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(Some(0)),
+        Err(e) => return Err(e),
+    };
+    let Some(group) = group else {
+        return Ok(None);
+    };
+    conn.execute(
+        "UPDATE pending_screenshots SET capture_seconds = 0 WHERE capture_group = ?1",
+        [&group],
+    )?;
+    Ok(Some(seconds.unwrap_or(0)))
+}
+
 pub fn get_pending_sessions(conn: &Connection) -> Result<Vec<Session>, rusqlite::Error> {
-    let mut stmt = conn.prepare(
-        "SELECT id, uuid, project_id, project_type, start_time, end_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events, duration_minutes, target_name 
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}
          FROM sessions
-         WHERE status = 'pending'"
-    )?;
+         WHERE status IN ('pending', 'recovered')",
+        SESSION_COLUMNS
+    ))?;
     
-    let rows = stmt.query_map([], |row| {
-        Ok(Session {
-            id: Some(row.get(0)?),
-            uuid: row.get(1)?,
-            project_id: row.get(2)?,
-            project_type: row.get(3)?,
-            start_time: row.get(4)?,
-            end_time: row.get(5)?,
-            is_active: row.get(6)?,
-            idle_seconds: row.get(7)?,
-            deducted_seconds: row.get(8)?,
-            status: row.get(9)?,
-            keyboard_events: row.get(10)?,
-            mouse_events: row.get(11)?,
-            duration_minutes: row.get(12)?,
-            target_name: row.get(13)?,
-        })
-    })?;
+    let rows = stmt.query_map([], session_from_row)?;
 
     let mut result = Vec::new();
     for row in rows {
@@ -579,6 +978,20 @@ pub fn get_pending_sessions(conn: &Connection) -> Result<Vec<Session>, rusqlite:
     Ok(result)
 }
 
+/// Projects with tracked sessions, most recently used first.
+pub fn get_recent_project_ids(conn: &Connection, limit: usize) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT project_id
+         FROM sessions
+         WHERE project_type = 'Project'
+         GROUP BY project_id
+         ORDER BY MAX(start_time) DESC
+         LIMIT ?1"
+    )?;
+    let rows = stmt.query_map([limit as i64], |row| row.get(0))?;
+    rows.collect()
+}
+
 pub fn get_used_break_policy_ids_today(conn: &Connection) -> Result<Vec<String>, rusqlite::Error> {
     let now = Local::now();
     let start_of_day = now.date_naive().and_hms_opt(0, 0, 0).unwrap().and_local_timezone(Local).unwrap().timestamp_millis();
@@ -600,3 +1013,307 @@ pub fn get_used_break_policy_ids_today(conn: &Connection) -> Result<Vec<String>,
     Ok(result)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run(&mut conn).unwrap();
+        conn
+    }
+
+    /// A finished ten-minute session in `status`.
+    fn finished_session(conn: &Connection, uuid: &str, status: &str) {
+        conn.execute(
+            "INSERT INTO sessions (uuid, project_id, project_type, start_time, end_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events)
+             VALUES (?1, 'project-1', 'Project', 0, 600000, 0, 0, 0, ?2, 0, 0)",
+            (uuid, status),
+        )
+        .unwrap();
+    }
+
+    /// A session left running since `start_time`, last heartbeat at `end_time`.
+    fn orphaned_session(conn: &Connection, uuid: &str, start_time: i64, end_time: Option<i64>, status: &str) {
+        conn.execute(
+            "INSERT INTO sessions (uuid, project_id, project_type, start_time, end_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events)
+             VALUES (?1, 'project-1', 'Project', ?2, ?3, 1, 0, 0, ?4, 0, 0)",
+            (uuid, start_time, end_time, status),
+        )
+        .unwrap();
+    }
+
+    fn session(conn: &Connection, uuid: &str) -> Session {
+        get_session_by_uuid(conn, uuid).unwrap().unwrap()
+    }
+
+    /// Stores a pending screenshot of `file` in capture `group` and returns its id.
+    fn screenshot(conn: &Connection, file: &str, group: &str, seconds: i64) -> i64 {
+        saved(conn, file, group, seconds, false)
+    }
+
+    /// Records a marker repeating the image `file`.
+    fn marker(conn: &Connection, file: &str, group: &str) -> i64 {
+        saved(conn, file, group, 150, true)
+    }
+
+    fn saved(conn: &Connection, file: &str, group: &str, seconds: i64, unchanged: bool) -> i64 {
+        let stored = StoredScreenshot {
+            file_name: file.to_string(),
+            size: 100,
+            hash: file.to_string(),
+        };
+        let group = CaptureGroup {
+            id: group.to_string(),
+            seconds,
+        };
+        save_pending_screenshot(conn, "session-1", "project-1", &stored, &[], 0, unchanged, &CaptureContext::default(), &group).unwrap();
+        conn.last_insert_rowid()
+    }
+
+    #[test]
+    fn deducting_requeues_a_synced_session() {
+        let conn = test_db();
+        finished_session(&conn, "done", "done");
+        assert_eq!(deduct_session_time(&conn, "done", 120).unwrap(), 120);
+        let done = session(&conn, "done");
+        assert_eq!(done.status, "pending");
+        assert_eq!(done.deducted_seconds, 120);
+    }
+
+    #[test]
+    fn deducting_keeps_an_unsynced_status() {
+        let conn = test_db();
+        for status in ["pending", "recovered"] {
+            finished_session(&conn, status, status);
+            deduct_session_time(&conn, status, 120).unwrap();
+            assert_eq!(session(&conn, status).status, status);
+        }
+    }
+
+    #[test]
+    fn deducting_never_goes_below_zero() {
+        let conn = test_db();
+        finished_session(&conn, "short", "pending");
+        assert_eq!(deduct_session_time(&conn, "short", 500).unwrap(), 500);
+        assert_eq!(deduct_session_time(&conn, "short", 500).unwrap(), 100);
+        assert_eq!(deduct_session_time(&conn, "missing", 500).unwrap(), 0);
+    }
+
+    #[test]
+    fn syncing_marks_a_finished_session_done() {
+        let conn = test_db();
+        finished_session(&conn, "sent", "pending");
+        mark_session_synced(&conn, &session(&conn, "sent")).unwrap();
+        assert_eq!(session(&conn, "sent").status, "done");
+    }
+
+    #[test]
+    fn deduction_during_sync_keeps_the_session_pending() {
+        let conn = test_db();
+        finished_session(&conn, "sent", "pending");
+        let sent = session(&conn, "sent");
+
+        // Review takes time off while the request with `sent` is out
+        deduct_session_time(&conn, "sent", 120).unwrap();
+        mark_session_synced(&conn, &sent).unwrap();
+        let row = session(&conn, "sent");
+        assert_eq!(row.status, "pending");
+        assert_eq!(get_synced_times(&conn).unwrap(), vec![]);
+
+        mark_session_synced(&conn, &row).unwrap();
+        assert_eq!(session(&conn, "sent").status, "done");
+    }
+
+    #[test]
+    fn orphaned_session_ends_at_its_last_heartbeat() {
+        let conn = test_db();
+        orphaned_session(&conn, "crashed", 1_000, Some(61_000), "pending");
+        let recovered = recover_orphaned_sessions(&conn).unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].end_time, Some(61_000));
+
+        let crashed = session(&conn, "crashed");
+        assert!(!crashed.is_active);
+        assert_eq!(crashed.end_time, Some(61_000));
+        assert_eq!(crashed.status, "recovered");
+    }
+
+    #[test]
+    fn orphan_without_a_heartbeat_ends_at_its_start() {
+        let conn = test_db();
+        orphaned_session(&conn, "no-beat", 1_000, None, "pending");
+        // A clock set back while it ran
+        orphaned_session(&conn, "skewed", 1_000, Some(500), "pending");
+        recover_orphaned_sessions(&conn).unwrap();
+        assert_eq!(session(&conn, "no-beat").end_time, Some(1_000));
+        assert_eq!(session(&conn, "skewed").end_time, Some(1_000));
+    }
+
+    #[test]
+    fn recovery_leaves_finished_and_synced_sessions_alone() {
+        let conn = test_db();
+        finished_session(&conn, "finished", "pending");
+        orphaned_session(&conn, "synced", 1_000, Some(61_000), "done");
+        let recovered = recover_orphaned_sessions(&conn).unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(session(&conn, "finished").status, "pending");
+        assert_eq!(session(&conn, "finished").end_time, Some(600_000));
+        // Already on the server; only closed
+        assert_eq!(session(&conn, "synced").status, "done");
+        assert!(recover_orphaned_sessions(&conn).unwrap().is_empty());
+    }
+
+    /// Starts a project session and returns its uuid.
+    fn running_session(conn: &Connection) -> String {
+        start_session(conn, "project-1", "Project", 0, None).unwrap();
+        get_active_session(conn, "project-1").unwrap().unwrap().uuid
+    }
+
+    /// Moves the open pause of `uuid` back by `seconds`, as if it had started that long ago.
+    fn backdate_pause(conn: &Connection, uuid: &str, seconds: i64) {
+        conn.execute("UPDATE sessions SET paused_at = paused_at - ?1 WHERE uuid = ?2", (seconds * 1000, uuid)).unwrap();
+        conn.execute("UPDATE session_pauses SET start_time = start_time - ?1 WHERE session_uuid = ?2", (seconds * 1000, uuid)).unwrap();
+    }
+
+    #[test]
+    fn resuming_records_the_pause() {
+        let conn = test_db();
+        let uuid = running_session(&conn);
+        pause_session(&conn, &uuid).unwrap();
+        // Pausing again doesn't start another interval
+        pause_session(&conn, &uuid).unwrap();
+        assert!(session(&conn, &uuid).paused_at.is_some());
+        backdate_pause(&conn, &uuid, 90);
+
+        resume_session(&conn, &uuid).unwrap();
+        let resumed = session(&conn, &uuid);
+        assert!(resumed.is_active);
+        assert_eq!(resumed.paused_at, None);
+        assert_eq!(resumed.paused_seconds, 90);
+
+        let pauses = get_session_pauses(&conn, &uuid).unwrap();
+        assert_eq!(pauses.len(), 1);
+        let end = pauses[0].end_time.unwrap();
+        assert_eq!((end - pauses[0].start_time) / 1000, 90);
+        // The heartbeat carries on from the resume
+        assert_eq!(resumed.end_time, Some(end));
+    }
+
+    #[test]
+    fn paused_time_is_not_tracked() {
+        let conn = test_db();
+        finished_session(&conn, "paused", "pending");
+        conn.execute("UPDATE sessions SET paused_seconds = 120 WHERE uuid = 'paused'", []).unwrap();
+        assert_eq!(project_session_times(&conn, "project-1", 0, 600_000).unwrap(), [(480, 0)]);
+        // Deductions can only take what's left after the pause
+        assert_eq!(deduct_session_time(&conn, "paused", 1_000).unwrap(), 480);
+    }
+
+    #[test]
+    fn open_pause_is_not_tracked_while_it_lasts() {
+        let conn = test_db();
+        let uuid = running_session(&conn);
+        pause_session(&conn, &uuid).unwrap();
+        let paused_at = session(&conn, &uuid).paused_at.unwrap();
+
+        // Ten minutes tracked, then paused for five
+        conn.execute("UPDATE sessions SET start_time = ?1 WHERE uuid = ?2", (paused_at - 600_000, &uuid)).unwrap();
+        let times = project_session_times(&conn, "project-1", 0, paused_at + 300_000).unwrap();
+        assert_eq!(times, [(600, 0)]);
+    }
+
+    #[test]
+    fn recovering_a_paused_orphan_ends_its_pause_at_the_last_heartbeat() {
+        let conn = test_db();
+        orphaned_session(&conn, "paused", 0, Some(600_000), "pending");
+        conn.execute("UPDATE sessions SET paused_at = 480000 WHERE uuid = 'paused'", []).unwrap();
+        conn.execute("INSERT INTO session_pauses (session_uuid, start_time) VALUES ('paused', 480000)", []).unwrap();
+
+        recover_orphaned_sessions(&conn).unwrap();
+        let recovered = session(&conn, "paused");
+        assert_eq!(recovered.paused_at, None);
+        assert_eq!(recovered.paused_seconds, 120);
+        assert_eq!(get_session_pauses(&conn, "paused").unwrap()[0].end_time, Some(600_000));
+    }
+
+    #[test]
+    fn capture_time_is_taken_once_per_capture() {
+        let conn = test_db();
+        let left = screenshot(&conn, "left.webp", "capture-1", 150);
+        let right = screenshot(&conn, "right.webp", "capture-1", 150);
+        let next = screenshot(&conn, "next.webp", "capture-2", 120);
+
+        assert_eq!(take_capture_seconds(&conn, right).unwrap(), Some(150));
+        assert_eq!(take_capture_seconds(&conn, left).unwrap(), Some(0));
+        assert_eq!(take_capture_seconds(&conn, next).unwrap(), Some(120));
+    }
+
+    #[test]
+    fn ungrouped_screenshot_has_no_capture_time() {
+        let conn = test_db();
+        let id = screenshot(&conn, "old.webp", "capture-1", 150);
+        conn.execute("UPDATE pending_screenshots SET capture_group = NULL, capture_seconds = NULL WHERE id = ?1", [id])
+            .unwrap();
+        assert_eq!(take_capture_seconds(&conn, id).unwrap(), None);
+    }
+
+    #[test]
+    fn claimed_screenshot_is_neither_uploaded_nor_claimed_again() {
+        let conn = test_db();
+        let id = screenshot(&conn, "a.webp", "capture-1", 150);
+        assert!(claim_screenshot(&conn, id).unwrap());
+        assert!(!claim_screenshot(&conn, id).unwrap());
+        assert!(get_uploadable_screenshots(&conn, i64::MAX, i64::MAX).unwrap().is_empty());
+
+        // A failed upload gives it back for a later pass
+        defer_screenshot(&conn, id, "Status 500", 0, 1000).unwrap();
+        assert!(claim_screenshot(&conn, id).unwrap());
+        release_screenshot_claims(&conn).unwrap();
+        assert_eq!(get_uploadable_screenshots(&conn, i64::MAX, i64::MAX).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn deleted_screenshot_cannot_be_claimed() {
+        let conn = test_db();
+        let id = screenshot(&conn, "a.webp", "capture-1", 150);
+        delete_pending_screenshot(&conn, id).unwrap();
+        assert!(!claim_screenshot(&conn, id).unwrap());
+    }
+
+    fn uploadable(conn: &Connection) -> Vec<i64> {
+        get_uploadable_screenshots(conn, i64::MAX, i64::MAX)
+            .unwrap()
+            .iter()
+            .map(|s| s.id)
+            .collect()
+    }
+
+    #[test]
+    fn marker_waits_for_the_image_it_repeats() {
+        let conn = test_db();
+        let original = screenshot(&conn, "a.webp", "capture-1", 150);
+        let repeat = marker(&conn, "a.webp", "capture-2");
+        let other = screenshot(&conn, "b.webp", "capture-3", 150);
+        assert_eq!(uploadable(&conn), vec![original, other]);
+
+        delete_pending_screenshot(&conn, original).unwrap();
+        assert_eq!(uploadable(&conn), vec![repeat, other]);
+    }
+
+    #[test]
+    fn deleting_a_repeated_image_drops_its_markers() {
+        let conn = test_db();
+        let original = screenshot(&conn, "a.webp", "capture-1", 150);
+        marker(&conn, "a.webp", "capture-2");
+        marker(&conn, "a.webp", "capture-3");
+        let other = screenshot(&conn, "b.webp", "capture-4", 150);
+
+        delete_pending_screenshot(&conn, original).unwrap();
+        assert_eq!(delete_unchanged_markers(&conn, "a.webp").unwrap(), 2);
+        let uploadable = get_uploadable_screenshots(&conn, i64::MAX, i64::MAX).unwrap();
+        assert!(uploadable.iter().all(|s| s.content_hash != "a.webp"));
+        assert_eq!(uploadable.iter().map(|s| s.id).collect::<Vec<_>>(), vec![other]);
+        assert_eq!(count_screenshots_with_file(&conn, "a.webp").unwrap(), 0);
+    }
+}
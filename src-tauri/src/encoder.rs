@@ -0,0 +1,238 @@
+use crate::db;
+use crate::error::WatchtowerError;
+use crate::models::{Codec, EncodingPreset, EncodingProfile, Project};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageEncoder, RgbaImage};
+use rusqlite::Connection;
+
+// Screenshot Encoding
+//
+// A profile picks a preset (size limit, resampling filter, default codec and quality)
+// and optionally another codec. The organization can set one per project; otherwise the
+// user's choice for that project applies, and Standard (800x600 WebP) by default.
+
+/// Local profile for one project (JSON), keyed by project id.
+const PROFILE_SETTING_PREFIX: &str = "encoding_profile:";
+
+pub struct Settings {
+    /// Per monitor; a composite gets one width per monitor.
+    pub max_width: u32,
+    pub max_height: u32,
+    pub filter: FilterType,
+    pub codec: Codec,
+    /// For the lossy codecs, 0 to 100.
+    pub quality: u8,
+}
+
+pub fn settings(profile: &EncodingProfile) -> Settings {
+    let (max_width, max_height, filter, codec, quality) = match profile.preset {
+        // Enough to see which app is open, small enough for metered connections
+        EncodingPreset::Thumbnail => (480, 300, FilterType::Triangle, Codec::Jpeg, 60),
+        EncodingPreset::Standard => (800, 600, FilterType::Lanczos3, Codec::Webp, 75),
+        // Text stays readable on large and HiDPI screens
+        EncodingPreset::HighDetail => (2560, 1600, FilterType::CatmullRom, Codec::Webp, 90),
+    };
+    Settings {
+        max_width,
+        max_height,
+        filter,
+        codec: profile.codec.unwrap_or(codec),
+        quality,
+    }
+}
+
+fn setting_key(project_id: &str) -> String {
+    format!("{}{}", PROFILE_SETTING_PREFIX, project_id)
+}
+
+pub fn encoding_profile(
+    conn: &Connection,
+    project: Option<&Project>,
+    project_id: &str,
+) -> EncodingProfile {
+    project
+        .and_then(|p| p.encoding_profile)
+        .or_else(|| {
+            db::get_setting(conn, &setting_key(project_id))
+                .ok()
+                .flatten()
+                .and_then(|json| serde_json::from_str(&json).ok())
+        })
+        .unwrap_or_default()
+}
+
+/// Stores the user's profile for a project, or clears it with `None`.
+pub fn set_local_profile(
+    conn: &Connection,
+    project_id: &str,
+    profile: Option<EncodingProfile>,
+) -> Result<(), WatchtowerError> {
+    match profile {
+        Some(profile) => {
+            let json = serde_json::to_string(&profile)
+                .map_err(|e| WatchtowerError::invalid_input(e.to_string()))?;
+            db::set_setting(conn, &setting_key(project_id), &json)?;
+        }
+        None => db::delete_setting(conn, &setting_key(project_id))?,
+    }
+    Ok(())
+}
+
+/// Scales `image` down to fit `max_width` x `max_height`, keeping its aspect ratio.
+/// Images that already fit are left alone.
+pub fn fit(image: RgbaImage, max_width: u32, max_height: u32, filter: FilterType) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    if width <= max_width && height <= max_height {
+        return image;
+    }
+    let scale = (max_width as f64 / width as f64).min(max_height as f64 / height as f64);
+    let new_width = ((width as f64 * scale).round() as u32).max(1);
+    let new_height = ((height as f64 * scale).round() as u32).max(1);
+    imageops::resize(&image, new_width, new_height, filter)
+}
+
+pub fn encode(image: &RgbaImage, settings: &Settings) -> Result<Vec<u8>, WatchtowerError> {
+    let (width, height) = image.dimensions();
+    match settings.codec {
+        Codec::Webp => Ok(webp::Encoder::from_rgba(image.as_raw(), width, height)
+            .encode(settings.quality as f32)
+            .to_vec()),
+        Codec::WebpLossless => Ok(webp::Encoder::from_rgba(image.as_raw(), width, height)
+            .encode_lossless()
+            .to_vec()),
+        Codec::Jpeg => {
+            // JPEG has no alpha channel; screen captures are opaque anyway
+            let rgb = DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+            let mut bytes = Vec::new();
+            JpegEncoder::new_with_quality(&mut bytes, settings.quality)
+                .encode_image(&rgb)
+                .map_err(|e| WatchtowerError::capture(e.to_string()))?;
+            Ok(bytes)
+        }
+        Codec::Png => {
+            let mut bytes = Vec::new();
+            PngEncoder::new(&mut bytes)
+                .write_image(
+                    image.as_raw(),
+                    width,
+                    height,
+                    image::ExtendedColorType::Rgba8,
+                )
+                .map_err(|e| WatchtowerError::capture(e.to_string()))?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// MIME type for a stored file's extension; files from older releases are all WebP.
+pub fn mime_type(extension: &str) -> &'static str {
+    match extension {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        _ => "image/webp",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+
+    fn project(id: &str, encoding_profile: Option<EncodingProfile>) -> Project {
+        Project {
+            id: id.to_string(),
+            name: id.to_string(),
+            weekly_limit_hours: None,
+            daily_limit_hours: None,
+            screenshots_enabled: true,
+            total_hours_this_week: None,
+            chunk_policy: None,
+            monitor_layout: None,
+            capture_schedule: None,
+            blur_level: None,
+            encoding_profile,
+        }
+    }
+
+    fn profile(preset: EncodingPreset, codec: Option<Codec>) -> EncodingProfile {
+        EncodingProfile { preset, codec }
+    }
+
+    #[test]
+    fn profile_is_chosen_per_project() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run(&mut conn).unwrap();
+        let local = profile(EncodingPreset::Thumbnail, None);
+        let org = profile(EncodingPreset::HighDetail, Some(Codec::Png));
+        set_local_profile(&conn, "p1", Some(local)).unwrap();
+
+        let p1 = project("p1", None);
+        assert_eq!(encoding_profile(&conn, Some(&p1), "p1"), local);
+        // Another project doesn't pick up p1's choice
+        let p2 = project("p2", None);
+        assert_eq!(
+            encoding_profile(&conn, Some(&p2), "p2"),
+            EncodingProfile::default()
+        );
+        // The organization's profile wins over the user's
+        let p1 = project("p1", Some(org));
+        assert_eq!(encoding_profile(&conn, Some(&p1), "p1"), org);
+        // Projects not synced yet still get the user's choice
+        assert_eq!(encoding_profile(&conn, None, "p1"), local);
+
+        set_local_profile(&conn, "p1", None).unwrap();
+        assert_eq!(
+            encoding_profile(&conn, None, "p1"),
+            EncodingProfile::default()
+        );
+    }
+
+    #[test]
+    fn default_profile_is_standard_webp() {
+        let settings = settings(&EncodingProfile::default());
+        assert_eq!((settings.max_width, settings.max_height), (800, 600));
+        assert_eq!(settings.codec, Codec::Webp);
+    }
+
+    #[test]
+    fn codec_replaces_the_presets() {
+        let settings = settings(&profile(EncodingPreset::Thumbnail, Some(Codec::Png)));
+        assert_eq!((settings.max_width, settings.max_height), (480, 300));
+        assert_eq!(settings.codec, Codec::Png);
+    }
+
+    #[test]
+    fn unknown_codec_falls_back_to_the_presets() {
+        let json = r#"{"id":"p1","name":"P1","screenshotsEnabled":true,
+            "encodingProfile":{"preset":"thumbnail","codec":"avif"}}"#;
+        let project: Project = serde_json::from_str(json).unwrap();
+        let profile = project.encoding_profile.unwrap();
+        assert_eq!(profile, self::profile(EncodingPreset::Thumbnail, None));
+        assert_eq!(settings(&profile).codec, Codec::Jpeg);
+
+        // The same goes for a local choice saved by a newer release
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run(&mut conn).unwrap();
+        let json = r#"{"preset":"highDetail","codec":"avif"}"#;
+        db::set_setting(&conn, &setting_key("p1"), json).unwrap();
+        let profile = encoding_profile(&conn, None, "p1");
+        assert_eq!(profile, self::profile(EncodingPreset::HighDetail, None));
+    }
+
+    #[test]
+    fn every_stored_extension_has_its_mime_type() {
+        let expected = [
+            (Codec::Webp, "image/webp"),
+            (Codec::WebpLossless, "image/webp"),
+            (Codec::Jpeg, "image/jpeg"),
+            (Codec::Png, "image/png"),
+        ];
+        for (codec, mime) in expected {
+            assert_eq!(mime_type(codec.extension()), mime, "{:?}", codec);
+        }
+        // Files stored before the codec was configurable
+        assert_eq!(mime_type("webp"), "image/webp");
+    }
+}
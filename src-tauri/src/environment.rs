@@ -0,0 +1,241 @@
+use crate::error::WatchtowerError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// API Environment
+//
+// Which backend the app talks to. Resolved once at startup, in order of precedence:
+//   1. `WATCHTOWER_API_ENV` / `WATCHTOWER_API_URL` in the process environment
+//   2. `environment.json` in the app config dir (written by `set_api_environment`)
+//   3. The compile-time default (same variables, read via `option_env!` at build time)
+//
+// Presets: `staging` is the backend the app has always shipped with, `local` is a dev
+// server on port 3000, and `production` only exists in builds made with
+// `WATCHTOWER_PRODUCTION_API_URL` set.
+//
+// Each environment keeps its own database and screenshot store so sessions recorded
+// against one backend are never uploaded to another.
+
+pub const ENV_NAME_VAR: &str = "WATCHTOWER_API_ENV";
+pub const ENV_URL_VAR: &str = "WATCHTOWER_API_URL";
+const CONFIG_FILE: &str = "environment.json";
+
+/// Production has no host of its own until a build supplies one.
+const PRODUCTION_URL_VAR: &str = "WATCHTOWER_PRODUCTION_API_URL";
+const PRODUCTION_URL: Option<&str> = option_env!("WATCHTOWER_PRODUCTION_API_URL");
+const STAGING_URL: &str = "https://watchtower.staging-api.nykon.cloud/v1";
+const LOCAL_URL: &str = "http://localhost:3000/v1";
+
+/// Every release before environments existed was hard-wired to staging.
+const LEGACY_ENVIRONMENT: &str = "staging";
+const ENVIRONMENTS_DIR: &str = "environments";
+const DB_FILE: &str = "auth_v2.db";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiEnvironment {
+    pub name: String,
+    /// Includes the version prefix, e.g. `https://host/v1`.
+    pub base_url: String,
+}
+
+fn preset_url(name: &str) -> Option<&'static str> {
+    match name {
+        "production" => PRODUCTION_URL,
+        "staging" => Some(STAGING_URL),
+        "local" => Some(LOCAL_URL),
+        _ => None,
+    }
+}
+
+impl ApiEnvironment {
+    /// Builds an environment from a preset name, or any name plus an explicit URL.
+    /// The name doubles as a directory name, so it is kept to `[a-z0-9_-]`.
+    pub fn new(name: &str, base_url: Option<&str>) -> Result<Self, WatchtowerError> {
+        let name = name.trim().to_lowercase();
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(WatchtowerError::invalid_input(format!(
+                "Invalid environment name: {:?}",
+                name
+            )));
+        }
+
+        let base_url = match base_url.map(str::trim).filter(|u| !u.is_empty()) {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => preset_url(&name)
+                .ok_or_else(|| {
+                    WatchtowerError::invalid_input(format!(
+                        "No base URL for environment {:?}; pass one (production needs {} at build time)",
+                        name, PRODUCTION_URL_VAR
+                    ))
+                })?
+                .to_string(),
+        };
+        if !base_url.starts_with("https://") && !base_url.starts_with("http://") {
+            return Err(WatchtowerError::invalid_input(format!(
+                "Base URL must start with http:// or https://: {}",
+                base_url
+            )));
+        }
+
+        Ok(Self { name, base_url })
+    }
+
+    /// Where this environment's database and screenshots live.
+    pub fn data_dir(&self, app_data_dir: &Path) -> PathBuf {
+        app_data_dir.join(ENVIRONMENTS_DIR).join(&self.name)
+    }
+
+    pub fn db_path(&self, app_data_dir: &Path) -> PathBuf {
+        self.data_dir(app_data_dir).join(DB_FILE)
+    }
+}
+
+/// The databases of every environment that has been used on this machine.
+pub fn db_paths(app_data_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(app_data_dir.join(ENVIRONMENTS_DIR)) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join(DB_FILE))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+pub fn compiled_default() -> ApiEnvironment {
+    let name = option_env!("WATCHTOWER_API_ENV").unwrap_or(LEGACY_ENVIRONMENT);
+    ApiEnvironment::new(name, option_env!("WATCHTOWER_API_URL")).unwrap_or_else(|e| {
+        eprintln!("Env: Bad compile-time environment ({}), using staging", e);
+        ApiEnvironment::new(LEGACY_ENVIRONMENT, None).unwrap()
+    })
+}
+
+/// Whether the process environment pins the backend, making saved choices inert.
+pub fn is_pinned_by_env_var() -> bool {
+    std::env::var(ENV_NAME_VAR).is_ok() || std::env::var(ENV_URL_VAR).is_ok()
+}
+
+pub fn resolve(config_dir: &Path) -> ApiEnvironment {
+    if is_pinned_by_env_var() {
+        // A bare URL is treated as a custom environment named "custom"
+        let name = std::env::var(ENV_NAME_VAR).unwrap_or_else(|_| "custom".to_string());
+        let url = std::env::var(ENV_URL_VAR).ok();
+        match ApiEnvironment::new(&name, url.as_deref()) {
+            Ok(env) => return env,
+            Err(e) => eprintln!("Env: Ignoring {}/{}: {}", ENV_NAME_VAR, ENV_URL_VAR, e),
+        }
+    }
+
+    match load(config_dir) {
+        Ok(Some(env)) => env,
+        Ok(None) => compiled_default(),
+        Err(e) => {
+            eprintln!("Env: Ignoring {}: {}", CONFIG_FILE, e);
+            compiled_default()
+        }
+    }
+}
+
+fn load(config_dir: &Path) -> Result<Option<ApiEnvironment>, WatchtowerError> {
+    let path = config_dir.join(CONFIG_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let saved: ApiEnvironment = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|e| WatchtowerError::invalid_input(e.to_string()))?;
+    // Re-validate; the file may have been edited by hand
+    ApiEnvironment::new(&saved.name, Some(&saved.base_url)).map(Some)
+}
+
+pub fn save(config_dir: &Path, env: &ApiEnvironment) -> Result<(), WatchtowerError> {
+    fs::create_dir_all(config_dir)?;
+    let json = serde_json::to_vec_pretty(env)
+        .map_err(|e| WatchtowerError::invalid_input(e.to_string()))?;
+    fs::write(config_dir.join(CONFIG_FILE), json)?;
+    Ok(())
+}
+
+/// One-time move of the database and screenshot store from the app data dir root
+/// (where older releases kept them) into the staging environment's directory.
+pub fn adopt_legacy_data(app_data_dir: &Path) -> Result<(), WatchtowerError> {
+    let legacy_db = app_data_dir.join(DB_FILE);
+    if !legacy_db.exists() {
+        return Ok(());
+    }
+
+    let legacy = ApiEnvironment::new(LEGACY_ENVIRONMENT, None)?;
+    let target_dir = legacy.data_dir(app_data_dir);
+    if target_dir.join(DB_FILE).exists() {
+        eprintln!(
+            "Env: Legacy database left in place, {} already has one",
+            legacy.name
+        );
+        return Ok(());
+    }
+
+    fs::create_dir_all(&target_dir)?;
+    let legacy_screenshots = app_data_dir.join("screenshots");
+    if legacy_screenshots.exists() {
+        fs::rename(&legacy_screenshots, target_dir.join("screenshots"))?;
+    }
+    // Database last: if anything above fails, the next launch simply tries again
+    fs::rename(&legacy_db, target_dir.join(DB_FILE))?;
+    println!("Env: Moved existing data into {}", target_dir.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(name: &str) -> Option<String> {
+        ApiEnvironment::new(name, None).ok().map(|env| env.base_url)
+    }
+
+    #[test]
+    fn presets_resolve_to_their_backends() {
+        assert_eq!(
+            url("staging").as_deref(),
+            Some("https://watchtower.staging-api.nykon.cloud/v1")
+        );
+        assert_eq!(url("local").as_deref(), Some("http://localhost:3000/v1"));
+        // Only builds that name a production backend have the preset
+        assert_eq!(
+            url("production").as_deref(),
+            option_env!("WATCHTOWER_PRODUCTION_API_URL")
+        );
+        assert_eq!(url("custom"), None);
+    }
+
+    #[test]
+    fn explicit_url_overrides_the_preset() {
+        let env = ApiEnvironment::new(" Staging ", Some("https://example.test/v2/")).unwrap();
+        assert_eq!(env.name, "staging");
+        assert_eq!(env.base_url, "https://example.test/v2");
+    }
+
+    #[test]
+    fn db_paths_lists_environments_with_a_database() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(db_paths(dir.path()).is_empty());
+
+        let staging = ApiEnvironment::new("staging", None).unwrap();
+        let local = ApiEnvironment::new("local", None).unwrap();
+        fs::create_dir_all(staging.data_dir(dir.path())).unwrap();
+        fs::write(staging.db_path(dir.path()), b"").unwrap();
+        fs::create_dir_all(local.data_dir(dir.path())).unwrap();
+        assert_eq!(db_paths(dir.path()), vec![staging.db_path(dir.path())]);
+    }
+
+    #[test]
+    fn legacy_data_belongs_to_staging() {
+        let env = ApiEnvironment::new(LEGACY_ENVIRONMENT, None).unwrap();
+        assert_eq!(env.base_url, STAGING_URL);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::migrations::MigrationError;
+use serde::Serialize;
+use std::fmt;
+
+// Error type shared by the api, db and command layers. It serializes as
+// `{ "kind": "...", ...fields }` so the frontend can branch on `kind` instead of
+// matching message text.
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LimitKind {
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum WatchtowerError {
+    #[serde(rename_all = "camelCase")]
+    LimitReached {
+        project_id: String,
+        limit: LimitKind,
+        limit_hours: f64,
+        tracked_hours: f64,
+    },
+    /// The session could not be refreshed; the user has been logged out.
+    #[serde(rename_all = "camelCase")]
+    AuthExpired {
+        reason: String,
+    },
+    NotAuthenticated,
+    /// The API environment was switched while the operation was in progress.
+    EnvironmentChanged,
+    #[serde(rename_all = "camelCase")]
+    Network {
+        message: String,
+        status: Option<u16>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Database {
+        message: String,
+    },
+    /// An OS permission (e.g. `accessibility`, `screenRecording`) has not been granted.
+    #[serde(rename_all = "camelCase")]
+    PermissionDenied {
+        permission: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Capture {
+        message: String,
+    },
+    /// Reading or writing local files (e.g. the screenshot store) failed.
+    #[serde(rename_all = "camelCase")]
+    Storage {
+        message: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    InvalidInput {
+        message: String,
+    },
+    /// Runtime failures that fit nowhere else (e.g. a background task panicked).
+    #[serde(rename_all = "camelCase")]
+    Internal {
+        message: String,
+    },
+}
+
+impl WatchtowerError {
+    pub fn network(message: impl Into<String>) -> Self {
+        WatchtowerError::Network {
+            message: message.into(),
+            status: None,
+        }
+    }
+
+    pub fn capture(message: impl Into<String>) -> Self {
+        WatchtowerError::Capture {
+            message: message.into(),
+        }
+    }
+
+    pub fn storage(message: impl Into<String>) -> Self {
+        WatchtowerError::Storage {
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        WatchtowerError::InvalidInput {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for WatchtowerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchtowerError::LimitReached { limit, .. } => match limit {
+                LimitKind::Daily => write!(f, "Daily limit reached for this project"),
+                LimitKind::Weekly => write!(f, "Weekly limit reached for this project"),
+            },
+            WatchtowerError::AuthExpired { reason } => write!(f, "Session expired: {}", reason),
+            WatchtowerError::NotAuthenticated => write!(f, "Not signed in"),
+            WatchtowerError::EnvironmentChanged => write!(f, "API environment changed"),
+            WatchtowerError::Network { message, status } => match status {
+                Some(code) => write!(f, "Request failed with status {}: {}", code, message),
+                None => write!(f, "Network error: {}", message),
+            },
+            WatchtowerError::Database { message } => write!(f, "Database error: {}", message),
+            WatchtowerError::PermissionDenied { permission } => {
+                write!(f, "Permission not granted: {}", permission)
+            }
+            WatchtowerError::Capture { message } => write!(f, "Capture failed: {}", message),
+            WatchtowerError::Storage { message } => write!(f, "Storage error: {}", message),
+            WatchtowerError::InvalidInput { message } => write!(f, "{}", message),
+            WatchtowerError::Internal { message } => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for WatchtowerError {}
+
+impl From<rusqlite::Error> for WatchtowerError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            // Credential store errors raised inside `db` (see `db::credential_error`)
+            rusqlite::Error::ToSqlConversionFailure(inner) => {
+                match inner.downcast::<WatchtowerError>() {
+                    Ok(e) => *e,
+                    Err(other) => WatchtowerError::Database {
+                        message: other.to_string(),
+                    },
+                }
+            }
+            e => WatchtowerError::Database {
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+impl From<MigrationError> for WatchtowerError {
+    fn from(e: MigrationError) -> Self {
+        WatchtowerError::Database {
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<reqwest::Error> for WatchtowerError {
+    fn from(e: reqwest::Error) -> Self {
+        WatchtowerError::Network {
+            message: e.to_string(),
+            status: e.status().map(|s| s.as_u16()),
+        }
+    }
+}
+
+impl From<std::io::Error> for WatchtowerError {
+    fn from(e: std::io::Error) -> Self {
+        WatchtowerError::storage(e.to_string())
+    }
+}
+
+impl From<tauri::Error> for WatchtowerError {
+    fn from(e: tauri::Error) -> Self {
+        WatchtowerError::Internal {
+            message: e.to_string(),
+        }
+    }
+}
@@ -9,6 +9,9 @@ pub struct IdleState {
     pub is_monitoring: AtomicBool,
     pub keyboard_count: AtomicU64,
     pub mouse_count: AtomicU64,
+    /// Like the counts above, but reset by each screenshot instead of each session.
+    pub capture_keyboard_count: AtomicU64,
+    pub capture_mouse_count: AtomicU64,
 
     pub is_capture_loop_running: AtomicBool,
     pub is_activity_loop_running: AtomicBool,
@@ -25,11 +28,46 @@ impl IdleState {
             is_monitoring: AtomicBool::new(false),
             keyboard_count: AtomicU64::new(0),
             mouse_count: AtomicU64::new(0),
+            capture_keyboard_count: AtomicU64::new(0),
+            capture_mouse_count: AtomicU64::new(0),
 
             is_capture_loop_running: AtomicBool::new(false),
             is_activity_loop_running: AtomicBool::new(false),
         }
     }
+
+    pub fn count_keyboard(&self) {
+        self.keyboard_count.fetch_add(1, Ordering::Relaxed);
+        self.capture_keyboard_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_mouse(&self) {
+        self.mouse_count.fetch_add(1, Ordering::Relaxed);
+        self.capture_mouse_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Keyboard and mouse events since the last stored screenshot. Reading doesn't reset
+    /// them, so a capture that fails or is skipped loses none.
+    pub fn capture_counts(&self) -> (i64, i64) {
+        (
+            self.capture_keyboard_count.load(Ordering::Relaxed) as i64,
+            self.capture_mouse_count.load(Ordering::Relaxed) as i64,
+        )
+    }
+
+    /// Takes the counts a stored screenshot carries off the totals. Events counted since
+    /// they were read stay for the next screenshot.
+    pub fn commit_capture_counts(&self, keyboard: i64, mouse: i64) {
+        self.capture_keyboard_count
+            .fetch_sub(keyboard as u64, Ordering::Relaxed);
+        self.capture_mouse_count
+            .fetch_sub(mouse as u64, Ordering::Relaxed);
+    }
+
+    pub fn reset_capture_counts(&self) {
+        self.capture_keyboard_count.store(0, Ordering::Relaxed);
+        self.capture_mouse_count.store(0, Ordering::Relaxed);
+    }
 }
 
 pub fn start_idle_check<R: Runtime>(app: AppHandle<R>, state: Arc<IdleState>) {
@@ -83,23 +121,23 @@ fn run_os_listener<R: Runtime>(app: AppHandle<R>, state: Arc<IdleState>) {
             // Simple counting since we have event type
             match type_ {
                 CGEventType::KeyDown => {
-                    state.keyboard_count.fetch_add(1, Ordering::Relaxed);
+                    state.count_keyboard();
                 }
 
                 CGEventType::LeftMouseDown => {
-                    state.mouse_count.fetch_add(1, Ordering::Relaxed);
+                    state.count_mouse();
                 }
 
                 CGEventType::RightMouseDown => {
-                    state.mouse_count.fetch_add(1, Ordering::Relaxed);
+                    state.count_mouse();
                 }
 
                 CGEventType::OtherMouseDown => {
-                    state.mouse_count.fetch_add(1, Ordering::Relaxed);
+                    state.count_mouse();
                 }
 
                 CGEventType::ScrollWheel => {
-                    state.mouse_count.fetch_add(1, Ordering::Relaxed);
+                    state.count_mouse();
                 }
 
                 CGEventType::MouseMoved => {
@@ -113,7 +151,7 @@ fn run_os_listener<R: Runtime>(app: AppHandle<R>, state: Arc<IdleState>) {
 
                     // Ignore micro jitter, count only real movement
                     if dx + dy >= 20 {
-                        state.mouse_count.fetch_add(1, Ordering::Relaxed);
+                        state.count_mouse();
                     }
                 }
 
@@ -177,7 +215,7 @@ fn run_os_listener<R: Runtime>(app: AppHandle<R>, state: Arc<IdleState>) {
                     // or split it evenly? Let's just do mouse_count to be safe/lazy or maybe both?
                     // Better: just increment mouse_count.
                     println!("Activity event fired (Windows)");
-                    state.mouse_count.fetch_add(1, Ordering::Relaxed);
+                    state.count_mouse();
 
                     let now = SystemTime::now()
                         .duration_since(UNIX_EPOCH)
@@ -229,7 +267,7 @@ fn run_os_listener<R: Runtime>(app: AppHandle<R>, state: Arc<IdleState>) {
             if idle_ms < last_idle_ms && last_idle_ms > 1000 {
                 // Activity!
                 println!("Activity event fired (Linux)");
-                state.mouse_count.fetch_add(1, Ordering::Relaxed);
+                state.count_mouse();
 
                 let now = SystemTime::now()
                     .duration_since(UNIX_EPOCH)
@@ -1,41 +1,73 @@
-use chrono::{Local, Timelike};
 use rusqlite::Connection;
 use serde::Serialize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 #[cfg(target_os = "macos")]
 use std::process::Command;
 use tauri::{
-    menu::{Menu, MenuItem},
+    menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu},
     tray::TrayIconBuilder,
     AppHandle, Emitter, Listener, Manager, Runtime,
 };
 mod activity;
 mod api;
+mod auth;
+mod capture_schedule;
+mod credentials;
 mod db;
+mod encoder;
+mod environment;
+mod error;
 mod idle;
+mod migrations;
 mod models;
+mod project_sync;
+mod redaction;
 mod screenshot;
+mod screenshot_store;
+mod timer;
 mod tray_generator;
+mod upload_queue;
+mod week;
 
 // Use relevant types from the plugin or underlying crates if needed
 // but for commands we can just call them if they are re-exported.
 
 // ...
 
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
 // use std::time::Instant;
 
 use idle::IdleState;
-
-use models::User;
+use screenshot_store::LastStored;
+use timer::TimerState;
+use upload_queue::{UploadQueue, UploadQueueStatus};
+
+use environment::ApiEnvironment;
+use error::WatchtowerError;
+use models::{
+    CaptureSchedule, ChunkPolicy, EncodingProfile, PendingScreenshot, PrivacySettings, Project,
+    ScreenshotPreview, User,
+};
 // we don't need `Project` in lib.rs anymore unless we use it explicitly, but it's part of User.
 
 pub struct AppState {
     pub db_path: Mutex<PathBuf>,
+    /// Lock before `db_path` when both are needed; see `api::environment_and_db_path`.
+    pub environment: Mutex<ApiEnvironment>,
     pub idle_state: Arc<IdleState>,
     pub client: reqwest::Client,
+    /// Held while refreshing the access token so concurrent requests share one refresh.
+    pub token_refresh: tokio::sync::Mutex<()>,
     pub current_idle_time: Mutex<Option<u64>>,
+    pub recovered_sessions: Mutex<Vec<RecoveredSessionPayload>>,
+    pub upload_queue: Arc<UploadQueue>,
+    pub timer_state: Mutex<TimerState>,
+    /// Last stored screenshot per set of monitors, for spotting unchanged screens.
+    pub last_stored: Mutex<HashMap<String, LastStored>>,
 }
 
 #[derive(Serialize, Clone)]
@@ -47,110 +79,139 @@ struct TimeUpdatePayload {
     target_name: Option<String>,
 }
 
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveredSessionPayload {
+    uuid: String,
+    project_id: String,
+    project_type: String,
+    start_time: i64,
+    end_time: Option<i64>,
+}
+
 #[tauri::command]
 fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
 #[tauri::command]
-fn login(app: AppHandle, user: User) -> Result<(), String> {
-    let state = app.state::<AppState>();
-    let mut conn = Connection::open(&*state.db_path.lock().unwrap()).map_err(|e| e.to_string())?;
-
-    db::save_user(&mut conn, &user).map_err(|e| e.to_string())?;
+async fn login(app: AppHandle, email: String, password: String) -> Result<User, WatchtowerError> {
+    let user = auth::login(&app, &email, &password).await?;
 
     update_tray(&app, true, &user.email);
     // Sync Daily Sessions from server
     screenshot::sync_daily_sessions(&app);
-    Ok(())
+    Ok(user)
+}
+
+#[tauri::command]
+async fn refresh_projects(app: AppHandle) -> Result<Vec<Project>, WatchtowerError> {
+    let projects = auth::refresh_projects(&app).await?;
+    refresh_tray(&app);
+    Ok(projects)
 }
 
 #[tauri::command]
-fn set_current_project(app: AppHandle, project_id: String) -> Result<(), String> {
+async fn get_work_break_policies(
+    app: AppHandle,
+    project_id: String,
+) -> Result<serde_json::Value, WatchtowerError> {
+    let endpoint = format!("/desktop/projects/{}/work-break-policy", project_id);
+    let response = api::request::<_, ()>(&app, reqwest::Method::GET, &endpoint, None)
+        .await?
+        .error_for_status()?;
+    Ok(response.json().await?)
+}
+
+/// Selects a project. A running timer moves along with it (after the new project's limits
+/// are checked) without stopping the idle and capture loops; a paused session is ended;
+/// a break keeps running.
+#[tauri::command]
+fn set_current_project(app: AppHandle, project_id: String) -> Result<(), WatchtowerError> {
     let state = app.state::<AppState>();
-    let conn = Connection::open(&*state.db_path.lock().unwrap()).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+
+    let user = db::get_user(&conn)?.ok_or(WatchtowerError::NotAuthenticated)?;
+    if !user.projects.iter().any(|p| p.id == project_id) {
+        return Err(WatchtowerError::invalid_input(format!(
+            "Unknown project: {}",
+            project_id
+        )));
+    }
+    if user.current_project_id.as_deref() == Some(project_id.as_str()) {
+        return Ok(());
+    }
 
-    db::set_current_project(&conn, &project_id).map_err(|e| e.to_string())?;
+    let running = match &user.current_project_id {
+        Some(current) => db::get_active_session(&conn, current)?,
+        None => None,
+    };
+    match running {
+        Some(session) if session.paused_at.is_none() => {
+            check_can_track(&conn, &user, &project_id)?;
+            db::switch_running_project(&conn, &project_id)?;
+
+            // New session on the new project; the loops keep going
+            state.idle_state.keyboard_count.store(0, Ordering::Relaxed);
+            state.idle_state.mouse_count.store(0, Ordering::Relaxed);
+        }
+        Some(_) => {
+            db::stop_all_active_sessions(&conn)?;
+            db::set_current_project(&conn, &project_id)?;
+            let _ = app.emit("timer-active", false);
+        }
+        None => db::set_current_project(&conn, &project_id)?,
+    }
 
+    update_tray(&app, true, &user.email);
+    let _ = app.emit("project-changed", &project_id);
     Ok(())
 }
 
 #[tauri::command]
-fn logout(app: AppHandle) -> Result<(), String> {
-    let state = app.state::<AppState>();
-    let conn = Connection::open(&*state.db_path.lock().unwrap()).map_err(|e| e.to_string())?;
-
-    db::clear_user(&conn).map_err(|e| e.to_string())?;
+async fn logout(app: AppHandle) -> Result<(), WatchtowerError> {
+    auth::logout(&app).await?;
 
     update_tray(&app, false, "");
     Ok(())
 }
 
 #[tauri::command]
-fn check_auth(app: AppHandle) -> Result<Option<User>, String> {
+fn check_auth(app: AppHandle) -> Result<Option<User>, WatchtowerError> {
     let state = app.state::<AppState>();
-    let conn = Connection::open(&*state.db_path.lock().unwrap()).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
 
-    db::get_user(&conn).map_err(|e| e.to_string())
+    Ok(db::get_user(&conn)?)
 }
 
 #[tauri::command]
-fn start_timer(app: AppHandle) -> Result<(), String> {
+fn start_timer(app: AppHandle) -> Result<(), WatchtowerError> {
     start_timer_internal(&app)
 }
 
-fn start_timer_internal(app: &AppHandle) -> Result<(), String> {
+fn start_timer_internal(app: &AppHandle) -> Result<(), WatchtowerError> {
     let state = app.state::<AppState>();
-    let conn = Connection::open(&*state.db_path.lock().unwrap()).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
 
-    let user_opt = db::get_user(&conn).map_err(|e| e.to_string())?;
+    let user_opt = db::get_user(&conn)?;
     if let Some(user) = user_opt {
-        if let Some(project_id) = user.current_project_id {
+        if let Some(project_id) = user.current_project_id.clone() {
             // Check if already active
-            let active = db::get_active_session(&conn, &project_id).map_err(|e| e.to_string())?;
+            let active = db::get_active_session(&conn, &project_id)?;
+            if active.as_ref().is_some_and(|s| s.paused_at.is_some()) {
+                return resume_timer_internal(app);
+            }
             if active.is_none() {
-                // Check Limits
-                if let Some(project) = user.projects.iter().find(|p| p.id == project_id) {
-                    // Check Daily Limit
-                    if let Some(daily_limit) = project.daily_limit_hours {
-                        let today_total = db::get_today_total_time(&conn, &project_id)
-                            .map_err(|e| e.to_string())?;
-                        if (today_total as f64) / 3600.0 >= daily_limit {
-                            return Err("Daily limit reached for this project".to_string());
-                        }
-                    }
+                check_can_track(&conn, &user, &project_id)?;
 
-                    // Check Weekly Limit
-                    if let Some(weekly_limit) = project.weekly_limit_hours {
-                        let total_this_week = project.total_hours_this_week.unwrap_or(0.0);
-                        if total_this_week >= weekly_limit {
-                            return Err("Weekly limit reached for this project".to_string());
-                        }
-                    }
-                }
-
-                db::start_session(&conn, &project_id, "Project", 0, None)
-                    .map_err(|e| e.to_string())?;
+                db::start_session(&conn, &project_id, "Project", 0, None)?;
                 update_tray(&app, true, &user.email); // Refresh menu state
 
-                // Enable Idle Monitoring
-                state.idle_state.is_monitoring.store(true, Ordering::SeqCst);
-                let now = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs();
-                state
-                    .idle_state
-                    .last_activity_timestamp
-                    .store(now, Ordering::Relaxed);
-
                 // Reset Activity Counts for new session
                 state.idle_state.keyboard_count.store(0, Ordering::Relaxed);
                 state.idle_state.mouse_count.store(0, Ordering::Relaxed);
 
-                // Start Monitoring Loops (If not already running)
-                screenshot::start_capture_loop(app.clone(), state.idle_state.clone());
-                activity::start_activity_loop(app.clone(), state.idle_state.clone());
+                start_monitoring(app);
 
                 let _ = app.emit("timer-active", true);
             }
@@ -159,18 +220,129 @@ fn start_timer_internal(app: &AppHandle) -> Result<(), String> {
     Ok(())
 }
 
+/// Permissions and limits, checked before time starts counting.
+fn check_can_track(
+    conn: &Connection,
+    user: &User,
+    project_id: &str,
+) -> Result<(), WatchtowerError> {
+    if let Some(permission) = missing_permission() {
+        return Err(WatchtowerError::PermissionDenied {
+            permission: permission.to_string(),
+        });
+    }
+
+    // Check Limits
+    if let Some(project) = user.projects.iter().find(|p| p.id == project_id) {
+        let today_total = db::get_today_total_time(conn, project_id)?;
+        let week_total = timer::get_week_total(
+            conn,
+            project,
+            week::locale_week_start(),
+            &chrono::Local::now(),
+        )?;
+        timer::check_limits(project, today_total, week_total)?;
+    }
+    Ok(())
+}
+
+/// Turns on idle monitoring and the capture and activity loops for a running session.
+fn start_monitoring(app: &AppHandle) {
+    let state = app.state::<AppState>();
+
+    // Enable Idle Monitoring
+    state.idle_state.is_monitoring.store(true, Ordering::SeqCst);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    state
+        .idle_state
+        .last_activity_timestamp
+        .store(now, Ordering::Relaxed);
+
+    // Start Monitoring Loops (If not already running)
+    screenshot::start_capture_loop(
+        app.clone(),
+        state.idle_state.clone(),
+        StdRng::from_entropy(),
+    );
+    activity::start_activity_loop(app.clone(), state.idle_state.clone());
+}
+
+#[tauri::command]
+fn pause_timer(app: AppHandle) -> Result<(), WatchtowerError> {
+    pause_timer_internal(&app)
+}
+
+/// Pauses the running project session. Unlike stopping, the session (and its activity
+/// counts) carries on after `resume_timer`; the pause is recorded against it.
+fn pause_timer_internal(app: &AppHandle) -> Result<(), WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+
+    let user_opt = db::get_user(&conn)?;
+    if let Some(user) = user_opt {
+        if let Some(project_id) = &user.current_project_id {
+            if let Some(session) = db::get_active_session(&conn, project_id)? {
+                if session.paused_at.is_none() {
+                    db::pause_session(&conn, &session.uuid)?;
+                    update_tray(&app, true, &user.email); // Refresh menu state
+
+                    // Idle detection, capture and activity loops all stop with this
+                    state
+                        .idle_state
+                        .is_monitoring
+                        .store(false, Ordering::SeqCst);
+
+                    let _ = app.emit("timer-paused", true);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn resume_timer(app: AppHandle) -> Result<(), WatchtowerError> {
+    resume_timer_internal(&app)
+}
+
+fn resume_timer_internal(app: &AppHandle) -> Result<(), WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+
+    let user_opt = db::get_user(&conn)?;
+    if let Some(user) = user_opt {
+        if let Some(project_id) = &user.current_project_id {
+            if let Some(session) = db::get_active_session(&conn, project_id)? {
+                if session.paused_at.is_some() {
+                    check_can_track(&conn, &user, project_id)?;
+
+                    db::resume_session(&conn, &session.uuid)?;
+                    update_tray(&app, true, &user.email); // Refresh menu state
+                    start_monitoring(app);
+
+                    let _ = app.emit("timer-paused", false);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 #[tauri::command]
-fn stop_timer(app: AppHandle) -> Result<(), String> {
+fn stop_timer(app: AppHandle) -> Result<(), WatchtowerError> {
     stop_timer_internal(&app)
 }
 
-fn stop_timer_internal(app: &AppHandle) -> Result<(), String> {
+fn stop_timer_internal(app: &AppHandle) -> Result<(), WatchtowerError> {
     let state = app.state::<AppState>();
-    let conn = Connection::open(&*state.db_path.lock().unwrap()).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
 
-    let user_opt = db::get_user(&conn).map_err(|e| e.to_string())?;
+    let user_opt = db::get_user(&conn)?;
     if let Some(user) = user_opt {
-        db::stop_all_active_sessions(&conn).map_err(|e| e.to_string())?;
+        db::stop_all_active_sessions(&conn)?;
         update_tray(&app, true, &user.email); // Refresh menu state
 
         // Disable Idle Monitoring
@@ -190,14 +362,14 @@ fn start_break(
     policy_id: String,
     duration_minutes: i64,
     target_name: String,
-) -> Result<(), String> {
+) -> Result<(), WatchtowerError> {
     let state = app.state::<AppState>();
-    let conn = Connection::open(&*state.db_path.lock().unwrap()).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
 
-    let user_opt = db::get_user(&conn).map_err(|e| e.to_string())?;
+    let user_opt = db::get_user(&conn)?;
     if let Some(user) = user_opt {
         // Stop any active work session or other break session
-        db::stop_all_active_sessions(&conn).map_err(|e| e.to_string())?;
+        db::stop_all_active_sessions(&conn)?;
 
         // Start the break session
         db::start_session(
@@ -206,8 +378,7 @@ fn start_break(
             "WorkBreakPolicy",
             duration_minutes,
             Some(target_name),
-        )
-        .map_err(|e| e.to_string())?;
+        )?;
 
         update_tray(&app, true, &user.email); // Refresh menu state
 
@@ -228,11 +399,11 @@ fn process_idle_choice(
     idle_time: i64,
     keep: bool,
     resume: bool,
-) -> Result<(), String> {
+) -> Result<(), WatchtowerError> {
     let state = app.state::<AppState>();
-    let conn = Connection::open(&*state.db_path.lock().unwrap()).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
 
-    let user_opt = db::get_user(&conn).map_err(|e| e.to_string())?;
+    let user_opt = db::get_user(&conn)?;
     if let Some(user) = user_opt {
         if let Some(project_id) = user.current_project_id {
             let inc_idle = idle_time;
@@ -244,7 +415,7 @@ fn process_idle_choice(
                      deducted_seconds = deducted_seconds + ?2 
                  WHERE id = (SELECT id FROM sessions WHERE project_id = ?3 ORDER BY start_time DESC LIMIT 1)",
                 (inc_idle, inc_deducted, &project_id),
-            ).map_err(|e| e.to_string())?;
+            )?;
             // Reset current idle time
             *state.current_idle_time.lock().unwrap() = None;
 
@@ -276,14 +447,291 @@ fn upload_and_quit(app: AppHandle) {
 }
 
 #[tauri::command]
-fn get_project_today_total(app: AppHandle, project_id: String) -> Result<String, String> {
+fn get_upload_status(app: AppHandle) -> UploadQueueStatus {
+    app.state::<AppState>().upload_queue.status()
+}
+
+#[tauri::command]
+fn set_upload_concurrency(app: AppHandle, limit: usize) -> Result<(), WatchtowerError> {
+    if !(1..=upload_queue::MAX_CONCURRENCY).contains(&limit) {
+        return Err(WatchtowerError::invalid_input(format!(
+            "Upload concurrency must be between 1 and {}",
+            upload_queue::MAX_CONCURRENCY
+        )));
+    }
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    Ok(db::set_setting(
+        &conn,
+        upload_queue::CONCURRENCY_SETTING,
+        &limit.to_string(),
+    )?)
+}
+
+/// Screenshots are held back this many seconds before upload so they can be reviewed.
+#[tauri::command]
+fn get_review_delay(app: AppHandle) -> Result<i64, WatchtowerError> {
     let state = app.state::<AppState>();
-    let conn = Connection::open(&*state.db_path.lock().unwrap()).map_err(|e| e.to_string())?;
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    Ok(upload_queue::review_delay_secs(&conn))
+}
+
+#[tauri::command]
+fn set_review_delay(app: AppHandle, seconds: i64) -> Result<(), WatchtowerError> {
+    if !(0..=upload_queue::MAX_REVIEW_DELAY_SECS).contains(&seconds) {
+        return Err(WatchtowerError::invalid_input(
+            "Review delay must be between 0 seconds and 24 hours",
+        ));
+    }
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    Ok(db::set_setting(
+        &conn,
+        upload_queue::REVIEW_DELAY_SETTING,
+        &seconds.to_string(),
+    )?)
+}
+
+const THUMBNAIL_SIZE: u32 = 320;
+
+/// Screenshots waiting for upload, oldest first, with thumbnails for review.
+#[tauri::command]
+fn get_pending_screenshots(app: AppHandle) -> Result<Vec<ScreenshotPreview>, WatchtowerError> {
+    let state = app.state::<AppState>();
+    let db_path = state.db_path.lock().unwrap().clone();
+    let conn = Connection::open(&db_path)?;
+    let store_dir = screenshot_store::dir_for(&db_path);
+    let delay_ms = upload_queue::review_delay_secs(&conn) * 1000;
+
+    Ok(db::get_pending_screenshots(&conn)?
+        .into_iter()
+        .map(|screenshot| ScreenshotPreview {
+            thumbnail: screenshot_store::thumbnail(
+                &store_dir,
+                &screenshot.file_path,
+                THUMBNAIL_SIZE,
+            )
+            .map_err(|e| {
+                eprintln!(
+                    "Store: Failed to make thumbnail for {}: {}",
+                    screenshot.id, e
+                )
+            })
+            .ok(),
+            uploads_at: screenshot.timestamp + delay_ms,
+            screenshot,
+        })
+        .collect())
+}
+
+/// Deletes a screenshot before it is uploaded. With `deduct_time`, the time its capture
+/// stood for is taken off its session, once per capture however many monitor images it
+/// has. Unchanged markers repeating a deleted image go with it. Returns the seconds
+/// deducted. A screenshot already being uploaded can't be called back.
+#[tauri::command]
+fn delete_pending_screenshot(
+    app: AppHandle,
+    id: i64,
+    deduct_time: bool,
+) -> Result<i64, WatchtowerError> {
+    let state = app.state::<AppState>();
+    let db_path = state.db_path.lock().unwrap().clone();
+    let conn = Connection::open(&db_path)?;
+
+    let screenshot = db::get_pending_screenshots(&conn)?
+        .into_iter()
+        .find(|s| s.id == id)
+        .ok_or_else(|| WatchtowerError::invalid_input("Screenshot is no longer pending"))?;
+    // Claimed like an upload would, so the upload pass leaves it alone from here on
+    if !db::claim_screenshot(&conn, id)? {
+        return Err(WatchtowerError::invalid_input(
+            "Screenshot is being uploaded and can no longer be deleted",
+        ));
+    }
+    let result = delete_claimed_screenshot(&state, &conn, &db_path, &screenshot, deduct_time);
+    if result.is_err() {
+        let _ = db::release_screenshot_claim(&conn, id);
+    }
+    result
+}
+
+fn delete_claimed_screenshot(
+    state: &AppState,
+    conn: &Connection,
+    db_path: &Path,
+    screenshot: &PendingScreenshot,
+    deduct_time: bool,
+) -> Result<i64, WatchtowerError> {
+    let id = screenshot.id;
+    let tx = conn.unchecked_transaction()?;
+
+    let mut deducted = 0;
+    if deduct_time {
+        let slice = match db::take_capture_seconds(&tx, id)? {
+            Some(seconds) => seconds,
+            // Taken before captures were grouped: the project's schedule now
+            None => {
+                let user = db::get_user(&tx)?;
+                let project = user
+                    .as_ref()
+                    .and_then(|u| u.projects.iter().find(|p| p.id == screenshot.project_id));
+                let schedule = capture_schedule::capture_schedule(&tx, project);
+                capture_schedule::seconds_per_capture(&schedule) as i64
+            }
+        };
+        deducted = db::deduct_session_time(&tx, &screenshot.session_uuid, slice)?;
+    }
+    let file_name = db::delete_pending_screenshot(&tx, id)?;
+    let mut markers = 0;
+    if !screenshot.unchanged {
+        markers = db::delete_unchanged_markers(&tx, &screenshot.content_hash)?;
+    }
+    tx.commit()?;
+
+    // A deleted image mustn't become the reference for later unchanged markers
+    state
+        .last_stored
+        .lock()
+        .unwrap()
+        .retain(|_, last| last.stored.hash != screenshot.content_hash);
+    if let Some(file_name) = file_name {
+        screenshot_store::release(conn, &screenshot_store::dir_for(db_path), &file_name)?;
+    }
+    println!(
+        "Store: Screenshot {} deleted on review with {} unchanged markers ({}s deducted)",
+        id, markers, deducted
+    );
+    Ok(deducted)
+}
+
+/// The screenshot encoding profile in effect for a project.
+#[tauri::command]
+fn get_encoding_profile(
+    app: AppHandle,
+    project_id: String,
+) -> Result<EncodingProfile, WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    let user = db::get_user(&conn)?;
+    let project = user
+        .as_ref()
+        .and_then(|u| u.projects.iter().find(|p| p.id == project_id));
+    Ok(encoder::encoding_profile(&conn, project, &project_id))
+}
+
+/// Chooses the encoding profile for a project, or goes back to the default with `None`.
+/// Projects whose organization sets a profile keep using that one.
+#[tauri::command]
+fn set_encoding_profile(
+    app: AppHandle,
+    project_id: String,
+    profile: Option<EncodingProfile>,
+) -> Result<(), WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    encoder::set_local_profile(&conn, &project_id, profile)
+}
+
+/// The chunk policy in effect for the selected project.
+#[tauri::command]
+fn get_chunk_policy(app: AppHandle) -> Result<ChunkPolicy, WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    let user = db::get_user(&conn)?;
+    let project = user.as_ref().and_then(|u| {
+        u.projects
+            .iter()
+            .find(|p| Some(&p.id) == u.current_project_id.as_ref())
+    });
+    Ok(timer::chunk_policy(&conn, project))
+}
+
+/// Sets the local chunk policy, or clears it with `None`. Projects whose organization
+/// sets a policy keep using that one.
+#[tauri::command]
+fn set_chunk_policy(app: AppHandle, policy: Option<ChunkPolicy>) -> Result<(), WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    match policy {
+        Some(policy) => {
+            timer::validate_chunk_policy(&policy)?;
+            let json = serde_json::to_string(&policy)
+                .map_err(|e| WatchtowerError::invalid_input(e.to_string()))?;
+            db::set_setting(&conn, timer::CHUNK_POLICY_SETTING, &json)?;
+        }
+        None => db::delete_setting(&conn, timer::CHUNK_POLICY_SETTING)?,
+    }
+    Ok(())
+}
+
+/// The screenshot schedule in effect for the selected project.
+#[tauri::command]
+fn get_capture_schedule(app: AppHandle) -> Result<CaptureSchedule, WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    let user = db::get_user(&conn)?;
+    let project = user.as_ref().and_then(|u| {
+        u.projects
+            .iter()
+            .find(|p| Some(&p.id) == u.current_project_id.as_ref())
+    });
+    Ok(capture_schedule::capture_schedule(&conn, project))
+}
 
-    let total_secs = db::get_today_total_time(&conn, &project_id).map_err(|e| e.to_string())?;
+/// Sets the local screenshot schedule, or clears it with `None`. Projects whose
+/// organization sets a schedule keep using that one.
+#[tauri::command]
+fn set_capture_schedule(
+    app: AppHandle,
+    schedule: Option<CaptureSchedule>,
+) -> Result<(), WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    match schedule {
+        Some(schedule) => {
+            capture_schedule::validate_capture_schedule(&schedule)?;
+            let json = serde_json::to_string(&schedule)
+                .map_err(|e| WatchtowerError::invalid_input(e.to_string()))?;
+            db::set_setting(&conn, capture_schedule::CAPTURE_SCHEDULE_SETTING, &json)?;
+        }
+        None => db::delete_setting(&conn, capture_schedule::CAPTURE_SCHEDULE_SETTING)?,
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn get_privacy_settings(app: AppHandle) -> Result<PrivacySettings, WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    Ok(redaction::privacy_settings(&conn))
+}
+
+/// Replaces the screenshot denylist and the skip-when-focused switch. Applies from the
+/// next capture on.
+#[tauri::command]
+fn set_privacy_settings(app: AppHandle, settings: PrivacySettings) -> Result<(), WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    let json = serde_json::to_string(&redaction::normalize(settings))
+        .map_err(|e| WatchtowerError::invalid_input(e.to_string()))?;
+    db::set_setting(&conn, redaction::PRIVACY_SETTING, &json)?;
+    Ok(())
+}
+
+#[tauri::command]
+fn get_project_today_total(app: AppHandle, project_id: String) -> Result<String, WatchtowerError> {
+    let state = app.state::<AppState>();
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+
+    let total_secs = db::get_today_total_time(&conn, &project_id)?;
     Ok(format_duration(total_secs))
 }
 
+#[tauri::command]
+fn get_timer_state(app: AppHandle) -> TimerState {
+    app.state::<AppState>().timer_state.lock().unwrap().clone()
+}
+
 #[tauri::command]
 fn get_timer_status(app: AppHandle) -> bool {
     let state = app.state::<AppState>();
@@ -302,6 +750,62 @@ fn format_duration(seconds: u64) -> String {
     format!("{:02}:{:02}:{:02}", hours, minutes, secs)
 }
 
+#[tauri::command]
+fn get_api_environment(app: AppHandle) -> ApiEnvironment {
+    api::environment(&app)
+}
+
+/// Switches the backend the app talks to. The timer is stopped first, and the new
+/// environment gets its own database, so nothing recorded so far is sent to it.
+#[tauri::command]
+fn set_api_environment(
+    app: AppHandle,
+    name: String,
+    base_url: Option<String>,
+) -> Result<ApiEnvironment, WatchtowerError> {
+    let target = ApiEnvironment::new(&name, base_url.as_deref())?;
+    if api::environment(&app) == target {
+        return Ok(target);
+    }
+
+    // Close the running session in the environment it belongs to
+    stop_timer_internal(&app)?;
+
+    let app_data_dir = app.path().app_data_dir()?;
+    let config_dir = app.path().app_config_dir()?;
+    let db_path = target.db_path(&app_data_dir);
+    prepare_database(&app, &db_path)?;
+    environment::save(&config_dir, &target)?;
+    if environment::is_pinned_by_env_var() {
+        eprintln!(
+            "Env: {} is set and will override this choice on the next launch",
+            environment::ENV_NAME_VAR
+        );
+    }
+
+    let state = app.state::<AppState>();
+    {
+        let mut env = state.environment.lock().unwrap();
+        *state.db_path.lock().unwrap() = db_path.clone();
+        *env = target.clone();
+    }
+    println!("Env: Switched to {} ({})", target.name, target.base_url);
+
+    refresh_tray(&app);
+    let _ = app.emit("environment-changed", target.clone());
+
+    screenshot::upload_pending_screenshots(&app);
+    screenshot::sync_daily_sessions(&app);
+    project_sync::sync_projects(&app);
+    Ok(target)
+}
+
+#[tauri::command]
+fn get_recovered_sessions(app: AppHandle) -> Vec<RecoveredSessionPayload> {
+    let state = app.state::<AppState>();
+    std::mem::take(&mut *state.recovered_sessions.lock().unwrap())
+}
+
 #[tauri::command]
 async fn check_permissions() -> serde_json::Value {
     #[cfg(target_os = "macos")]
@@ -354,48 +858,90 @@ async fn open_permissions_settings(type_name: String) {
     }
 }
 
+/// Returns the first OS permission tracking needs that has not been granted yet.
+/// Uses the synchronous checks so it can run from the tray and command paths.
+fn missing_permission() -> Option<&'static str> {
+    #[cfg(target_os = "macos")]
+    {
+        // For screen recording, we preflight via extern
+        extern "C" {
+            fn CGPreflightScreenCaptureAccess() -> bool;
+        }
+
+        if !macos_accessibility_client::accessibility::application_is_trusted() {
+            return Some("accessibility");
+        }
+        if !unsafe { CGPreflightScreenCaptureAccess() } {
+            return Some("screenRecording");
+        }
+    }
+    None
+}
+
+/// Rebuilds the tray menu for whoever is logged in to the current environment.
+pub fn refresh_tray<R: Runtime>(app: &AppHandle<R>) {
+    let db_path = app.state::<AppState>().db_path.lock().unwrap().clone();
+    match Connection::open(&db_path).and_then(|conn| db::get_user(&conn)) {
+        Ok(Some(user)) => update_tray(app, true, &user.email),
+        _ => update_tray(app, false, ""),
+    }
+}
+
+/// Tray menu ids of project items are one of these prefixes plus the project id.
+const PROJECT_MENU_PREFIX: &str = "project:";
+const RECENT_PROJECT_MENU_PREFIX: &str = "recent-project:";
+/// How many projects the tray's Recent section lists.
+const RECENT_PROJECTS: usize = 3;
+
+/// The project a tray menu item selects, if it is a project item.
+fn project_menu_target(menu_id: &str) -> Option<&str> {
+    menu_id
+        .strip_prefix(PROJECT_MENU_PREFIX)
+        .or_else(|| menu_id.strip_prefix(RECENT_PROJECT_MENU_PREFIX))
+}
+
 pub fn update_tray<R: Runtime>(app: &AppHandle<R>, is_logged_in: bool, email: &str) {
     let state = app.state::<AppState>();
     // We need to fetch current state to enable/disable items correctly
     let mut current_project_name = "None".to_string();
     let mut has_active_session = false;
+    let mut is_paused = false;
     let mut is_project_selected = false;
-    let permissions_granted = {
-        #[cfg(target_os = "macos")]
-        {
-            // Simple sync check for tray (re-implementing bits to avoid async in tray)
-            // Note: We need to make sure macos_accessibility_client is available or we use a different approach.
-            // Since we moved it to target-specific dep, we can't use it here if cfg is not macos.
-            // But this block is cfg(target_os = "macos"), so it should be fine.
-            let mut granted = macos_accessibility_client::accessibility::application_is_trusted();
-
-            // For screen recording, we preflight via extern
-            extern "C" {
-                fn CGPreflightScreenCaptureAccess() -> bool;
-            }
-            unsafe {
-                granted = granted && CGPreflightScreenCaptureAccess();
-            }
-            granted
-        }
-        #[cfg(not(target_os = "macos"))]
-        {
-            true
-        }
-    };
+    let mut current_project_id = None;
+    let mut projects = Vec::new();
+    let mut recent_ids = Vec::new();
+    let permissions_granted = missing_permission().is_none();
 
     if is_logged_in {
         if let Ok(conn) = Connection::open(&*state.db_path.lock().unwrap()) {
             if let Ok(Some(user)) = db::get_user(&conn) {
+                projects = user
+                    .projects
+                    .iter()
+                    .map(|p| {
+                        let today = db::get_today_total_time(&conn, &p.id).unwrap_or(0);
+                        (
+                            p.id.clone(),
+                            format!("{}  ({})", p.name, format_duration(today)),
+                        )
+                    })
+                    .collect();
+                recent_ids = db::get_recent_project_ids(&conn, RECENT_PROJECTS)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|id| user.projects.iter().any(|p| &p.id == id))
+                    .collect();
                 if let Some(pid) = &user.current_project_id {
                     is_project_selected = true;
+                    current_project_id = Some(pid.clone());
                     // Find project name
                     if let Some(p) = user.projects.iter().find(|p| &p.id == pid) {
                         current_project_name = p.name.clone();
                     }
 
-                    if let Ok(Some(_)) = db::get_active_session(&conn, pid) {
+                    if let Ok(Some(session)) = db::get_active_session(&conn, pid) {
                         has_active_session = true;
+                        is_paused = session.paused_at.is_some();
                     }
                 }
             }
@@ -418,16 +964,50 @@ pub fn update_tray<R: Runtime>(app: &AppHandle<R>, is_logged_in: bool, email: &s
             .append(&MenuItem::with_id(app, "status", &db_status, false, None::<&str>).unwrap());
 
         if is_logged_in {
-            let _ = menu.append(
-                &MenuItem::with_id(
-                    app,
-                    "project_display",
-                    &project_item_text,
-                    false,
-                    None::<&str>,
-                )
-                .unwrap(),
-            );
+            // Project submenu; choosing one goes through `set_current_project`
+            if let Ok(project_menu) = Submenu::with_id(
+                app,
+                "project_display",
+                &project_item_text,
+                !projects.is_empty(),
+            ) {
+                let current = current_project_id.as_deref();
+                let check_item = |prefix: &str, id: &str, label: &str| {
+                    CheckMenuItem::with_id(
+                        app,
+                        format!("{}{}", prefix, id),
+                        label,
+                        true,
+                        Some(id) == current,
+                        None::<&str>,
+                    )
+                };
+
+                if !recent_ids.is_empty() {
+                    if let Ok(header) =
+                        MenuItem::with_id(app, "recent_header", "Recent", false, None::<&str>)
+                    {
+                        let _ = project_menu.append(&header);
+                    }
+                    for id in &recent_ids {
+                        if let Some((_, label)) = projects.iter().find(|(pid, _)| pid == id) {
+                            if let Ok(item) = check_item(RECENT_PROJECT_MENU_PREFIX, id, label) {
+                                let _ = project_menu.append(&item);
+                            }
+                        }
+                    }
+                    if let Ok(separator) = PredefinedMenuItem::separator(app) {
+                        let _ = project_menu.append(&separator);
+                    }
+                }
+
+                for (id, label) in &projects {
+                    if let Ok(item) = check_item(PROJECT_MENU_PREFIX, id, label) {
+                        let _ = project_menu.append(&item);
+                    }
+                }
+                let _ = menu.append(&project_menu);
+            }
 
             // Start Timer (Enabled if NO active session and project selected and permissions granted)
             let start_enabled = !has_active_session && is_project_selected && permissions_granted;
@@ -441,6 +1021,26 @@ pub fn update_tray<R: Runtime>(app: &AppHandle<R>, is_logged_in: bool, email: &s
                     .unwrap(),
             );
 
+            // Pause or Resume, depending on whether the active session is paused
+            let pause_item = if is_paused {
+                MenuItem::with_id(
+                    app,
+                    "resume_timer",
+                    "Resume Timer",
+                    permissions_granted,
+                    None::<&str>,
+                )
+            } else {
+                MenuItem::with_id(
+                    app,
+                    "pause_timer",
+                    "Pause Timer",
+                    has_active_session,
+                    None::<&str>,
+                )
+            };
+            let _ = menu.append(&pause_item.unwrap());
+
             // Stop Timer (Enabled if active session)
             let stop_enabled = has_active_session;
             let _ = menu.append(
@@ -464,10 +1064,57 @@ pub fn update_tray<R: Runtime>(app: &AppHandle<R>, is_logged_in: bool, email: &s
 }
 
 #[tauri::command]
-fn get_used_break_ids(app: AppHandle) -> Result<Vec<String>, String> {
+fn get_used_break_ids(app: AppHandle) -> Result<Vec<String>, WatchtowerError> {
     let state = app.state::<AppState>();
-    let conn = Connection::open(&*state.db_path.lock().unwrap()).map_err(|e| e.to_string())?;
-    db::get_used_break_policy_ids_today(&conn).map_err(|e| e.to_string())
+    let conn = Connection::open(&*state.db_path.lock().unwrap())?;
+    Ok(db::get_used_break_policy_ids_today(&conn)?)
+}
+
+/// Migrates an environment's database and tidies it up before it becomes current:
+/// closes sessions left active by a crash and moves inline screenshots to disk.
+fn prepare_database(app: &AppHandle, db_path: &Path) -> Result<(), WatchtowerError> {
+    if let Some(dir) = db_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let conn = db::init_db(db_path)?;
+    if let Err(e) = credentials::move_plaintext_tokens(&conn) {
+        eprintln!("Failed to move tokens to the credential store: {}", e);
+    }
+
+    // Close sessions left active by a crash at their last heartbeat, not now
+    match db::recover_orphaned_sessions(&conn) {
+        Ok(recovered) if !recovered.is_empty() => {
+            let payload: Vec<RecoveredSessionPayload> = recovered
+                .into_iter()
+                .map(|s| RecoveredSessionPayload {
+                    uuid: s.uuid,
+                    project_id: s.project_id,
+                    project_type: s.project_type,
+                    start_time: s.start_time,
+                    end_time: s.end_time,
+                })
+                .collect();
+            // Store for command-based retrieval; the webview may not be listening yet
+            let state = app.state::<AppState>();
+            *state.recovered_sessions.lock().unwrap() = payload.clone();
+
+            let app_handle_recovery = app.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(1000));
+                let _ = app_handle_recovery.emit("sessions-recovered", payload);
+            });
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Failed to recover active sessions: {}", e),
+    }
+
+    // Move screenshots stored inline by older releases to disk, then drop stray files
+    let store_dir = screenshot_store::dir_for(db_path);
+    if let Err(e) = screenshot_store::externalize_inline_rows(&conn, &store_dir) {
+        eprintln!("Failed to move screenshots to disk: {}", e);
+    }
+    let _ = screenshot_store::remove_unreferenced(&conn, &store_dir);
+    Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -478,35 +1125,74 @@ pub fn run() {
     let mut builder = tauri::Builder::default()
         .manage(AppState {
             db_path: Mutex::new(PathBuf::new()),
+            environment: Mutex::new(environment::compiled_default()),
             idle_state: idle_state.clone(),
             client: reqwest::Client::new(),
+            token_refresh: tokio::sync::Mutex::new(()),
             current_idle_time: Mutex::new(None),
+            recovered_sessions: Mutex::new(Vec::new()),
+            upload_queue: Arc::new(UploadQueue::new()),
+            timer_state: Mutex::new(TimerState::Idle),
+            last_stored: Mutex::new(HashMap::new()),
         })
         .setup(move |app| {
             let app_handle = app.handle();
 
             #[cfg(desktop)]
-            app.handle().plugin(tauri_plugin_updater::Builder::new().build())?;
+            app.handle()
+                .plugin(tauri_plugin_updater::Builder::new().build())?;
 
             let app_data_dir = app
                 .path()
                 .app_data_dir()
                 .expect("failed to get app data dir");
             std::fs::create_dir_all(&app_data_dir).expect("failed to create app data dir");
-            let db_path = app_data_dir.join("auth_v2.db");
+            let config_dir = app
+                .path()
+                .app_config_dir()
+                .expect("failed to get app config dir");
 
-            // Update state with actual path
-            let state = app.state::<AppState>();
-            *state.db_path.lock().unwrap() = db_path.clone();
+            if let Err(e) = environment::adopt_legacy_data(&app_data_dir) {
+                eprintln!("Failed to move existing data into its environment: {}", e);
+            }
+            // The credential file's key goes where a copy of the data dir won't pick it
+            // up. On Windows the config dir is the roaming data dir, so use the local one.
+            #[cfg(windows)]
+            let key_dir = app
+                .path()
+                .app_local_data_dir()
+                .expect("failed to get app local data dir");
+            #[cfg(not(windows))]
+            let key_dir = config_dir.clone();
+            credentials::init(&app_data_dir, &key_dir);
+            let api_environment = environment::resolve(&config_dir);
+            println!(
+                "Env: Using {} ({})",
+                api_environment.name, api_environment.base_url
+            );
+            let db_path = api_environment.db_path(&app_data_dir);
 
-            // Init DB
-            if let Err(e) = db::init_db(&db_path) {
+            // Init DB (refuse to start rather than run against a schema we can't migrate)
+            if let Err(e) = prepare_database(&app_handle, &db_path) {
                 eprintln!("Failed to init db: {}", e);
+                return Err(Box::new(e));
+            }
+            // An upload pass cut short by the last quit leaves its rows claimed. Only safe
+            // before the first pass; after that one may be running against any environment.
+            for path in environment::db_paths(&app_data_dir) {
+                if let Err(e) =
+                    Connection::open(&path).and_then(|conn| db::release_screenshot_claims(&conn))
+                {
+                    eprintln!("Failed to release screenshot upload claims: {}", e);
+                }
             }
 
-            // Ensure all timers are stopped on startup globally
-            if let Ok(conn) = Connection::open(&db_path) {
-                let _ = db::stop_all_active_sessions(&conn);
+            // Update state with actual path
+            let state = app.state::<AppState>();
+            {
+                let mut env = state.environment.lock().unwrap();
+                *state.db_path.lock().unwrap() = db_path.clone();
+                *env = api_environment;
             }
 
             // process pending screenshots on startup
@@ -518,6 +1204,8 @@ pub fn run() {
             idle::start_idle_check(app_handle.clone(), idle_state.clone());
             // Start Permanent Sync Loop (screenshots and sessions)
             screenshot::start_screenshot_monitor(app_handle.clone());
+            // Keep limits and weekly totals current (also runs after each session sync)
+            project_sync::start_project_sync_loop(app_handle.clone());
             // (Capture and Activity loops only start when timer is ON)
 
             // Listen for Internal Idle Event
@@ -596,19 +1284,46 @@ pub fn run() {
                         }
                     }
                     "logout" => {
-                        let _ = logout(app.clone());
-                        let _ = app.emit("logout-user", ());
+                        let app = app.clone();
+                        tauri::async_runtime::spawn(async move {
+                            let _ = logout(app.clone()).await;
+                            let _ = app.emit("logout-user", ());
+                        });
                     }
                     "start_timer" => {
                         let _ = start_timer(app.clone());
                     }
+                    "pause_timer" => {
+                        let _ = pause_timer(app.clone());
+                    }
+                    "resume_timer" => {
+                        let _ = resume_timer(app.clone());
+                    }
                     "stop_timer" => {
                         let _ = stop_timer(app.clone());
                     }
-                    _ => {}
+                    id => {
+                        if let Some(project_id) = project_menu_target(id) {
+                            match set_current_project(app.clone(), project_id.to_string()) {
+                                Ok(()) => {}
+                                Err(e @ WatchtowerError::LimitReached { .. }) => {
+                                    let _ = app.emit("limit-reached", &e);
+                                }
+                                Err(e) => eprintln!("Failed to switch project: {}", e),
+                            }
+                            // Check items toggle themselves when clicked; redraw from the real state
+                            refresh_tray(app);
+                        }
+                    }
                 });
 
-            if let Some(icon) = tray_generator::generate_tray_icon("--:--:--") {
+            let spec = tray_generator::TrayIconSpec {
+                text: "--:--:--",
+                state: tray_generator::IconState::LoggedOut,
+                progress: None,
+                theme: tray_generator::Theme::Light,
+            };
+            if let Some(icon) = tray_generator::generate_tray_icon(&spec) {
                 tray_builder = tray_builder.icon(icon);
             } else {
                 eprintln!("Failed to generate tray icon, using default.");
@@ -627,169 +1342,8 @@ pub fn run() {
                 });
             }
 
-            // Spawn a thread to update the tray icon every second
-            let app_handle_for_thread = app_handle.clone();
-            std::thread::spawn(move || {
-                loop {
-                    std::thread::sleep(std::time::Duration::from_secs(1));
-
-                    let state = app_handle_for_thread.state::<AppState>();
-
-                    let mut payload = TimeUpdatePayload {
-                        time: "--:--:--".to_string(),
-                        project_type: "Project".to_string(),
-                        project_id: "".to_string(),
-                        target_name: None,
-                    };
-                    let mut should_update_db = false;
-                    let mut active_session_id = None;
-
-                    if let Ok(conn) = Connection::open(&*state.db_path.lock().unwrap()) {
-                        if let Ok(Some(user)) = db::get_user(&conn) {
-                            if let Some(project_id) = user.current_project_id {
-                                payload.project_id = project_id.clone();
-                                // Check active session (Global check to support Breaks)
-                                if let Ok(Some(session)) = db::get_global_active_session(&conn) {
-                                    payload.project_type = session.project_type.clone();
-                                    payload.project_id = session.project_id.clone();
-                                    payload.target_name = session.target_name.clone();
-
-                                    // Check if session exceeds 10 minutes or crosses hour boundary
-                                    let now = Local::now();
-                                    let now_ms = now.timestamp_millis();
-                                    let duration = now_ms - session.start_time;
-
-                                    // Get the hour and day of the session start
-                                    let start_time_dt =
-                                        chrono::DateTime::from_timestamp_millis(session.start_time)
-                                            .map(|dt| dt.with_timezone(&Local))
-                                            .unwrap_or(now);
-
-                                    let hour_changed = now.hour() != start_time_dt.hour()
-                                        || now.date_naive() != start_time_dt.date_naive();
-
-                                    if (session.project_type == "Project"
-                                        || session.project_type == "watchtower")
-                                        && (duration >= 10 * 60 * 1000 || hour_changed)
-                                    {
-                                        // Stop current session
-                                        let _ = db::stop_session(&conn, &project_id);
-                                        // Start new session
-                                        let _ = db::start_session(
-                                            &conn,
-                                            &project_id,
-                                            "Project",
-                                            0,
-                                            None,
-                                        );
-
-                                        // Reset activity counts for the new session
-                                        state.idle_state.keyboard_count.store(0, Ordering::Relaxed);
-                                        state.idle_state.mouse_count.store(0, Ordering::Relaxed);
-
-                                        // Refresh active session info
-                                        if let Ok(Some(new_session)) =
-                                            db::get_global_active_session(&conn)
-                                        {
-                                            should_update_db = true;
-                                            active_session_id = new_session.id;
-                                        }
-                                    } else {
-                                        should_update_db = true;
-                                        active_session_id = session.id;
-                                    }
-
-                                    // Calculate Display Time
-                                    if session.project_type == "WorkBreakPolicy" {
-                                        let elapsed_secs = duration / 1000;
-                                        let total_secs = session.duration_minutes * 60;
-                                        let remaining_secs =
-                                            total_secs.saturating_sub(elapsed_secs);
-
-                                        payload.time = format_duration(remaining_secs as u64);
-
-                                        // Auto-stop if reached zero
-                                        if remaining_secs <= 0 {
-                                            let _ = db::stop_all_active_sessions(&conn);
-                                            let _ =
-                                                app_handle_for_thread.emit("timer-active", false);
-                                            // update tray to project state
-                                            update_tray(&app_handle_for_thread, true, &user.email);
-                                        }
-                                    } else {
-                                        if let Ok(total) = db::get_today_total_time(&conn, &project_id)
-                                        {
-                                            payload.time = format_duration(total);
-
-                                            // Check Limits during active session
-                                            if let Some(project) = user.projects.iter().find(|p| p.id == project_id) {
-                                                let mut limit_reached = false;
-                                                let mut limit_msg = "";
-
-                                                if let Some(daily_limit) = project.daily_limit_hours {
-                                                    if (total as f64) / 3600.0 >= daily_limit {
-                                                        limit_reached = true;
-                                                        limit_msg = "Daily limit reached for this project";
-                                                    }
-                                                }
-
-                                                if !limit_reached {
-                                                    if let Some(weekly_limit) = project.weekly_limit_hours {
-                                                        let total_this_week = project.total_hours_this_week.unwrap_or(0.0);
-                                                        if total_this_week >= weekly_limit {
-                                                            limit_reached = true;
-                                                            limit_msg = "Weekly limit reached for this project";
-                                                        }
-                                                    }
-                                                }
-
-                                                if limit_reached {
-                                                    let _ = db::stop_all_active_sessions(&conn);
-                                                    let _ = app_handle_for_thread.emit("timer-active", false);
-                                                    let _ = app_handle_for_thread.emit("limit-reached", limit_msg);
-                                                    update_tray(&app_handle_for_thread, true, &user.email);
-                                                }
-                                            }
-                                        }
-                                    }
-                                } else {
-                                    // No active session
-                                    if let Ok(total) = db::get_today_total_time(&conn, &project_id)
-                                    {
-                                        payload.time = format_duration(total);
-                                    } else {
-                                        payload.time = "00:00:00".to_string();
-                                    }
-                                }
-                            } else {
-                                payload.time = "00:00:00".to_string();
-                            }
-                        }
-                    }
-
-                    // Update DB if active session
-                    if should_update_db {
-                        if let Some(sid) = active_session_id {
-                            if let Ok(conn) = Connection::open(&*state.db_path.lock().unwrap()) {
-                                let k_count =
-                                    state.idle_state.keyboard_count.load(Ordering::Relaxed) as i64;
-                                let m_count =
-                                    state.idle_state.mouse_count.load(Ordering::Relaxed) as i64;
-                                let _ = db::update_session_heartbeat(&conn, sid, k_count, m_count);
-                            }
-                        }
-                    }
-
-                    if let Some(tray) = app_handle_for_thread.tray_by_id("main") {
-                        if let Some(icon) = tray_generator::generate_tray_icon(&payload.time) {
-                            let _ = tray.set_icon(Some(icon));
-                        }
-                    }
-
-                    // Emit time update to Vite app
-                    let _ = app_handle_for_thread.emit("time-update", &payload);
-                }
-            });
+            // Tray clock, chunk rollover and limit enforcement, once a second
+            timer::start_timer_loop(app_handle.clone(), timer::SystemClock);
 
             Ok(())
         })
@@ -798,8 +1352,10 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(
             tauri_plugin_log::Builder::new()
-                .target(tauri_plugin_log::Target::new(tauri_plugin_log::TargetKind::Stdout))
-                .build()
+                .target(tauri_plugin_log::Target::new(
+                    tauri_plugin_log::TargetKind::Stdout,
+                ))
+                .build(),
         );
 
     #[cfg(target_os = "macos")]
@@ -816,6 +1372,8 @@ pub fn run() {
             set_current_project,
             start_timer,
             stop_timer,
+            pause_timer,
+            resume_timer,
             process_idle_choice,
             force_quit,
             upload_and_quit,
@@ -823,9 +1381,29 @@ pub fn run() {
             check_permissions,
             open_permissions_settings,
             get_timer_status,
+            get_timer_state,
+            get_review_delay,
+            set_review_delay,
+            get_pending_screenshots,
+            delete_pending_screenshot,
+            get_encoding_profile,
+            set_encoding_profile,
+            get_chunk_policy,
+            set_chunk_policy,
+            get_capture_schedule,
+            set_capture_schedule,
+            get_privacy_settings,
+            set_privacy_settings,
             get_idle_time,
             start_break,
-            get_used_break_ids
+            get_used_break_ids,
+            get_recovered_sessions,
+            get_upload_status,
+            set_upload_concurrency,
+            get_api_environment,
+            set_api_environment,
+            refresh_projects,
+            get_work_break_policies
         ])
         .on_window_event(|window, event| {
             if let tauri::WindowEvent::CloseRequested { api, .. } = event {
@@ -841,8 +1419,8 @@ pub fn run() {
             let state = app_handle.state::<AppState>();
             let mut has_pending = false;
             if let Ok(conn) = Connection::open(&*state.db_path.lock().unwrap()) {
-                if let Ok(pending) = db::get_pending_screenshots(&conn) {
-                    if !pending.is_empty() {
+                if let Ok(pending) = db::count_pending_screenshots(&conn) {
+                    if pending > 0 {
                         has_pending = true;
                     }
                 }
@@ -0,0 +1,879 @@
+use rusqlite::{Connection, Transaction};
+use std::fmt;
+
+// Schema Migrations
+//
+// The schema version lives in `PRAGMA user_version`. Each migration runs in its own
+// transaction together with the version bump, so a failure leaves the database exactly
+// as it was before that step. Migrations are forward-only and must never drop rows.
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Sqlite(rusqlite::Error),
+    /// The database was written by a newer build than this one.
+    NewerSchema {
+        found: i64,
+        supported: i64,
+    },
+    /// Upgrading `table` would require inventing a value for `column` on existing rows.
+    DataLoss {
+        table: String,
+        column: String,
+    },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Sqlite(e) => write!(f, "{}", e),
+            MigrationError::NewerSchema { found, supported } => write!(
+                f,
+                "Database schema version {} is newer than supported version {}",
+                found, supported
+            ),
+            MigrationError::DataLoss { table, column } => write!(
+                f,
+                "Refusing to migrate table {}: existing rows have no value for required column {}",
+                table, column
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(e)
+    }
+}
+
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub up: fn(&Transaction) -> Result<(), MigrationError>,
+}
+
+/// Ordered list of migrations. Append new entries; never edit or reorder released ones.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "baseline schema",
+        up: baseline,
+    },
+    Migration {
+        version: 2,
+        description: "screenshot files on disk",
+        up: screenshot_files,
+    },
+    Migration {
+        version: 3,
+        description: "upload retry state and settings",
+        up: upload_retries,
+    },
+    Migration {
+        version: 4,
+        description: "access token expiry",
+        up: token_expiry,
+    },
+    Migration {
+        version: 5,
+        description: "credential store reference",
+        up: credential_ref,
+    },
+    Migration {
+        version: 6,
+        description: "synced session time",
+        up: synced_time,
+    },
+    Migration {
+        version: 7,
+        description: "chunk policy and work runs",
+        up: work_runs,
+    },
+    Migration {
+        version: 8,
+        description: "session pauses",
+        up: session_pauses,
+    },
+    Migration {
+        version: 9,
+        description: "multi-monitor screenshots",
+        up: monitor_metadata,
+    },
+    Migration {
+        version: 10,
+        description: "capture schedule",
+        up: capture_schedule,
+    },
+    Migration {
+        version: 11,
+        description: "screenshot blur level",
+        up: blur_level,
+    },
+    Migration {
+        version: 12,
+        description: "perceptual hashes",
+        up: perceptual_hashes,
+    },
+    Migration {
+        version: 13,
+        description: "screenshot encoding profile",
+        up: encoding_profile,
+    },
+    Migration {
+        version: 14,
+        description: "screenshot activity context",
+        up: screenshot_context,
+    },
+    Migration {
+        version: 15,
+        description: "screenshot capture groups",
+        up: capture_groups,
+    },
+    Migration {
+        version: 16,
+        description: "screenshot upload claims",
+        up: upload_claims,
+    },
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn current_version(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+pub fn run(conn: &mut Connection) -> Result<(), MigrationError> {
+    let current = current_version(conn)?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(MigrationError::NewerSchema {
+            found: current,
+            supported: latest,
+        });
+    }
+    upgrade(conn, current, latest)
+}
+
+/// Applies the migrations after `from` up to and including `to`.
+fn upgrade(conn: &mut Connection, from: i64, to: i64) -> Result<(), MigrationError> {
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|m| m.version > from && m.version <= to)
+    {
+        println!(
+            "DB: Applying migration {} ({})",
+            migration.version, migration.description
+        );
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+// Baseline (v1)
+//
+// Databases created before versioning have `user_version = 0` and whatever columns the
+// release that last opened them defined. The baseline brings any of those layouts to the
+// v1 schema below, rebuilding tables via copy-rename so unsynced rows survive.
+
+struct DbColumn {
+    name: &'static str,
+    def: &'static str,
+    type_affinity: &'static str,
+}
+
+impl DbColumn {
+    fn is_required(&self) -> bool {
+        self.def.contains("NOT NULL") && !self.def.contains("DEFAULT")
+    }
+}
+
+struct DbTable {
+    name: &'static str,
+    columns: &'static [DbColumn],
+    constraints: Option<&'static str>,
+}
+
+const BASELINE_SCHEMA: &[DbTable] = &[
+    DbTable {
+        name: "users",
+        columns: &[
+            DbColumn {
+                name: "uuid",
+                def: "TEXT PRIMARY KEY",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "name",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "email",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "token",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "refresh_token",
+                def: "TEXT",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "current_project_id",
+                def: "TEXT",
+                type_affinity: "TEXT",
+            },
+        ],
+        constraints: None,
+    },
+    DbTable {
+        name: "projects",
+        columns: &[
+            DbColumn {
+                name: "id",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "name",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "weekly_limit_hours",
+                def: "REAL",
+                type_affinity: "REAL",
+            },
+            DbColumn {
+                name: "daily_limit_hours",
+                def: "REAL",
+                type_affinity: "REAL",
+            },
+            DbColumn {
+                name: "screenshots_enabled",
+                def: "INTEGER DEFAULT 1",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "total_hours_this_week",
+                def: "REAL",
+                type_affinity: "REAL",
+            },
+        ],
+        constraints: Some("PRIMARY KEY (id)"),
+    },
+    DbTable {
+        name: "sessions",
+        columns: &[
+            DbColumn {
+                name: "id",
+                def: "INTEGER PRIMARY KEY AUTOINCREMENT",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "uuid",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "project_id",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "project_type",
+                def: "TEXT DEFAULT 'Project'",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "duration_minutes",
+                def: "INTEGER DEFAULT 0",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "target_name",
+                def: "TEXT",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "start_time",
+                def: "INTEGER NOT NULL",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "end_time",
+                def: "INTEGER",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "is_active",
+                def: "INTEGER DEFAULT 0",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "idle_seconds",
+                def: "INTEGER DEFAULT 0",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "deducted_seconds",
+                def: "INTEGER DEFAULT 0",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "keyboard_events",
+                def: "INTEGER DEFAULT 0",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "mouse_events",
+                def: "INTEGER DEFAULT 0",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "status",
+                def: "TEXT DEFAULT 'pending'",
+                type_affinity: "TEXT",
+            },
+        ],
+        constraints: None,
+    },
+    DbTable {
+        name: "pending_screenshots",
+        columns: &[
+            DbColumn {
+                name: "id",
+                def: "INTEGER PRIMARY KEY AUTOINCREMENT",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "session_uuid",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "project_id",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "timestamp",
+                def: "INTEGER NOT NULL",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "image_data",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+        ],
+        constraints: None,
+    },
+    DbTable {
+        name: "activity_logs",
+        columns: &[
+            DbColumn {
+                name: "id",
+                def: "INTEGER PRIMARY KEY AUTOINCREMENT",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "session_uuid",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "project_id",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "timestamp",
+                def: "INTEGER NOT NULL",
+                type_affinity: "INTEGER",
+            },
+            DbColumn {
+                name: "app_name",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "window_title",
+                def: "TEXT NOT NULL",
+                type_affinity: "TEXT",
+            },
+            DbColumn {
+                name: "url",
+                def: "TEXT",
+                type_affinity: "TEXT",
+            },
+        ],
+        constraints: None,
+    },
+];
+
+fn baseline(tx: &Transaction) -> Result<(), MigrationError> {
+    for table in BASELINE_SCHEMA {
+        let existing_columns = table_columns(tx, table.name)?;
+
+        if existing_columns.is_empty() {
+            tx.execute(&create_table_sql(table, table.name), [])?;
+            continue;
+        }
+
+        let up_to_date = existing_columns.len() == table.columns.len()
+            && table.columns.iter().all(|col| {
+                existing_columns.iter().any(|(ex_name, ex_type)| {
+                    ex_name == col.name && ex_type.eq_ignore_ascii_case(col.type_affinity)
+                })
+            });
+
+        if !up_to_date {
+            rebuild_table(tx, table, &existing_columns)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rebuilds `table` with the target layout, copying every column the old table shares
+/// with it. Fails if existing rows would end up without a value for a required column.
+fn rebuild_table(
+    tx: &Transaction,
+    table: &DbTable,
+    existing_columns: &[(String, String)],
+) -> Result<(), MigrationError> {
+    let row_count: i64 =
+        tx.query_row(&format!("SELECT COUNT(*) FROM {}", table.name), [], |row| {
+            row.get(0)
+        })?;
+
+    let mut copied = Vec::new();
+    for col in table.columns {
+        if existing_columns
+            .iter()
+            .any(|(ex_name, _)| ex_name == col.name)
+        {
+            copied.push(col);
+        } else if row_count > 0 && col.is_required() {
+            return Err(MigrationError::DataLoss {
+                table: table.name.to_string(),
+                column: col.name.to_string(),
+            });
+        }
+    }
+
+    println!(
+        "DB: Rebuilding table {} ({} rows, {} columns carried over)",
+        table.name,
+        row_count,
+        copied.len()
+    );
+
+    let tmp_name = format!("{}_migrating", table.name);
+    tx.execute(&format!("DROP TABLE IF EXISTS {}", tmp_name), [])?;
+    tx.execute(&create_table_sql(table, &tmp_name), [])?;
+
+    if !copied.is_empty() {
+        let names: Vec<&str> = copied.iter().map(|c| c.name).collect();
+        let selects: Vec<String> = copied
+            .iter()
+            .map(|c| format!("CAST({} AS {})", c.name, c.type_affinity))
+            .collect();
+        tx.execute(
+            &format!(
+                "INSERT INTO {} ({}) SELECT {} FROM {}",
+                tmp_name,
+                names.join(", "),
+                selects.join(", "),
+                table.name
+            ),
+            [],
+        )?;
+    }
+
+    tx.execute(&format!("DROP TABLE {}", table.name), [])?;
+    tx.execute(
+        &format!("ALTER TABLE {} RENAME TO {}", tmp_name, table.name),
+        [],
+    )?;
+    Ok(())
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<(String, String)>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt
+        .query_map([], |row| Ok((row.get(1)?, row.get(2)?)))? // name, type
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(columns)
+}
+
+fn create_table_sql(table: &DbTable, name: &str) -> String {
+    let cols_sql: Vec<String> = table
+        .columns
+        .iter()
+        .map(|c| format!("{} {}", c.name, c.def))
+        .collect();
+
+    format!(
+        "CREATE TABLE {} ({}{})",
+        name,
+        cols_sql.join(", "),
+        table
+            .constraints
+            .map(|c| format!(", {}", c))
+            .unwrap_or_default()
+    )
+}
+
+// v2: screenshots move to the file store. `image_data` becomes nullable so rows written by
+// older releases can be externalized after startup (see `screenshot_store`).
+
+fn screenshot_files(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch(
+        "CREATE TABLE pending_screenshots_migrating (
+             id INTEGER PRIMARY KEY AUTOINCREMENT,
+             session_uuid TEXT NOT NULL,
+             project_id TEXT NOT NULL,
+             timestamp INTEGER NOT NULL,
+             file_path TEXT,
+             file_size INTEGER,
+             content_hash TEXT,
+             image_data TEXT
+         );
+         INSERT INTO pending_screenshots_migrating (id, session_uuid, project_id, timestamp, image_data)
+             SELECT id, session_uuid, project_id, timestamp, image_data FROM pending_screenshots;
+         DROP TABLE pending_screenshots;
+         ALTER TABLE pending_screenshots_migrating RENAME TO pending_screenshots;",
+    )?;
+    Ok(())
+}
+
+// v3: per-screenshot retry bookkeeping for the upload queue, plus a key/value table for
+// local settings.
+
+fn upload_retries(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch(
+        "ALTER TABLE pending_screenshots ADD COLUMN attempts INTEGER DEFAULT 0;
+         ALTER TABLE pending_screenshots ADD COLUMN next_attempt_at INTEGER DEFAULT 0;
+         ALTER TABLE pending_screenshots ADD COLUMN last_error TEXT;
+         CREATE TABLE IF NOT EXISTS settings (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+    )?;
+    Ok(())
+}
+
+// v4: when the access token runs out (epoch ms), so it can be refreshed ahead of time.
+// NULL for users who logged in before this was recorded; they refresh on the first 401.
+
+fn token_expiry(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch("ALTER TABLE users ADD COLUMN token_expires INTEGER;")?;
+    Ok(())
+}
+
+// v5: tokens move to the OS credential store; `users` keeps only a reference to them.
+// Existing plaintext tokens are moved by `credentials::move_plaintext_tokens` once the
+// store is available, since migrations run without it.
+
+fn credential_ref(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch("ALTER TABLE users ADD COLUMN credential_ref TEXT;")?;
+    Ok(())
+}
+
+// v6: how much of each session the server has been sent (`synced_seconds`) and how much of
+// that was already in the last fetched weekly totals (`baseline_seconds`). Sessions synced
+// before this release are fully accounted for on both counts.
+
+fn synced_time(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch(
+        "ALTER TABLE sessions ADD COLUMN synced_seconds INTEGER DEFAULT 0;
+         ALTER TABLE sessions ADD COLUMN baseline_seconds INTEGER DEFAULT 0;
+         UPDATE sessions
+         SET synced_seconds = MAX(0, (COALESCE(end_time, start_time) - start_time) / 1000 - deducted_seconds)
+         WHERE status = 'done';
+         UPDATE sessions SET baseline_seconds = synced_seconds;",
+    )?;
+    Ok(())
+}
+
+// v7: chunks of one continuous run share `parent_run_uuid` (the first chunk's uuid).
+// Older sessions can't be linked after the fact, so each becomes a run of its own.
+// Projects keep the server's chunk policy as JSON.
+
+fn work_runs(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch(
+        "ALTER TABLE sessions ADD COLUMN parent_run_uuid TEXT;
+         UPDATE sessions SET parent_run_uuid = uuid;
+         ALTER TABLE projects ADD COLUMN chunk_policy TEXT;",
+    )?;
+    Ok(())
+}
+
+// v8: pause/resume. A paused session stays active with `paused_at` set; on resume the
+// interval is added to `paused_seconds` and kept in `session_pauses` for the server.
+
+fn session_pauses(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch(
+        "ALTER TABLE sessions ADD COLUMN paused_seconds INTEGER DEFAULT 0;
+         ALTER TABLE sessions ADD COLUMN paused_at INTEGER;
+         CREATE TABLE IF NOT EXISTS session_pauses (
+             id INTEGER PRIMARY KEY AUTOINCREMENT,
+             session_uuid TEXT NOT NULL,
+             start_time INTEGER NOT NULL,
+             end_time INTEGER
+         );
+         CREATE INDEX IF NOT EXISTS idx_session_pauses_session ON session_pauses (session_uuid);",
+    )?;
+    Ok(())
+}
+
+// v9: multi-monitor capture. Projects keep the server's layout policy and each pending
+// screenshot lists the monitors in it (both JSON). Older rows have no monitor data.
+
+fn monitor_metadata(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch(
+        "ALTER TABLE projects ADD COLUMN monitor_layout TEXT;
+         ALTER TABLE pending_screenshots ADD COLUMN monitors TEXT;",
+    )?;
+    Ok(())
+}
+
+// v10: projects keep the server's screenshot schedule as JSON.
+
+fn capture_schedule(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch("ALTER TABLE projects ADD COLUMN capture_schedule TEXT;")?;
+    Ok(())
+}
+
+// v11: projects keep the server's screenshot blur level as JSON.
+
+fn blur_level(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch("ALTER TABLE projects ADD COLUMN blur_level TEXT;")?;
+    Ok(())
+}
+
+// v12: screenshot dHashes, and markers for captures that repeat the previous image.
+
+fn perceptual_hashes(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch(
+        "ALTER TABLE pending_screenshots ADD COLUMN perceptual_hash TEXT;
+         ALTER TABLE pending_screenshots ADD COLUMN unchanged INTEGER DEFAULT 0;",
+    )?;
+    Ok(())
+}
+
+// v13: projects keep the server's screenshot encoding profile as JSON. Pending rows need
+// nothing new: the file extension in `file_path` says how each image was encoded.
+
+fn encoding_profile(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch("ALTER TABLE projects ADD COLUMN encoding_profile TEXT;")?;
+    Ok(())
+}
+
+// v14: each screenshot records the foreground window and the input counts since the
+// previous one. Older rows have none.
+
+fn screenshot_context(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch(
+        "ALTER TABLE pending_screenshots ADD COLUMN app_name TEXT;
+         ALTER TABLE pending_screenshots ADD COLUMN window_title TEXT;
+         ALTER TABLE pending_screenshots ADD COLUMN url TEXT;
+         ALTER TABLE pending_screenshots ADD COLUMN keyboard_events INTEGER DEFAULT 0;
+         ALTER TABLE pending_screenshots ADD COLUMN mouse_events INTEGER DEFAULT 0;",
+    )?;
+    Ok(())
+}
+
+// v15: the screenshots of one capture share a group, and each records the tracked time
+// the capture stood for. Deleting one on review deducts that time once per group. Older
+// rows have neither and are deducted at the current schedule.
+
+fn capture_groups(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch(
+        "ALTER TABLE pending_screenshots ADD COLUMN capture_group TEXT;
+         ALTER TABLE pending_screenshots ADD COLUMN capture_seconds INTEGER;",
+    )?;
+    Ok(())
+}
+
+// v16: a screenshot being uploaded is claimed, so review can't delete it mid-upload.
+
+fn upload_claims(tx: &Transaction) -> Result<(), MigrationError> {
+    tx.execute_batch("ALTER TABLE pending_screenshots ADD COLUMN uploading INTEGER DEFAULT 0;")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    const SESSION_UUID: &str = "session-1";
+    const IMAGE_DATA: &str = "aGVsbG8=";
+
+    /// Tables as an early unversioned release left them: sessions without
+    /// `project_type`, `duration_minutes`, counters or `target_name`, and activity logs
+    /// without `url`.
+    fn create_legacy_schema(conn: &Connection) {
+        conn.execute_batch(
+            "CREATE TABLE users (uuid TEXT PRIMARY KEY, name TEXT NOT NULL, email TEXT NOT NULL, token TEXT NOT NULL, refresh_token TEXT, current_project_id TEXT);
+             CREATE TABLE projects (id TEXT NOT NULL, name TEXT NOT NULL, weekly_limit_hours REAL, daily_limit_hours REAL, PRIMARY KEY (id));
+             CREATE TABLE sessions (id INTEGER PRIMARY KEY AUTOINCREMENT, uuid TEXT NOT NULL, project_id TEXT NOT NULL, start_time INTEGER NOT NULL, end_time INTEGER, is_active INTEGER DEFAULT 0, idle_seconds INTEGER DEFAULT 0, deducted_seconds INTEGER DEFAULT 0, status TEXT DEFAULT 'pending');
+             CREATE TABLE pending_screenshots (id INTEGER PRIMARY KEY AUTOINCREMENT, session_uuid TEXT NOT NULL, project_id TEXT NOT NULL, timestamp INTEGER NOT NULL, image_data TEXT NOT NULL);
+             CREATE TABLE activity_logs (id INTEGER PRIMARY KEY AUTOINCREMENT, session_uuid TEXT NOT NULL, project_id TEXT NOT NULL, timestamp INTEGER NOT NULL, app_name TEXT NOT NULL, window_title TEXT NOT NULL);",
+        )
+        .unwrap();
+    }
+
+    /// Unsynced work, using only columns every schema version has.
+    fn insert_pending_rows(conn: &Connection) {
+        conn.execute(
+            "INSERT INTO sessions (uuid, project_id, start_time, end_time, status) VALUES (?1, 'project-1', 1000, 61000, 'pending')",
+            [SESSION_UUID],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO pending_screenshots (session_uuid, project_id, timestamp, image_data) VALUES (?1, 'project-1', 2000, ?2)",
+            [SESSION_UUID, IMAGE_DATA],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO activity_logs (session_uuid, project_id, timestamp, app_name, window_title) VALUES (?1, 'project-1', 3000, 'Editor', 'main.rs')",
+            [SESSION_UUID],
+        )
+        .unwrap();
+    }
+
+    /// A database file at schema `version` holding pending rows. Version 0 is the legacy
+    /// unversioned layout.
+    fn database_at(version: i64) -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("watchtower.db");
+        let mut conn = Connection::open(&path).unwrap();
+        if version == 0 {
+            create_legacy_schema(&conn);
+        } else {
+            upgrade(&mut conn, 0, version).unwrap();
+        }
+        assert_eq!(current_version(&conn).unwrap(), version);
+        insert_pending_rows(&conn);
+        (dir, path)
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn upgrades_every_past_schema_without_losing_pending_rows() {
+        for version in 0..latest_version() {
+            let (_dir, path) = database_at(version);
+            let conn = db::init_db(&path).unwrap_or_else(|e| panic!("v{}: {}", version, e));
+            assert_eq!(
+                current_version(&conn).unwrap(),
+                latest_version(),
+                "v{}",
+                version
+            );
+
+            let sessions = db::get_pending_sessions(&conn).unwrap();
+            assert_eq!(sessions.len(), 1, "v{}", version);
+            assert_eq!(sessions[0].uuid, SESSION_UUID);
+            assert_eq!(sessions[0].start_time, 1000);
+            assert_eq!(sessions[0].end_time, Some(61000));
+
+            let inline = db::get_inline_screenshot_ids(&conn).unwrap();
+            assert_eq!(inline.len(), 1, "v{}", version);
+            assert_eq!(
+                db::get_inline_screenshot_data(&conn, inline[0]).unwrap(),
+                IMAGE_DATA
+            );
+
+            let logs = db::get_activity_logs_for_session(&conn, SESSION_UUID).unwrap();
+            assert_eq!(logs.len(), 1, "v{}", version);
+            assert_eq!(logs[0].app_name, "Editor");
+        }
+    }
+
+    #[test]
+    fn latest_schema_is_left_as_is() {
+        let (_dir, path) = database_at(latest_version());
+        let conn = db::init_db(&path).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert_eq!(count(&conn, "sessions"), 1);
+        assert_eq!(count(&conn, "pending_screenshots"), 1);
+        assert_eq!(count(&conn, "activity_logs"), 1);
+    }
+
+    #[test]
+    fn failing_migration_refuses_to_start_and_keeps_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("watchtower.db");
+        {
+            // Screenshots without a timestamp can't be carried into the v1 layout
+            let conn = Connection::open(&path).unwrap();
+            create_legacy_schema(&conn);
+            conn.execute_batch(
+                "DROP TABLE pending_screenshots;
+                 CREATE TABLE pending_screenshots (id INTEGER PRIMARY KEY AUTOINCREMENT, session_uuid TEXT NOT NULL, project_id TEXT NOT NULL, image_data TEXT NOT NULL);
+                 INSERT INTO pending_screenshots (session_uuid, project_id, image_data) VALUES ('session-1', 'project-1', 'aGVsbG8=');
+                 INSERT INTO sessions (uuid, project_id, start_time) VALUES ('session-1', 'project-1', 1000);",
+            )
+            .unwrap();
+        }
+
+        match db::init_db(&path) {
+            Err(MigrationError::DataLoss { table, column }) => {
+                assert_eq!(table, "pending_screenshots");
+                assert_eq!(column, "timestamp");
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("migration should have failed"),
+        }
+
+        // The whole baseline step rolled back, including tables rebuilt before the failure
+        let conn = Connection::open(&path).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), 0);
+        assert_eq!(count(&conn, "pending_screenshots"), 1);
+        assert_eq!(count(&conn, "sessions"), 1);
+        assert!(table_columns(&conn, "sessions")
+            .unwrap()
+            .iter()
+            .all(|(name, _)| name != "project_type"));
+    }
+
+    #[test]
+    fn newer_schema_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("watchtower.db");
+        Connection::open(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+
+        match db::init_db(&path) {
+            Err(MigrationError::NewerSchema { found, supported }) => {
+                assert_eq!(found, latest_version() + 1);
+                assert_eq!(supported, latest_version());
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("a newer schema should be refused"),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -9,6 +9,154 @@ pub struct Project {
     pub daily_limit_hours: Option<f64>,
     pub screenshots_enabled: bool,
     pub total_hours_this_week: Option<f64>,
+    /// Set by the organization; overrides the local chunk setting.
+    #[serde(default)]
+    pub chunk_policy: Option<ChunkPolicy>,
+    /// How screenshots cover multiple displays; `None` means `MonitorLayout::default()`.
+    #[serde(default)]
+    pub monitor_layout: Option<MonitorLayout>,
+    /// Set by the organization; overrides the local capture schedule.
+    #[serde(default)]
+    pub capture_schedule: Option<CaptureSchedule>,
+    /// Whole-screenshot blur set by the organization; `None` means no blur.
+    #[serde(default)]
+    pub blur_level: Option<BlurLevel>,
+    /// Set by the organization; overrides the user's choice for this project.
+    #[serde(default)]
+    pub encoding_profile: Option<EncodingProfile>,
+}
+
+/// How a running project session is cut into chunks. Sessions are always split at local
+/// midnight as well, so each one counts towards a single day.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkPolicy {
+    /// Longest chunk in minutes; `None` leaves the length to `split_at_hour`.
+    pub chunk_minutes: Option<u32>,
+    /// Split when the local hour changes.
+    pub split_at_hour: bool,
+}
+
+impl Default for ChunkPolicy {
+    fn default() -> Self {
+        ChunkPolicy {
+            chunk_minutes: Some(10),
+            split_at_hour: true,
+        }
+    }
+}
+
+/// When screenshots are taken while tracking.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum CaptureSchedule {
+    /// Every `interval_seconds`.
+    #[serde(rename_all = "camelCase")]
+    Fixed { interval_seconds: u32 },
+    /// `per_window` screenshots at unpredictable moments in every `window_minutes`.
+    #[serde(rename_all = "camelCase")]
+    Random {
+        per_window: u32,
+        window_minutes: u32,
+    },
+}
+
+impl Default for CaptureSchedule {
+    fn default() -> Self {
+        CaptureSchedule::Fixed {
+            interval_seconds: 120,
+        }
+    }
+}
+
+/// Size and quality trade-off for screenshots; see `encoder::settings`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum EncodingPreset {
+    Thumbnail,
+    #[default]
+    Standard,
+    HighDetail,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Codec {
+    Webp,
+    WebpLossless,
+    Jpeg,
+    Png,
+}
+
+impl Codec {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Webp | Codec::WebpLossless => "webp",
+            Codec::Jpeg => "jpg",
+            Codec::Png => "png",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EncodingProfile {
+    pub preset: EncodingPreset,
+    /// Replaces the preset's codec.
+    #[serde(default, deserialize_with = "known_codec")]
+    pub codec: Option<Codec>,
+}
+
+/// Reads a codec this build doesn't know as `None`, so the preset's codec is used rather
+/// than the whole project failing to parse when the server offers a new one.
+fn known_codec<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Codec>, D::Error> {
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(value.and_then(|value| serde_json::from_value(value).ok()))
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum BlurLevel {
+    #[default]
+    None,
+    /// Layout and colors stay visible, text doesn't.
+    Light,
+    /// Only rough shapes stay visible.
+    Strong,
+}
+
+/// The user's own screenshot privacy rules.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivacySettings {
+    /// Case-insensitive substrings of app names or window titles to blur out.
+    pub denylist: Vec<String>,
+    /// Skip the capture entirely while a denylisted app is in the foreground.
+    pub skip_when_focused: bool,
+}
+
+/// How a capture covers the user's displays.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum MonitorLayout {
+    /// All monitors stitched into one image, positioned as they are arranged.
+    #[default]
+    Composite,
+    /// One screenshot per monitor.
+    PerMonitor,
+}
+
+/// A display as reported at capture time. Position and size are in the desktop
+/// coordinate space the OS uses to arrange monitors.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MonitorInfo {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub is_primary: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -16,8 +164,14 @@ pub struct User {
     pub uuid: String,
     pub name: String,
     pub email: String,
+    // Credentials stay on the Rust side; never serialized to the webview
+    #[serde(skip_serializing)]
     pub token: String,
+    #[serde(skip_serializing)]
     pub refresh_token: Option<String>,
+    /// When `token` expires, in epoch ms.
+    #[serde(default, skip_serializing)]
+    pub token_expires: Option<i64>,
     pub projects: Vec<Project>,
     pub current_project_id: Option<String>,
 }
@@ -44,6 +198,15 @@ pub struct Session {
     pub keyboard_events: i64,
     #[serde(default)]
     pub mouse_events: i64,
+    /// Uuid of the first chunk of the work run this session belongs to.
+    #[serde(default)]
+    pub parent_run_uuid: Option<String>,
+    /// Total of finished pauses; excluded from tracked time like `deducted_seconds`.
+    #[serde(default)]
+    pub paused_seconds: i64,
+    /// Set while the session is paused (epoch ms).
+    #[serde(default)]
+    pub paused_at: Option<i64>,
 }
 
 fn default_project_type() -> String {
@@ -63,6 +226,68 @@ pub struct ActivityLog {
     pub url: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingScreenshot {
+    pub id: i64,
+    pub session_uuid: String,
+    pub project_id: String,
+    pub timestamp: i64,
+    /// File name inside the screenshot store directory.
+    pub file_path: String,
+    pub file_size: i64,
+    pub content_hash: String,
+    /// The monitors the image shows; empty for screenshots taken before this was recorded.
+    pub monitors: Vec<MonitorInfo>,
+    /// 64-bit dHash as hex; `None` for screenshots taken before hashing.
+    pub perceptual_hash: Option<String>,
+    /// A near-duplicate of the session's previous image. No image of its own is sent;
+    /// `file_path` and `content_hash` are those of the earlier image.
+    pub unchanged: bool,
+    pub context: CaptureContext,
+}
+
+/// What the user was doing when a screenshot was taken. With one image per monitor, each
+/// image of the same capture carries the same context.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptureContext {
+    /// The foreground window; all `None` if it couldn't be read or is denylisted.
+    pub app_name: Option<String>,
+    pub window_title: Option<String>,
+    pub url: Option<String>,
+    /// Input events since the previous screenshot.
+    pub keyboard_events: i64,
+    pub mouse_events: i64,
+}
+
+/// The screenshots of one scheduled capture, one per monitor image, share a group.
+pub struct CaptureGroup {
+    pub id: String,
+    /// The tracked time the capture stands for, under the schedule it was taken on.
+    pub seconds: i64,
+}
+
+/// A pending screenshot as shown for review before upload.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenshotPreview {
+    #[serde(flatten)]
+    pub screenshot: PendingScreenshot,
+    /// A small WebP data URL; `None` if the image can't be read.
+    pub thumbnail: Option<String>,
+    /// Epoch ms after which the upload pass may send it.
+    pub uploads_at: i64,
+}
+
+/// A deliberate pause, as opposed to idle time. `end_time` is `None` while it lasts.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PauseInterval {
+    pub start_time: i64,
+    pub end_time: Option<i64>,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SessionPayload {
@@ -78,6 +303,12 @@ pub struct SessionPayload {
     pub deducted_seconds: i64,
     pub keyboard_events: i64,
     pub mouse_events: i64,
+    pub parent_run_uuid: Option<String>,
+    pub paused_seconds: i64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pauses: Vec<PauseInterval>,
+    /// Closed at its last heartbeat after the app died mid-session.
+    pub recovered: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub activity_logs: Option<Vec<ActivityLog>>,
 }
@@ -99,4 +330,6 @@ pub struct SyncSession {
     pub is_active: bool,
     pub idle_seconds: i64,
     pub deducted_seconds: i64,
+    #[serde(default)]
+    pub parent_run_uuid: Option<String>,
 }
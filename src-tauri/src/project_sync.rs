@@ -0,0 +1,41 @@
+use crate::auth;
+use crate::error::WatchtowerError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use tauri::{async_runtime, AppHandle, Runtime};
+
+// Project Sync
+//
+// Keeps limits and weekly totals fresh for the limit checks: projects are re-fetched on
+// a fixed schedule and after every successful session sync, since that is when the
+// server's weekly totals move.
+
+const SYNC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Set while a fetch is in flight so the timer and a session sync don't double up.
+static IS_SYNCING: AtomicBool = AtomicBool::new(false);
+
+pub fn start_project_sync_loop<R: Runtime>(app: AppHandle<R>) {
+    thread::spawn(move || loop {
+        thread::sleep(SYNC_INTERVAL);
+        sync_projects(&app);
+    });
+}
+
+pub fn sync_projects<R: Runtime>(app: &AppHandle<R>) {
+    if IS_SYNCING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let app_handle = app.clone();
+
+    async_runtime::spawn(async move {
+        match auth::refresh_projects(&app_handle).await {
+            Ok(_) => crate::refresh_tray(&app_handle),
+            // Logged out, or switched environments mid-fetch: nothing to update
+            Err(WatchtowerError::NotAuthenticated) | Err(WatchtowerError::EnvironmentChanged) => {}
+            Err(e) => eprintln!("Sync: Project refresh failed: {}", e),
+        }
+        IS_SYNCING.store(false, Ordering::SeqCst);
+    });
+}
@@ -0,0 +1,323 @@
+use crate::db;
+use crate::models::{BlurLevel, MonitorInfo, PrivacySettings};
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use rusqlite::Connection;
+
+// Redaction
+//
+// Runs on each monitor's capture before it is scaled and encoded. The project's blur
+// level applies to the whole image; windows whose app name or title matches the user's
+// denylist are blurred beyond recognition on top of that. Window rectangles come from
+// the OS without stacking information, so a denylisted window behind another one blurs
+// that part of the front window too, which errs on the private side. Listing windows and
+// blurring both run on the capture thread; only the grab itself needs the main thread.
+
+/// Local privacy settings (JSON).
+pub const PRIVACY_SETTING: &str = "privacy";
+
+/// Blur is a downscale by this factor and back up; a larger factor loses more detail.
+const LIGHT_FACTOR: u32 = 4;
+const STRONG_FACTOR: u32 = 12;
+const WINDOW_FACTOR: u32 = 32;
+
+/// A denylisted window, in the desktop coordinates monitors are positioned in.
+pub struct WindowRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct Redaction {
+    pub blur: BlurLevel,
+    pub windows: Vec<WindowRect>,
+}
+
+pub fn privacy_settings(conn: &Connection) -> PrivacySettings {
+    db::get_setting(conn, PRIVACY_SETTING)
+        .ok()
+        .flatten()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Trims patterns and drops empty ones, so a stray blank entry can't match everything.
+pub fn normalize(settings: PrivacySettings) -> PrivacySettings {
+    let mut denylist: Vec<String> = settings
+        .denylist
+        .into_iter()
+        .map(|pattern| pattern.trim().to_string())
+        .filter(|pattern| !pattern.is_empty())
+        .collect();
+    denylist.dedup();
+    PrivacySettings {
+        denylist,
+        ..settings
+    }
+}
+
+/// Case-insensitive substring match against an app name or window title.
+pub fn is_denied(settings: &PrivacySettings, app_name: &str, title: &str) -> bool {
+    let app_name = app_name.to_lowercase();
+    let title = title.to_lowercase();
+    settings.denylist.iter().any(|pattern| {
+        let pattern = pattern.to_lowercase();
+        app_name.contains(&pattern) || title.contains(&pattern)
+    })
+}
+
+/// What to redact in the capture about to be taken, or `None` if it should be skipped
+/// because a denylisted app is in the foreground.
+pub fn prepare(settings: &PrivacySettings, blur: BlurLevel) -> Option<Redaction> {
+    if settings.denylist.is_empty() {
+        return Some(Redaction {
+            blur,
+            windows: Vec::new(),
+        });
+    }
+
+    // Only ask the OS for the foreground window if it could matter
+    if settings.skip_when_focused {
+        if let Ok(active) = active_win_pos_rs::get_active_window() {
+            if skips_capture(settings, &active.app_name, &active.title) {
+                println!("Monitor: Denylisted app in foreground. Skipping capture.");
+                return None;
+            }
+        }
+    }
+
+    let windows = match xcap::Window::all() {
+        Ok(windows) => windows
+            .iter()
+            .filter(|w| !w.is_minimized().unwrap_or(false))
+            .filter_map(|w| {
+                Some(OpenWindow {
+                    app_name: w.app_name().unwrap_or_default(),
+                    title: w.title().unwrap_or_default(),
+                    rect: WindowRect {
+                        x: w.x().ok()?,
+                        y: w.y().ok()?,
+                        width: w.width().ok()?,
+                        height: w.height().ok()?,
+                    },
+                })
+            })
+            .collect(),
+        Err(e) => {
+            // Without window geometry there's no telling what is on screen
+            eprintln!("Monitor: Failed to list windows ({}). Skipping capture.", e);
+            return None;
+        }
+    };
+    Some(Redaction {
+        blur,
+        windows: denied_windows(settings, windows),
+    })
+}
+
+/// A window on screen, as the OS lists it.
+struct OpenWindow {
+    app_name: String,
+    title: String,
+    rect: WindowRect,
+}
+
+/// Whether the capture is skipped while this app is in the foreground.
+fn skips_capture(settings: &PrivacySettings, app_name: &str, title: &str) -> bool {
+    settings.skip_when_focused && is_denied(settings, app_name, title)
+}
+
+fn denied_windows(settings: &PrivacySettings, windows: Vec<OpenWindow>) -> Vec<WindowRect> {
+    windows
+        .into_iter()
+        .filter(|w| is_denied(settings, &w.app_name, &w.title))
+        .map(|w| w.rect)
+        .collect()
+}
+
+/// Blurs `image`, the full-resolution capture of `monitor`, as `redaction` says.
+pub fn apply(image: &mut RgbaImage, monitor: &MonitorInfo, redaction: &Redaction) {
+    let (width, height) = image.dimensions();
+    match redaction.blur {
+        BlurLevel::None => {}
+        BlurLevel::Light => blur_region(image, 0, 0, width, height, LIGHT_FACTOR),
+        BlurLevel::Strong => blur_region(image, 0, 0, width, height, STRONG_FACTOR),
+    }
+
+    // Captures are in physical pixels, monitor bounds may be logical (HiDPI)
+    let scale_x = width as f64 / monitor.width.max(1) as f64;
+    let scale_y = height as f64 / monitor.height.max(1) as f64;
+    for window in &redaction.windows {
+        let left = (window.x - monitor.x) as f64 * scale_x;
+        let top = (window.y - monitor.y) as f64 * scale_y;
+        let right = left + window.width as f64 * scale_x;
+        let bottom = top + window.height as f64 * scale_y;
+
+        // Clip to this monitor; windows elsewhere end up empty
+        let x0 = left.floor().clamp(0.0, width as f64) as u32;
+        let y0 = top.floor().clamp(0.0, height as f64) as u32;
+        let x1 = right.ceil().clamp(0.0, width as f64) as u32;
+        let y1 = bottom.ceil().clamp(0.0, height as f64) as u32;
+        if x1 > x0 && y1 > y0 {
+            blur_region(image, x0, y0, x1 - x0, y1 - y0, WINDOW_FACTOR);
+        }
+    }
+}
+
+fn blur_region(image: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, factor: u32) {
+    let region = imageops::crop_imm(image, x, y, width, height).to_image();
+    let small = imageops::resize(
+        &region,
+        (width / factor).max(1),
+        (height / factor).max(1),
+        FilterType::Triangle,
+    );
+    let blurred = imageops::resize(&small, width, height, FilterType::Triangle);
+    imageops::replace(image, &blurred, x as i64, y as i64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn settings(skip_when_focused: bool) -> PrivacySettings {
+        PrivacySettings {
+            denylist: vec!["KeePass".to_string(), "bank".to_string()],
+            skip_when_focused,
+        }
+    }
+
+    fn window(app_name: &str, title: &str, x: i32, y: i32) -> OpenWindow {
+        OpenWindow {
+            app_name: app_name.to_string(),
+            title: title.to_string(),
+            rect: WindowRect {
+                x,
+                y,
+                width: 400,
+                height: 300,
+            },
+        }
+    }
+
+    #[test]
+    fn denylisted_focus_skips_the_capture() {
+        assert!(skips_capture(&settings(true), "keepassxc", "Passwords"));
+        assert!(skips_capture(&settings(true), "Firefox", "My Bank - Login"));
+        assert!(!skips_capture(&settings(true), "Firefox", "Docs"));
+        // Without the option it is only blurred
+        assert!(!skips_capture(&settings(false), "keepassxc", "Passwords"));
+    }
+
+    #[test]
+    fn only_denylisted_windows_are_redacted() {
+        let windows = vec![
+            window("Code", "main.rs", 0, 0),
+            window("KeePassXC", "Passwords", 100, 50),
+            window("Firefox", "Online banking", -500, 20),
+        ];
+        let rects = denied_windows(&settings(false), windows);
+        let origins: Vec<_> = rects.iter().map(|r| (r.x, r.y)).collect();
+        assert_eq!(origins, [(100, 50), (-500, 20)]);
+    }
+
+    fn monitor(x: i32, y: i32, width: u32, height: u32) -> MonitorInfo {
+        MonitorInfo {
+            name: "DP-1".to_string(),
+            x,
+            y,
+            width,
+            height,
+            is_primary: true,
+        }
+    }
+
+    /// One-pixel black and white squares; any blur turns them gray.
+    fn checkerboard(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| match (x + y) % 2 {
+            0 => Rgba([0, 0, 0, 255]),
+            _ => Rgba([255, 255, 255, 255]),
+        })
+    }
+
+    /// The pixels `apply` changed, as (x0, y0, x1, y1) bounds; `None` if it changed none.
+    fn changed_bounds(before: &RgbaImage, after: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for (x, y, pixel) in after.enumerate_pixels() {
+            if pixel != before.get_pixel(x, y) {
+                bounds = Some(match bounds {
+                    None => (x, y, x + 1, y + 1),
+                    Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1)),
+                });
+            }
+        }
+        bounds
+    }
+
+    fn redact_window(monitor: &MonitorInfo, image: &RgbaImage, rect: WindowRect) -> RgbaImage {
+        let mut redacted = image.clone();
+        let redaction = Redaction {
+            blur: BlurLevel::None,
+            windows: vec![rect],
+        };
+        apply(&mut redacted, monitor, &redaction);
+        redacted
+    }
+
+    #[test]
+    fn window_blur_stays_inside_the_window() {
+        // Monitor right of the primary, window fully on it
+        let monitor = monitor(1920, 0, 640, 480);
+        let image = checkerboard(640, 480);
+        let rect = WindowRect {
+            x: 2020,
+            y: 50,
+            width: 200,
+            height: 100,
+        };
+        let redacted = redact_window(&monitor, &image, rect);
+        assert_eq!(changed_bounds(&image, &redacted), Some((100, 50, 300, 150)));
+    }
+
+    #[test]
+    fn window_blur_is_clipped_to_the_monitor() {
+        let monitor = monitor(0, 0, 640, 480);
+        let image = checkerboard(640, 480);
+        // Hangs off the top left corner
+        let rect = WindowRect {
+            x: -100,
+            y: -40,
+            width: 300,
+            height: 200,
+        };
+        let redacted = redact_window(&monitor, &image, rect);
+        assert_eq!(changed_bounds(&image, &redacted), Some((0, 0, 200, 160)));
+
+        // On another monitor entirely
+        let rect = WindowRect {
+            x: 700,
+            y: 0,
+            width: 300,
+            height: 200,
+        };
+        let redacted = redact_window(&monitor, &image, rect);
+        assert_eq!(changed_bounds(&image, &redacted), None);
+    }
+
+    #[test]
+    fn window_blur_scales_to_hidpi_captures() {
+        // Logical 320x240, captured at twice that
+        let monitor = monitor(0, 0, 320, 240);
+        let image = checkerboard(640, 480);
+        let rect = WindowRect {
+            x: 100,
+            y: 40,
+            width: 100,
+            height: 60,
+        };
+        let redacted = redact_window(&monitor, &image, rect);
+        assert_eq!(changed_bounds(&image, &redacted), Some((200, 80, 400, 200)));
+    }
+}
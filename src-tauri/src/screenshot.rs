@@ -1,44 +1,246 @@
+use crate::activity;
 use crate::api;
+use crate::capture_schedule::{self, Scheduler};
 use crate::db;
+use crate::encoder;
+use crate::error::WatchtowerError;
 use crate::idle::IdleState;
-use crate::models::SessionPayload;
+use crate::models::{
+    CaptureContext, CaptureGroup, CaptureSchedule, EncodingProfile, MonitorInfo, MonitorLayout,
+    SessionPayload,
+};
+use crate::project_sync;
+use crate::redaction::{self, Redaction};
+use crate::screenshot_store::{self, LastStored};
+use crate::upload_queue;
 use crate::AppState;
-use base64::{engine::general_purpose, Engine as _};
-use image::imageops::FilterType;
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use rand::Rng;
 use rusqlite::Connection;
 use serde_json::json;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tauri::{async_runtime, AppHandle, Manager, Runtime};
 
-pub fn capture_screen() -> Result<String, String> {
-    use xcap::Monitor;
+/// An encoded screenshot and the monitors it shows.
+pub struct Capture {
+    pub bytes: Vec<u8>,
+    /// File extension for the codec the bytes are in.
+    pub extension: &'static str,
+    pub monitors: Vec<MonitorInfo>,
+    /// dHash of the image as encoded (after redaction and layout).
+    pub perceptual_hash: u64,
+}
 
-    let monitors = Monitor::all().map_err(|e| e.to_string())?;
-    // Prefer the first monitor or primary
-    let monitor = monitors.first().ok_or("No monitor found")?;
+/// Captures whose dHash differs from the previous image in at most this many of 64 bits
+/// count as unchanged (a ticking clock, a blinking cursor).
+const UNCHANGED_MAX_DISTANCE: u32 = 2;
+
+/// Grabs every monitor at full resolution. The OS capture APIs want the main thread, so
+/// this is all that runs there. The images are unredacted: `redact` them first thing.
+pub fn grab_monitors() -> Result<Vec<(MonitorInfo, RgbaImage)>, WatchtowerError> {
+    use xcap::Monitor;
 
-    // xcap capture returns an image::RgbaImage buffer in recent versions
-    let image_buffer = monitor.capture_image().map_err(|e| e.to_string())?;
+    let monitors = Monitor::all().map_err(|e| WatchtowerError::capture(e.to_string()))?;
+
+    // xcap capture returns an image::RgbaImage buffer in recent versions.
+    // A monitor that fails (e.g. unplugged mid-capture) doesn't cost us the others.
+    let mut shots = Vec::new();
+    for monitor in &monitors {
+        let info = MonitorInfo {
+            name: monitor.name().unwrap_or_default(),
+            x: monitor.x().unwrap_or_default(),
+            y: monitor.y().unwrap_or_default(),
+            width: monitor.width().unwrap_or_default(),
+            height: monitor.height().unwrap_or_default(),
+            is_primary: monitor.is_primary().unwrap_or_default(),
+        };
+        match monitor.capture_image() {
+            Ok(image) => shots.push((info, image)),
+            Err(e) => eprintln!("Monitor: Failed to capture {}: {}", info.name, e),
+        }
+    }
+    if shots.is_empty() {
+        return Err(WatchtowerError::capture("No monitor found"));
+    }
+    Ok(shots)
+}
 
-    // Convert to DynamicImage for resizing
-    let dynamic_image = image::DynamicImage::ImageRgba8(image_buffer);
+/// Blurs grabbed monitors as `redaction` says, on the capture thread.
+pub fn redact(
+    mut shots: Vec<(MonitorInfo, RgbaImage)>,
+    redaction: &Redaction,
+) -> Vec<(MonitorInfo, RgbaImage)> {
+    for (info, image) in &mut shots {
+        redaction::apply(image, info, redaction);
+    }
+    shots
+}
 
-    // Resize (e.g., width 800, maintain aspect ratio)
-    let resized = dynamic_image.resize(800, 600, FilterType::Lanczos3);
+/// Lays out, scales and encodes grabbed monitors as `layout` and `profile` say.
+pub fn encode_captures(
+    shots: Vec<(MonitorInfo, RgbaImage)>,
+    layout: MonitorLayout,
+    profile: &EncodingProfile,
+) -> Result<Vec<Capture>, WatchtowerError> {
+    let settings = encoder::settings(profile);
+    match layout {
+        MonitorLayout::PerMonitor => shots
+            .into_iter()
+            .map(|(info, image)| {
+                // Resize to the profile's box, maintaining aspect ratio
+                let resized = encoder::fit(
+                    image,
+                    settings.max_width,
+                    settings.max_height,
+                    settings.filter,
+                );
+                Ok(Capture {
+                    bytes: encoder::encode(&resized, &settings)?,
+                    extension: settings.codec.extension(),
+                    monitors: vec![info],
+                    perceptual_hash: dhash(&resized),
+                })
+            })
+            .collect(),
+        MonitorLayout::Composite => {
+            let composite = stitch(&shots, &settings);
+            Ok(vec![Capture {
+                bytes: encoder::encode(&composite, &settings)?,
+                extension: settings.codec.extension(),
+                monitors: shots.into_iter().map(|(info, _)| info).collect(),
+                perceptual_hash: dhash(&composite),
+            }])
+        }
+    }
+}
 
-    let (w, h) = (resized.width(), resized.height());
-    // Convert resized back to rgba8
-    let raw_data = resized.to_rgba8().into_raw();
+/// Places every capture where its monitor sits on the desktop, scaled down as a whole so
+/// relative sizes and offsets survive. Gaps between monitors stay black.
+fn stitch(shots: &[(MonitorInfo, RgbaImage)], settings: &encoder::Settings) -> RgbaImage {
+    let left = shots.iter().map(|(m, _)| m.x).min().unwrap_or(0);
+    let top = shots.iter().map(|(m, _)| m.y).min().unwrap_or(0);
+    let right = shots
+        .iter()
+        .map(|(m, _)| m.x + m.width as i32)
+        .max()
+        .unwrap_or(0);
+    let bottom = shots
+        .iter()
+        .map(|(m, _)| m.y + m.height as i32)
+        .max()
+        .unwrap_or(0);
+    let desktop_w = (right - left).max(1) as f64;
+    let desktop_h = (bottom - top).max(1) as f64;
+
+    let max_w = (settings.max_width * shots.len() as u32) as f64;
+    let scale = (max_w / desktop_w)
+        .min(settings.max_height as f64 / desktop_h)
+        .min(1.0);
+    let mut canvas = RgbaImage::from_pixel(
+        ((desktop_w * scale).round() as u32).max(1),
+        ((desktop_h * scale).round() as u32).max(1),
+        Rgba([0, 0, 0, 255]),
+    );
+
+    // Captures are in physical pixels, monitor bounds may be logical (HiDPI); each image
+    // is scaled to its monitor's bounds rather than by its own pixel size.
+    for (monitor, image) in shots {
+        let w = ((monitor.width as f64 * scale).round() as u32).max(1);
+        let h = ((monitor.height as f64 * scale).round() as u32).max(1);
+        let resized = imageops::resize(image, w, h, settings.filter);
+        let x = ((monitor.x - left) as f64 * scale).round() as i64;
+        let y = ((monitor.y - top) as f64 * scale).round() as i64;
+        imageops::replace(&mut canvas, &resized, x, y);
+    }
+    canvas
+}
 
-    let encoder = webp::Encoder::from_rgba(&raw_data, w, h);
-    let webp_memory = encoder.encode(75.0); // 75% quality
+/// Difference hash: shrink to 9x8 grayscale and set one bit per pixel that is brighter
+/// than its right-hand neighbour. Similar images differ in few bits.
+fn dhash(image: &RgbaImage) -> u64 {
+    let gray = imageops::grayscale(image);
+    let small = imageops::resize(&gray, 9, 8, FilterType::Triangle);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
 
-    // Convert to Base64
-    let b64 = general_purpose::STANDARD.encode(&*webp_memory);
+/// Stores a capture, or records it as unchanged if it looks like the last image stored
+/// for the same session and monitors.
+#[allow(clippy::too_many_arguments)]
+fn save_capture(
+    last_stored: &Mutex<HashMap<String, LastStored>>,
+    conn: &Connection,
+    store_dir: &std::path::Path,
+    session_uuid: &str,
+    project_id: &str,
+    capture: Capture,
+    context: &CaptureContext,
+    group: &CaptureGroup,
+) -> Result<(), WatchtowerError> {
+    let key = capture
+        .monitors
+        .iter()
+        .map(|m| m.name.as_str())
+        .collect::<Vec<_>>()
+        .join("|");
+    let mut last_stored = last_stored.lock().unwrap();
+
+    let repeated = last_stored.get(&key).filter(|last| {
+        last.session_uuid == session_uuid
+            && (last.perceptual_hash ^ capture.perceptual_hash).count_ones()
+                <= UNCHANGED_MAX_DISTANCE
+    });
+    if let Some(last) = repeated {
+        db::save_pending_screenshot(
+            conn,
+            session_uuid,
+            project_id,
+            &last.stored,
+            &capture.monitors,
+            capture.perceptual_hash,
+            true,
+            context,
+            group,
+        )?;
+        println!("Monitor: Screen unchanged, recorded marker.");
+        return Ok(());
+    }
 
-    Ok(b64)
+    let stored = screenshot_store::store(store_dir, &capture.bytes, capture.extension)?;
+    db::save_pending_screenshot(
+        conn,
+        session_uuid,
+        project_id,
+        &stored,
+        &capture.monitors,
+        capture.perceptual_hash,
+        false,
+        context,
+        group,
+    )?;
+    println!("Monitor: Screenshot saved ({} bytes).", stored.size);
+    // Markers always compare against a stored image, so slow drift still gets captured
+    last_stored.insert(
+        key,
+        LastStored {
+            session_uuid: session_uuid.to_string(),
+            perceptual_hash: capture.perceptual_hash,
+            stored,
+        },
+    );
+    Ok(())
 }
 
 pub fn start_screenshot_monitor<R: Runtime>(app: AppHandle<R>) {
@@ -50,7 +252,13 @@ pub fn start_screenshot_monitor<R: Runtime>(app: AppHandle<R>) {
     });
 }
 
-pub fn start_capture_loop<R: Runtime>(app: AppHandle<R>, state: Arc<IdleState>) {
+/// Runs while monitoring is on, capturing on the selected project's schedule. `rng`
+/// drives random schedules.
+pub fn start_capture_loop<R: Runtime, G: Rng + Send + 'static>(
+    app: AppHandle<R>,
+    state: Arc<IdleState>,
+    rng: G,
+) {
     // Ensure only one loop runs
     if state
         .is_capture_loop_running
@@ -59,17 +267,22 @@ pub fn start_capture_loop<R: Runtime>(app: AppHandle<R>, state: Arc<IdleState>)
         return;
     }
 
+    // Input from before monitoring (re)started doesn't belong to the first screenshot
+    state.reset_capture_counts();
+
     let app_monitor = app.clone();
     let state_monitor = state.clone();
 
     thread::spawn(move || {
         println!("Monitor: Starting Capture Loop");
-        // Use a fixed 2-minute interval (120 seconds)
-        let mut next_capture_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            + 120;
+        let mut scheduler = Scheduler::new(rng);
+        let mut next_capture_time = scheduler.next_capture(
+            &current_schedule(&app_monitor),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        );
 
         loop {
             thread::sleep(Duration::from_secs(10));
@@ -93,44 +306,116 @@ pub fn start_capture_loop<R: Runtime>(app: AppHandle<R>, state: Arc<IdleState>)
 
             if now >= next_capture_time {
                 println!("Monitor: Time to capture screenshot");
+                capture_once(&app_monitor);
 
-                let app_inner = app_monitor.clone();
-
-                let _ = app_monitor.run_on_main_thread(move || {
-                    let app_state = app_inner.state::<AppState>();
-                    let db_path = app_state.db_path.lock().unwrap().clone();
-                    if let Ok(conn) = Connection::open(&db_path) {
-                        if let Ok(Some(user)) = db::get_user(&conn) {
-                            if let Some(pid) = user.current_project_id {
-                                if let Some(project) = user.projects.iter().find(|p| p.id == pid) {
-                                    if !project.screenshots_enabled {
-                                        println!("Monitor: Screenshots disabled for project {}. Skipping capture.", pid);
-                                        return;
-                                    }
-                                }
-                                if let Ok(Some(session)) = db::get_active_session(&conn, &pid) {
-                                    match capture_screen() {
-                                        Ok(b64) => {
-                                            let _ = db::save_pending_screenshot(
-                                                &conn,
-                                                &session.uuid,
-                                                &pid,
-                                                &b64,
-                                            );
-                                            println!("Monitor: Screenshot saved.");
-                                        }
-                                        Err(e) => eprintln!("Monitor: Capture failed: {}", e),
-                                    }
-                                }
-                            }
-                        }
-                    }
-                });
+                next_capture_time = scheduler.next_capture(&current_schedule(&app_monitor), now);
+            }
+        }
+    });
+}
+
+/// Takes and stores one scheduled capture of the selected project. Runs on the capture
+/// thread; only the grab itself is handed to the main thread, and the UI waits for
+/// nothing else.
+fn capture_once<R: Runtime>(app: &AppHandle<R>) {
+    let app_state = app.state::<AppState>();
+    let db_path = app_state.db_path.lock().unwrap().clone();
+    let Ok(conn) = Connection::open(&db_path) else {
+        return;
+    };
+    let Ok(Some(user)) = db::get_user(&conn) else {
+        return;
+    };
+    let Some(pid) = user.current_project_id.clone() else {
+        return;
+    };
+    let project = user.projects.iter().find(|p| p.id == pid);
+    if let Some(project) = project {
+        if !project.screenshots_enabled {
+            println!(
+                "Monitor: Screenshots disabled for project {}. Skipping capture.",
+                pid
+            );
+            return;
+        }
+    }
+    let layout = project.and_then(|p| p.monitor_layout).unwrap_or_default();
+    let blur = project.and_then(|p| p.blur_level).unwrap_or_default();
+    let profile = encoder::encoding_profile(&conn, project, &pid);
+    let Ok(Some(session)) = db::get_active_session(&conn, &pid) else {
+        return;
+    };
+    let privacy = redaction::privacy_settings(&conn);
+    let schedule = capture_schedule::capture_schedule(&conn, project);
+    let group = CaptureGroup {
+        id: uuid::Uuid::new_v4().to_string(),
+        seconds: capture_schedule::seconds_per_capture(&schedule) as i64,
+    };
+
+    // Windows and the foreground app are read just before the grab, off the main thread
+    let Some(redaction) = redaction::prepare(&privacy, blur) else {
+        return;
+    };
+    let context = activity::capture_context(&app_state.idle_state, &privacy);
 
-                next_capture_time = now + 120;
+    let (sender, receiver) = mpsc::channel();
+    let dispatched = app.run_on_main_thread(move || {
+        let _ = sender.send(grab_monitors());
+    });
+    if dispatched.is_err() {
+        return;
+    }
+    let Ok(grabbed) = receiver.recv() else {
+        return;
+    };
+
+    // Nothing unredacted gets past this point
+    let grabbed = grabbed.map(|shots| redact(shots, &redaction));
+    match grabbed.and_then(|shots| encode_captures(shots, layout, &profile)) {
+        Ok(captures) => {
+            let store_dir = screenshot_store::dir_for(&db_path);
+            let mut stored = false;
+            for capture in captures {
+                match save_capture(
+                    &app_state.last_stored,
+                    &conn,
+                    &store_dir,
+                    &session.uuid,
+                    &pid,
+                    capture,
+                    &context,
+                    &group,
+                ) {
+                    Ok(()) => stored = true,
+                    Err(e) => eprintln!("Monitor: Failed to store screenshot: {}", e),
+                }
+            }
+            // Input counts belong to the first screenshot that actually got stored
+            if stored {
+                app_state
+                    .idle_state
+                    .commit_capture_counts(context.keyboard_events, context.mouse_events);
             }
         }
+        Err(e) => eprintln!("Monitor: Capture failed: {}", e),
+    }
+}
+
+/// The schedule of the selected project, read fresh so a project switch or a new
+/// setting applies from the next capture on.
+fn current_schedule<R: Runtime>(app: &AppHandle<R>) -> CaptureSchedule {
+    let state = app.state::<AppState>();
+    let db_path = state.db_path.lock().unwrap().clone();
+    let Ok(conn) = Connection::open(&db_path) else {
+        return CaptureSchedule::default();
+    };
+    let user = db::get_user(&conn).ok().flatten();
+    let project = user.as_ref().and_then(|u| {
+        u.projects
+            .iter()
+            .find(|p| Some(&p.id) == u.current_project_id.as_ref())
     });
+    capture_schedule::capture_schedule(&conn, project)
 }
 
 pub fn upload_pending_screenshots<R: Runtime>(app: &AppHandle<R>) {
@@ -139,33 +424,55 @@ pub fn upload_pending_screenshots<R: Runtime>(app: &AppHandle<R>) {
 
     async_runtime::spawn(async move {
         // 1. Fetch Data (Blocking DB op)
-        let app_state = app_handle.state::<AppState>();
-        let db_path = app_state.db_path.lock().unwrap().clone();
+        let (environment, db_path) = api::environment_and_db_path(&app_handle);
 
         let db_path_fetch = db_path.clone();
         let data_op = async_runtime::spawn_blocking(move || {
             if let Ok(conn) = Connection::open(&db_path_fetch) {
                 let _user = db::get_user(&conn).ok().flatten();
-                let pending_sc = db::get_pending_screenshots(&conn).unwrap_or_default();
+                let now = chrono::Local::now().timestamp_millis();
+                let taken_before = now - upload_queue::review_delay_secs(&conn) * 1000;
+                let pending_sc =
+                    db::get_uploadable_screenshots(&conn, now, taken_before).unwrap_or_default();
+                let concurrency = upload_queue::concurrency(&conn);
                 let pending_sess = db::get_pending_sessions(&conn).unwrap_or_default();
 
                 let mut session_logs = std::collections::HashMap::new();
+                let mut session_pauses = std::collections::HashMap::new();
                 for sess in &pending_sess {
                     if let Ok(logs) = db::get_activity_logs_for_session(&conn, &sess.uuid) {
                         session_logs.insert(sess.uuid.clone(), logs);
                     }
+                    if let Ok(pauses) = db::get_session_pauses(&conn, &sess.uuid) {
+                        session_pauses.insert(sess.uuid.clone(), pauses);
+                    }
                 }
 
-                Ok((_user, pending_sc, pending_sess, session_logs))
+                Ok((
+                    _user,
+                    pending_sc,
+                    pending_sess,
+                    session_logs,
+                    session_pauses,
+                    concurrency,
+                ))
             } else {
                 Err("Failed to open DB")
             }
         })
         .await;
 
-        if let Ok(Ok((_, pending_sc, pending_sess, session_logs))) = data_op {
+        if let Ok(Ok((
+            _,
+            pending_sc,
+            pending_sess,
+            session_logs,
+            mut session_pauses,
+            concurrency,
+        ))) = data_op
+        {
             // 2. Bulk Session Sync
-            let mut synced_session_uuids = Vec::new();
+            let mut synced_sessions = Vec::new();
             if !pending_sess.is_empty() {
                 println!("Monitor: Syncing {} sessions...", pending_sess.len());
                 let endpoint = "/desktop/sessions";
@@ -203,6 +510,10 @@ pub fn upload_pending_screenshots<R: Runtime>(app: &AppHandle<R>) {
                             deducted_seconds: s.deducted_seconds,
                             keyboard_events: s.keyboard_events,
                             mouse_events: s.mouse_events,
+                            parent_run_uuid: s.parent_run_uuid.clone(),
+                            paused_seconds: s.paused_seconds,
+                            pauses: session_pauses.remove(&s.uuid).unwrap_or_default(),
+                            recovered: s.status == "recovered",
                             activity_logs: if logs.is_empty() { None } else { Some(logs) },
                         }
                     })
@@ -210,15 +521,18 @@ pub fn upload_pending_screenshots<R: Runtime>(app: &AppHandle<R>) {
 
                 let payload = json!(payload_data);
 
-                match api::request(&app_handle, reqwest::Method::POST, endpoint, Some(&payload))
-                    .await
-                {
+                let result = match api::ensure_environment(&app_handle, &environment) {
+                    Ok(()) => {
+                        api::request(&app_handle, reqwest::Method::POST, endpoint, Some(&payload))
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+                match result {
                     Ok(response) => {
                         if response.status().is_success() {
                             println!("Monitor: Bulk session sync success.");
-                            for s in &pending_sess {
-                                synced_session_uuids.push((s.uuid.clone(), s.is_active));
-                            }
+                            synced_sessions = pending_sess.clone();
                         } else {
                             eprintln!(
                                 "Monitor: Bulk session sync failed. Status: {}",
@@ -230,97 +544,61 @@ pub fn upload_pending_screenshots<R: Runtime>(app: &AppHandle<R>) {
                 }
             }
 
-            // 3. Parallel Screenshot Upload (One by One)
-            let mut uploaded_screenshot_ids = Vec::new();
-            let mut screenshot_handles = Vec::new();
-
-            if !pending_sc.is_empty() {
-                println!(
-                    "Monitor: Uploading {} screenshots individually...",
-                    pending_sc.len()
-                );
-
-                for (id, session_uuid, project_id, timestamp, image_data) in pending_sc {
-                    let app_inner = app_handle.clone();
-
-                    let task = async_runtime::spawn(async move {
-                        println!(
-                            "Monitor: Uploading screenshot {} for session {}",
-                            id, session_uuid
-                        );
-
-                        let payload = json!({
-                            "sessionUuid": session_uuid,
-                            "projectId": project_id,
-                            "timestamp": timestamp,
-                            "image": image_data,
-                            "fileExt": "webp"
-                        });
-
-                        match api::request(
-                            &app_inner,
-                            reqwest::Method::POST,
-                            "/desktop/screenshots",
-                            Some(&payload),
-                        )
-                        .await
-                        {
-                            Ok(response) => {
-                                if response.status().is_success() {
-                                    println!("Monitor: Upload success for {}", id);
-                                    Some(id)
-                                } else {
-                                    eprintln!(
-                                        "Monitor: Upload failed for {}. Status: {}",
-                                        id,
-                                        response.status()
-                                    );
-                                    None
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("Monitor: Request error for {}: {}", id, e);
-                                None
-                            }
-                        }
-                    });
-                    screenshot_handles.push(task);
-                }
-            }
-
-            // Collect results
-            for handle in screenshot_handles {
-                if let Ok(Some(id)) = handle.await {
-                    uploaded_screenshot_ids.push(id);
-                }
-            }
+            // 3. Screenshot Upload (bounded concurrency, see upload_queue)
+            let uploaded_screenshot_ids = if pending_sc.is_empty() {
+                Vec::new()
+            } else {
+                upload_queue::drain(
+                    &app_handle,
+                    pending_sc,
+                    &environment,
+                    screenshot_store::dir_for(&db_path),
+                    db_path.clone(),
+                    concurrency,
+                )
+                .await
+            };
 
             // 4. Batch Update/Delete (Blocking DB op)
-            if !synced_session_uuids.is_empty() || !uploaded_screenshot_ids.is_empty() {
+            let sessions_synced = !synced_sessions.is_empty();
+            if sessions_synced || !uploaded_screenshot_ids.is_empty() {
                 let db_path_sync = db_path.clone();
                 let _ = async_runtime::spawn_blocking(move || {
+                    let store_dir = screenshot_store::dir_for(&db_path_sync);
                     if let Ok(conn) = Connection::open(&db_path_sync) {
+                        let mut released_files = Vec::new();
                         // Use a transaction for safety
                         if let Ok(tx) = conn.unchecked_transaction() {
-                            for (uuid, is_active) in synced_session_uuids {
-                                if !is_active {
-                                    let _ = tx.execute(
-                                        "UPDATE sessions SET status = 'done' WHERE uuid = ?1",
-                                        [&uuid],
-                                    );
-                                    let _ = db::delete_activity_logs_for_session(&tx, &uuid);
+                            for session in &synced_sessions {
+                                let _ = db::mark_session_synced(&tx, session);
+                                if !session.is_active {
+                                    let _ =
+                                        db::delete_activity_logs_for_session(&tx, &session.uuid);
+                                    let _ = db::delete_session_pauses(&tx, &session.uuid);
                                 }
                             }
                             for id in uploaded_screenshot_ids {
-                                let _ = tx
-                                    .execute("DELETE FROM pending_screenshots WHERE id = ?1", [id]);
+                                if let Ok(Some(file_path)) = db::delete_pending_screenshot(&tx, id)
+                                {
+                                    released_files.push(file_path);
+                                }
                             }
                             let _ = tx.commit();
                         }
+                        // Files go only after the rows are gone for good
+                        for file_path in released_files {
+                            let _ = screenshot_store::release(&conn, &store_dir, &file_path);
+                        }
                     }
                 })
                 .await;
             }
+
+            // Weekly totals on the server just changed. Fetched only now so the synced
+            // time recorded above is matched against them.
+            if sessions_synced {
+                project_sync::sync_projects(&app_handle);
+            }
         } else {
             // Quiet failure
         }
@@ -332,8 +610,7 @@ pub fn sync_daily_sessions<R: Runtime>(app: &AppHandle<R>) {
     let app_handle = app.clone();
 
     async_runtime::spawn(async move {
-        let app_state = app_handle.state::<AppState>();
-        let db_path = app_state.db_path.lock().unwrap().clone();
+        let (environment, db_path) = api::environment_and_db_path(&app_handle);
 
         let endpoint = "/desktop/sessions/today";
         match api::request::<R, ()>(&app_handle, reqwest::Method::GET, endpoint, None).await {
@@ -342,6 +619,11 @@ pub fn sync_daily_sessions<R: Runtime>(app: &AppHandle<R>) {
                     if let Ok(server_sessions) =
                         response.json::<Vec<crate::models::SyncSession>>().await
                     {
+                        // Don't merge another backend's sessions into this database
+                        if let Err(e) = api::ensure_environment(&app_handle, &environment) {
+                            eprintln!("Monitor: Discarding daily sessions: {}", e);
+                            return;
+                        }
                         println!(
                             "Monitor: Fetched {} sessions from server.",
                             server_sessions.len()
@@ -393,6 +675,8 @@ pub fn sync_daily_sessions<R: Runtime>(app: &AppHandle<R>) {
                             }
                         })
                         .await;
+                        // Imported time is already in the server's weekly totals
+                        project_sync::sync_projects(&app_handle);
                     }
                 } else {
                     eprintln!(
@@ -405,3 +689,232 @@ pub fn sync_daily_sessions<R: Runtime>(app: &AppHandle<R>) {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use crate::models::Codec;
+
+    fn monitor(name: &str, x: i32, y: i32, width: u32, height: u32) -> MonitorInfo {
+        MonitorInfo {
+            name: name.to_string(),
+            x,
+            y,
+            width,
+            height,
+            is_primary: x == 0 && y == 0,
+        }
+    }
+
+    /// A screen-like frame of flat blocks, like windows and panels; `seed` picks the shades.
+    fn frame(seed: u32) -> RgbaImage {
+        RgbaImage::from_fn(960, 540, |x, y| {
+            let block = (x / 80) * 31 + (y / 60) * 17 + seed * 7919;
+            let shade = (block.wrapping_mul(2654435761) >> 24) as u8;
+            Rgba([shade, shade / 2, 255 - shade, 255])
+        })
+    }
+
+    fn distance(a: &RgbaImage, b: &RgbaImage) -> u32 {
+        (dhash(a) ^ dhash(b)).count_ones()
+    }
+
+    #[test]
+    fn identical_frames_hash_the_same() {
+        assert_eq!(distance(&frame(1), &frame(1)), 0);
+    }
+
+    #[test]
+    fn one_changed_pixel_counts_as_unchanged() {
+        let mut changed = frame(1);
+        let pixel = changed.get_pixel_mut(480, 270);
+        *pixel = Rgba([255 - pixel[0], 255 - pixel[1], 255 - pixel[2], 255]);
+        assert!(distance(&frame(1), &changed) <= UNCHANGED_MAX_DISTANCE);
+    }
+
+    #[test]
+    fn different_frame_counts_as_changed() {
+        assert!(distance(&frame(1), &frame(2)) > UNCHANGED_MAX_DISTANCE);
+    }
+
+    const LEFT: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const MAIN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    /// No downscaling, no blending at the edges.
+    fn full_size() -> encoder::Settings {
+        encoder::Settings {
+            max_width: 10_000,
+            max_height: 10_000,
+            filter: FilterType::Nearest,
+            codec: Codec::Png,
+            quality: 100,
+        }
+    }
+
+    fn solid(width: u32, height: u32, color: Rgba<u8>) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, color)
+    }
+
+    #[test]
+    fn stitch_places_monitors_left_of_and_above_the_primary() {
+        let shots = vec![
+            (monitor("DP-1", 0, 0, 1920, 1080), solid(1920, 1080, MAIN)),
+            (
+                monitor("DP-2", -1280, -200, 1280, 1024),
+                solid(1280, 1024, LEFT),
+            ),
+        ];
+        let composite = stitch(&shots, &full_size());
+        assert_eq!(composite.dimensions(), (1920 + 1280, 1080 + 200));
+        assert_eq!(*composite.get_pixel(0, 0), LEFT);
+        assert_eq!(*composite.get_pixel(1279, 1023), LEFT);
+        assert_eq!(*composite.get_pixel(1280, 200), MAIN);
+        assert_eq!(*composite.get_pixel(3199, 1279), MAIN);
+        // Above the primary, and below the left monitor, nothing is shown
+        assert_eq!(*composite.get_pixel(1280, 199), BLACK);
+        assert_eq!(*composite.get_pixel(0, 1024), BLACK);
+    }
+
+    #[test]
+    fn stitch_sizes_hidpi_captures_by_their_monitor_bounds() {
+        // A Retina panel reports 1440x900 but captures 2880x1800 pixels
+        let shots = vec![
+            (
+                monitor("Built-in", 0, 0, 1440, 900),
+                solid(2880, 1800, LEFT),
+            ),
+            (
+                monitor("DP-1", 1440, 0, 1920, 1080),
+                solid(1920, 1080, MAIN),
+            ),
+        ];
+        let composite = stitch(&shots, &full_size());
+        assert_eq!(composite.dimensions(), (1440 + 1920, 1080));
+        assert_eq!(*composite.get_pixel(1439, 899), LEFT);
+        assert_eq!(*composite.get_pixel(1440, 0), MAIN);
+        assert_eq!(*composite.get_pixel(0, 900), BLACK);
+    }
+
+    #[test]
+    fn composite_monitors_match_where_they_were_drawn() {
+        let shots = vec![
+            (monitor("DP-1", 0, 0, 1920, 1080), solid(1920, 1080, MAIN)),
+            (
+                monitor("DP-2", -1280, -200, 1280, 1024),
+                solid(2560, 2048, LEFT),
+            ),
+        ];
+        let profile = EncodingProfile {
+            preset: crate::models::EncodingPreset::Thumbnail,
+            codec: Some(Codec::Png),
+        };
+        let captures = encode_captures(shots, MonitorLayout::Composite, &profile).unwrap();
+        assert_eq!(captures.len(), 1);
+        let composite = image::load_from_memory(&captures[0].bytes)
+            .unwrap()
+            .to_rgba8();
+
+        // Everything a server needs to cut the image back up is in the metadata
+        let monitors = &captures[0].monitors;
+        let left = monitors.iter().map(|m| m.x).min().unwrap();
+        let top = monitors.iter().map(|m| m.y).min().unwrap();
+        let right = monitors.iter().map(|m| m.x + m.width as i32).max().unwrap();
+        let scale = composite.width() as f64 / (right - left) as f64;
+        assert!(scale < 1.0);
+        for (monitor, color) in monitors.iter().zip([MAIN, LEFT]) {
+            let x0 = (monitor.x - left) as f64 * scale;
+            let y0 = (monitor.y - top) as f64 * scale;
+            let x1 = x0 + monitor.width as f64 * scale;
+            let y1 = y0 + monitor.height as f64 * scale;
+            // Just inside each corner, clear of resampling at the edges
+            for (x, y) in [(x0 + 2.0, y0 + 2.0), (x1 - 3.0, y1 - 3.0)] {
+                assert_eq!(*composite.get_pixel(x as u32, y as u32), color);
+            }
+        }
+    }
+
+    fn capture(image: &RgbaImage, monitors: Vec<MonitorInfo>) -> Capture {
+        Capture {
+            // Encoders don't reproduce bytes exactly; the marker decision must not need them to
+            bytes: uuid::Uuid::new_v4().as_bytes().to_vec(),
+            extension: "png",
+            monitors,
+            perceptual_hash: dhash(image),
+        }
+    }
+
+    struct Saver {
+        last_stored: Mutex<HashMap<String, LastStored>>,
+        conn: Connection,
+        dir: tempfile::TempDir,
+    }
+
+    impl Saver {
+        fn new() -> Self {
+            let mut conn = Connection::open_in_memory().unwrap();
+            migrations::run(&mut conn).unwrap();
+            Self {
+                last_stored: Mutex::new(HashMap::new()),
+                conn,
+                dir: tempfile::tempdir().unwrap(),
+            }
+        }
+
+        /// Saves `capture` and returns whether it was recorded as unchanged.
+        fn save(&self, session_uuid: &str, capture: Capture) -> bool {
+            let group = CaptureGroup {
+                id: uuid::Uuid::new_v4().to_string(),
+                seconds: 600,
+            };
+            save_capture(
+                &self.last_stored,
+                &self.conn,
+                self.dir.path(),
+                session_uuid,
+                "p1",
+                capture,
+                &CaptureContext::default(),
+                &group,
+            )
+            .unwrap();
+            let saved = db::get_pending_screenshots(&self.conn).unwrap();
+            saved.iter().max_by_key(|s| s.id).unwrap().unchanged
+        }
+    }
+
+    #[test]
+    fn repeated_frame_is_recorded_as_a_marker() {
+        let saver = Saver::new();
+        let screen = vec![monitor("DP-1", 0, 0, 1920, 1080)];
+        assert!(!saver.save("s1", capture(&frame(1), screen.clone())));
+        assert!(saver.save("s1", capture(&frame(1), screen.clone())));
+        assert!(!saver.save("s1", capture(&frame(2), screen)));
+
+        let saved = db::get_pending_screenshots(&saver.conn).unwrap();
+        assert_eq!(saved[0].content_hash, saved[1].content_hash);
+        assert_ne!(saved[0].content_hash, saved[2].content_hash);
+    }
+
+    #[test]
+    fn no_marker_across_sessions() {
+        let saver = Saver::new();
+        let screen = vec![monitor("DP-1", 0, 0, 1920, 1080)];
+        assert!(!saver.save("s1", capture(&frame(1), screen.clone())));
+        assert!(!saver.save("s2", capture(&frame(1), screen.clone())));
+        // The new session's image is what later captures compare against
+        assert!(saver.save("s2", capture(&frame(1), screen)));
+    }
+
+    #[test]
+    fn no_marker_across_monitor_sets() {
+        let saver = Saver::new();
+        let laptop = monitor("eDP-1", 0, 0, 1920, 1080);
+        let external = monitor("DP-1", 1920, 0, 1920, 1080);
+        assert!(!saver.save("s1", capture(&frame(1), vec![laptop.clone()])));
+        assert!(!saver.save("s1", capture(&frame(1), vec![external.clone()])));
+        assert!(!saver.save("s1", capture(&frame(1), vec![laptop.clone(), external])));
+        assert!(saver.save("s1", capture(&frame(1), vec![laptop])));
+    }
+}
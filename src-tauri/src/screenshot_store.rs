@@ -0,0 +1,226 @@
+use crate::db;
+use crate::error::WatchtowerError;
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Content-addressed screenshot store.
+//
+// Each image is written once as `<sha256>.<ext>` in a directory next to the database;
+// `pending_screenshots` rows only keep the file name, size and hash. Identical captures
+// share a file, so a file is only removed once no pending row points at it.
+
+#[derive(Clone)]
+pub struct StoredScreenshot {
+    pub file_name: String,
+    pub size: i64,
+    pub hash: String,
+}
+
+/// The last image stored for a session and set of monitors. Later captures that look the
+/// same are recorded as unchanged instead of being stored again.
+pub struct LastStored {
+    pub session_uuid: String,
+    pub perceptual_hash: u64,
+    pub stored: StoredScreenshot,
+}
+
+/// Screenshots live beside the database they belong to.
+pub fn dir_for(db_path: &Path) -> PathBuf {
+    db_path.with_file_name("screenshots")
+}
+
+pub fn store(dir: &Path, bytes: &[u8], ext: &str) -> Result<StoredScreenshot, WatchtowerError> {
+    fs::create_dir_all(dir)?;
+
+    let hash = format!("{:x}", Sha256::digest(bytes));
+    let file_name = format!("{}.{}", hash, ext);
+    let path = dir.join(&file_name);
+
+    if !path.exists() {
+        // Write under a temp name first so a crash never leaves a truncated image behind
+        let tmp_path = dir.join(format!("{}.tmp", file_name));
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(&tmp_path, &path)?;
+    }
+
+    Ok(StoredScreenshot {
+        file_name,
+        size: bytes.len() as i64,
+        hash,
+    })
+}
+
+pub fn read(dir: &Path, file_name: &str) -> Result<Vec<u8>, WatchtowerError> {
+    Ok(fs::read(dir.join(file_name))?)
+}
+
+/// A WebP data URL of the stored image scaled to fit `max_size`, for the review list.
+pub fn thumbnail(dir: &Path, file_name: &str, max_size: u32) -> Result<String, WatchtowerError> {
+    let bytes = read(dir, file_name)?;
+    let image = image::load_from_memory(&bytes)
+        .map_err(|e| WatchtowerError::storage(e.to_string()))?
+        .thumbnail(max_size, max_size)
+        .to_rgba8();
+    let encoded =
+        webp::Encoder::from_rgba(image.as_raw(), image.width(), image.height()).encode(60.0);
+    Ok(format!(
+        "data:image/webp;base64,{}",
+        general_purpose::STANDARD.encode(&*encoded)
+    ))
+}
+
+/// Removes `file_name` from disk unless another pending row still references it.
+pub fn release(conn: &Connection, dir: &Path, file_name: &str) -> Result<(), WatchtowerError> {
+    let refs = db::count_screenshots_with_file(conn, file_name)?;
+    if refs == 0 {
+        match fs::remove_file(dir.join(file_name)) {
+            // Unchanged markers outlive the image they point at
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => eprintln!("Store: Failed to remove {}: {}", file_name, e),
+            Ok(()) => {}
+        }
+    }
+    Ok(())
+}
+
+/// One-time move of base64 rows written by older releases into the file store.
+/// Rows are processed one at a time so a large backlog is never held in memory.
+pub fn externalize_inline_rows(conn: &Connection, dir: &Path) -> Result<usize, WatchtowerError> {
+    let ids = db::get_inline_screenshot_ids(conn)?;
+    let mut moved = 0;
+
+    for id in ids {
+        let data = db::get_inline_screenshot_data(conn, id)?;
+        match general_purpose::STANDARD.decode(data.as_bytes()) {
+            Ok(bytes) => {
+                let stored = store(dir, &bytes, "webp")?;
+                db::set_screenshot_file(conn, id, &stored)?;
+                moved += 1;
+            }
+            Err(e) => {
+                // Undecodable rows could never be uploaded; drop them instead of retrying forever
+                eprintln!("Store: Dropping unreadable screenshot {}: {}", id, e);
+                db::delete_pending_screenshot(conn, id)?;
+            }
+        }
+    }
+
+    if moved > 0 {
+        println!("Store: Moved {} screenshots out of the database", moved);
+    }
+    Ok(moved)
+}
+
+/// Deletes files (and stale temp files) that no pending row references, e.g. after a
+/// crash between writing the image and inserting its row.
+pub fn remove_unreferenced(conn: &Connection, dir: &Path) -> Result<usize, WatchtowerError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(0), // Nothing stored yet
+    };
+
+    let referenced: HashSet<String> = db::get_screenshot_file_paths(conn)?.into_iter().collect();
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !referenced.contains(&name) && fs::remove_file(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use crate::models::{CaptureContext, CaptureGroup};
+
+    fn test_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run(&mut conn).unwrap();
+        conn
+    }
+
+    /// Adds a pending row pointing at `stored` and returns its id.
+    fn reference(conn: &Connection, stored: &StoredScreenshot) -> i64 {
+        let group = CaptureGroup {
+            id: uuid::Uuid::new_v4().to_string(),
+            seconds: 600,
+        };
+        db::save_pending_screenshot(
+            conn,
+            "session-1",
+            "project-1",
+            stored,
+            &[],
+            0,
+            false,
+            &CaptureContext::default(),
+            &group,
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    #[test]
+    fn identical_images_share_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = store(dir.path(), b"image", "webp").unwrap();
+        let second = store(dir.path(), b"image", "webp").unwrap();
+        assert_eq!(first.file_name, second.file_name);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        assert_eq!(read(dir.path(), &first.file_name).unwrap(), b"image");
+    }
+
+    #[test]
+    fn release_keeps_a_file_until_its_last_row_is_gone() {
+        let conn = test_db();
+        let dir = tempfile::tempdir().unwrap();
+        let stored = store(dir.path(), b"image", "webp").unwrap();
+        let first = reference(&conn, &stored);
+        let second = reference(&conn, &stored);
+        let path = dir.path().join(&stored.file_name);
+
+        db::delete_pending_screenshot(&conn, first).unwrap();
+        release(&conn, dir.path(), &stored.file_name).unwrap();
+        assert!(path.exists());
+
+        db::delete_pending_screenshot(&conn, second).unwrap();
+        release(&conn, dir.path(), &stored.file_name).unwrap();
+        assert!(!path.exists());
+        // Releasing a file that is already gone is fine
+        release(&conn, dir.path(), &stored.file_name).unwrap();
+    }
+
+    #[test]
+    fn unreferenced_files_are_removed() {
+        let conn = test_db();
+        let dir = tempfile::tempdir().unwrap();
+        let kept = store(dir.path(), b"kept", "webp").unwrap();
+        reference(&conn, &kept);
+        let orphan = store(dir.path(), b"orphan", "png").unwrap();
+        fs::write(dir.path().join("crashed.webp.tmp"), b"half").unwrap();
+
+        assert_eq!(remove_unreferenced(&conn, dir.path()).unwrap(), 2);
+        let names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, [kept.file_name]);
+        assert!(!dir.path().join(orphan.file_name).exists());
+    }
+
+    #[test]
+    fn nothing_to_remove_before_the_first_capture() {
+        let conn = test_db();
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("screenshots");
+        assert_eq!(remove_unreferenced(&conn, &missing).unwrap(), 0);
+    }
+}
@@ -0,0 +1,948 @@
+use crate::db;
+use crate::error::{LimitKind, WatchtowerError};
+use crate::models::{ChunkPolicy, Project, Session};
+use crate::tray_generator::{self, IconState, Theme, TrayIconSpec};
+use crate::{format_duration, update_tray, week, AppState, TimeUpdatePayload};
+use chrono::{DateTime, Local, Offset, TimeDelta, TimeZone, Timelike, Weekday};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+// Timer
+//
+// The once-a-second tick behind the tray clock. `step` is the state machine: given what
+// the database looks like right now, it decides the timer state, what the clock shows
+// and which side effects are due (chunk rollover, heartbeat, auto-stop). It reads the
+// time from a `Clock` and touches nothing else, so it can be driven by a fake clock.
+// `start_timer_loop` is the adapter that gathers the snapshot, applies the effects and
+// updates the tray and webview.
+
+const TICK: Duration = Duration::from_secs(1);
+
+/// Local chunk policy (JSON), used when the project doesn't set one.
+pub const CHUNK_POLICY_SETTING: &str = "chunk_policy";
+
+const BREAK_TYPE: &str = "WorkBreakPolicy";
+
+pub trait Clock: Send + 'static {
+    /// The zone whose midnights and hours chunks end at.
+    type Tz: TimeZone;
+
+    fn now(&self) -> DateTime<Self::Tz>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    type Tz = Local;
+
+    fn now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum TimerState {
+    /// Logged out, nothing selected, or simply not running.
+    Idle,
+    #[serde(rename_all = "camelCase")]
+    Tracking { project_id: String },
+    /// The session is still open but not counting; monitoring and capture are off.
+    #[serde(rename_all = "camelCase")]
+    Paused { project_id: String },
+    /// A work-break policy is counting down; `ends_at` is epoch ms.
+    #[serde(rename_all = "camelCase")]
+    OnBreak { policy_id: String, ends_at: i64 },
+    /// The timer was stopped for inactivity and the user hasn't said what to do with it.
+    #[serde(rename_all = "camelCase")]
+    IdlePrompt { idle_seconds: u64 },
+    /// The selected project's daily or weekly limit is used up.
+    #[serde(rename_all = "camelCase")]
+    LimitReached {
+        project_id: String,
+        limit: LimitKind,
+    },
+}
+
+/// What the database and app state looked like at the start of a tick.
+pub struct Snapshot<'a> {
+    pub logged_in: bool,
+    pub project_id: Option<&'a str>,
+    pub project: Option<&'a Project>,
+    pub session: Option<&'a Session>,
+    pub today_secs: u64,
+    pub week_secs: u64,
+    pub chunk_policy: ChunkPolicy,
+    /// Seconds of inactivity awaiting a decision in the idle window.
+    pub idle_prompt: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum Effect {
+    /// Close the running project session and open the next chunk.
+    SplitSession { session_uuid: String },
+    /// Write the end time and activity counts of whichever session is active.
+    Heartbeat,
+    /// The break ran out.
+    EndBreak,
+    /// Tracking went past a limit.
+    StopAtLimit(WatchtowerError),
+}
+
+#[derive(Debug)]
+pub struct Tick {
+    pub state: TimerState,
+    /// What the tray clock shows.
+    pub display: String,
+    /// The tray icon's ring: share of the daily limit used, or of the break still left.
+    pub progress: Option<f32>,
+    pub effects: Vec<Effect>,
+}
+
+impl Tick {
+    fn new(state: TimerState, display: String) -> Self {
+        Tick {
+            state,
+            display,
+            progress: None,
+            effects: Vec::new(),
+        }
+    }
+}
+
+pub fn step<Tz: TimeZone>(snapshot: &Snapshot, now: DateTime<Tz>) -> Tick {
+    let mut tick = transition(snapshot, &now);
+    tick.progress = progress(snapshot, &tick.state, &now);
+    tick
+}
+
+fn transition<Tz: TimeZone>(snapshot: &Snapshot, now: &DateTime<Tz>) -> Tick {
+    if !snapshot.logged_in {
+        return Tick::new(TimerState::Idle, "--:--:--".to_string());
+    }
+    let Some(project_id) = snapshot.project_id else {
+        return Tick::new(TimerState::Idle, format_duration(0));
+    };
+    let today = format_duration(snapshot.today_secs);
+
+    match snapshot.session {
+        // Nothing moves while paused: no heartbeat, no chunk rollover, no limit check
+        Some(session) if session.paused_at.is_some() => Tick::new(
+            TimerState::Paused {
+                project_id: session.project_id.clone(),
+            },
+            today,
+        ),
+        Some(session) if session.project_type == BREAK_TYPE => {
+            let ends_at = session.start_time + session.duration_minutes * 60 * 1000;
+            let remaining_secs = ((ends_at - now.timestamp_millis()) / 1000).max(0);
+            let state = TimerState::OnBreak {
+                policy_id: session.project_id.clone(),
+                ends_at,
+            };
+            let mut tick = Tick::new(state, format_duration(remaining_secs as u64));
+            tick.effects.push(Effect::Heartbeat);
+            if remaining_secs == 0 {
+                tick.state = TimerState::Idle;
+                tick.effects.push(Effect::EndBreak);
+            }
+            tick
+        }
+        Some(session) => {
+            if let Some(project) = snapshot.project {
+                if let Err(limit) = check_limits(project, snapshot.today_secs, snapshot.week_secs) {
+                    let mut tick = Tick::new(limit_state(&limit), today);
+                    tick.effects.push(Effect::StopAtLimit(limit));
+                    return tick;
+                }
+            }
+
+            let mut tick = Tick::new(
+                TimerState::Tracking {
+                    project_id: session.project_id.clone(),
+                },
+                today,
+            );
+            if is_chunked(&session.project_type)
+                && chunk_is_over(session.start_time, now, &snapshot.chunk_policy)
+            {
+                tick.effects.push(Effect::SplitSession {
+                    session_uuid: session.uuid.clone(),
+                });
+            }
+            tick.effects.push(Effect::Heartbeat);
+            tick
+        }
+        None => {
+            if let Some(idle_seconds) = snapshot.idle_prompt {
+                return Tick::new(TimerState::IdlePrompt { idle_seconds }, today);
+            }
+            let over_limit = snapshot
+                .project
+                .filter(|p| p.id == project_id)
+                .and_then(|p| check_limits(p, snapshot.today_secs, snapshot.week_secs).err());
+            match over_limit {
+                Some(limit) => Tick::new(limit_state(&limit), today),
+                None => Tick::new(TimerState::Idle, today),
+            }
+        }
+    }
+}
+
+fn progress<Tz: TimeZone>(
+    snapshot: &Snapshot,
+    state: &TimerState,
+    now: &DateTime<Tz>,
+) -> Option<f32> {
+    match state {
+        TimerState::OnBreak { ends_at, .. } => {
+            let total_ms = snapshot.session?.duration_minutes * 60 * 1000;
+            if total_ms <= 0 {
+                return None;
+            }
+            let remaining_ms = (ends_at - now.timestamp_millis()).max(0);
+            Some((remaining_ms as f32 / total_ms as f32).min(1.0))
+        }
+        TimerState::IdlePrompt { .. } => None,
+        TimerState::Idle
+        | TimerState::Tracking { .. }
+        | TimerState::Paused { .. }
+        | TimerState::LimitReached { .. } => {
+            let daily_limit = snapshot.project?.daily_limit_hours.filter(|h| *h > 0.0)?;
+            Some((snapshot.today_secs as f64 / (daily_limit * 3600.0)).min(1.0) as f32)
+        }
+    }
+}
+
+fn icon_state(logged_in: bool, state: &TimerState) -> IconState {
+    match state {
+        TimerState::Idle if !logged_in => IconState::LoggedOut,
+        TimerState::Idle => IconState::Idle,
+        TimerState::Tracking { .. } => IconState::Tracking,
+        TimerState::Paused { .. } => IconState::Paused,
+        TimerState::OnBreak { .. } => IconState::OnBreak,
+        TimerState::IdlePrompt { .. } => IconState::IdlePrompt,
+        TimerState::LimitReached { .. } => IconState::LimitReached,
+    }
+}
+
+/// The tray follows the main window's theme, which tracks the system's.
+fn tray_theme<R: Runtime>(app: &AppHandle<R>) -> Theme {
+    match app.get_webview_window("main").and_then(|w| w.theme().ok()) {
+        Some(tauri::Theme::Dark) => Theme::Dark,
+        _ => Theme::Light,
+    }
+}
+
+fn limit_state(error: &WatchtowerError) -> TimerState {
+    match error {
+        WatchtowerError::LimitReached {
+            project_id, limit, ..
+        } => TimerState::LimitReached {
+            project_id: project_id.clone(),
+            limit: *limit,
+        },
+        _ => TimerState::Idle,
+    }
+}
+
+fn is_chunked(project_type: &str) -> bool {
+    project_type == "Project" || project_type == "watchtower"
+}
+
+/// Whether the running chunk should be closed. Besides the policy, a chunk always ends
+/// at midnight in `now`'s zone. With hour splits, the UTC offset is compared as well
+/// because the wall clock repeats an hour when DST ends.
+fn chunk_is_over<Tz: TimeZone>(start_time: i64, now: &DateTime<Tz>, policy: &ChunkPolicy) -> bool {
+    let elapsed = now.timestamp_millis() - start_time;
+    let Some(start) = now.timezone().timestamp_millis_opt(start_time).single() else {
+        return true;
+    };
+
+    if now.date_naive() != start.date_naive() {
+        return true;
+    }
+    if let Some(minutes) = policy.chunk_minutes {
+        if elapsed >= TimeDelta::minutes(minutes as i64).num_milliseconds() {
+            return true;
+        }
+    }
+    policy.split_at_hour
+        && (now.hour() != start.hour() || now.offset().fix() != start.offset().fix())
+}
+
+/// The project's own policy if the server sent one, else the local setting, else the
+/// default of 10-minute chunks split at the hour.
+pub fn chunk_policy(conn: &Connection, project: Option<&Project>) -> ChunkPolicy {
+    project
+        .and_then(|p| p.chunk_policy)
+        .or_else(|| {
+            db::get_setting(conn, CHUNK_POLICY_SETTING)
+                .ok()
+                .flatten()
+                .and_then(|json| serde_json::from_str(&json).ok())
+        })
+        .filter(|policy| validate_chunk_policy(policy).is_ok())
+        .unwrap_or_default()
+}
+
+/// Rejects policies that never split, or split so often the server drowns in chunks.
+pub fn validate_chunk_policy(policy: &ChunkPolicy) -> Result<(), WatchtowerError> {
+    match policy.chunk_minutes {
+        Some(minutes) if !(1..=60).contains(&minutes) => Err(WatchtowerError::invalid_input(
+            "Chunk length must be between 1 and 60 minutes",
+        )),
+        None if !policy.split_at_hour => Err(WatchtowerError::invalid_input(
+            "A chunk policy needs a chunk length, hour splits, or both",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Errors with `LimitReached` once the project's daily or weekly limit is used up.
+pub fn check_limits(
+    project: &Project,
+    today_total_secs: u64,
+    week_total_secs: u64,
+) -> Result<(), WatchtowerError> {
+    let tracked_today = (today_total_secs as f64) / 3600.0;
+    if let Some(daily_limit) = project.daily_limit_hours {
+        if tracked_today >= daily_limit {
+            return Err(WatchtowerError::LimitReached {
+                project_id: project.id.clone(),
+                limit: LimitKind::Daily,
+                limit_hours: daily_limit,
+                tracked_hours: tracked_today,
+            });
+        }
+    }
+
+    if let Some(weekly_limit) = project.weekly_limit_hours {
+        let total_this_week = (week_total_secs as f64) / 3600.0;
+        if total_this_week >= weekly_limit {
+            return Err(WatchtowerError::LimitReached {
+                project_id: project.id.clone(),
+                limit: LimitKind::Weekly,
+                limit_hours: weekly_limit,
+                tracked_hours: total_this_week,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Seconds tracked on a project in the week containing `now`: the server's total as of
+/// the last project fetch, plus local time that total doesn't include yet.
+pub fn get_week_total<Tz: TimeZone>(
+    conn: &Connection,
+    project: &Project,
+    week_start: Weekday,
+    now: &DateTime<Tz>,
+) -> Result<u64, rusqlite::Error> {
+    let local = db::get_week_total_time(conn, &project.id, week_start, now)?;
+    let counted = db::get_week_baseline_time(conn, &project.id, week_start, now)?;
+
+    // Totals fetched last week say nothing about this one
+    let fetched_at = db::get_setting(conn, db::WEEKLY_TOTALS_FETCHED_SETTING)?
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(0);
+    let server = if fetched_at >= week::start_of_week(now, week_start) {
+        (project.total_hours_this_week.unwrap_or(0.0) * 3600.0).round() as u64
+    } else {
+        0
+    };
+    Ok(server + local.saturating_sub(counted))
+}
+
+pub fn start_timer_loop<R: Runtime, C: Clock>(app: AppHandle<R>, clock: C) {
+    thread::spawn(move || loop {
+        thread::sleep(TICK);
+        tick(&app, &clock);
+    });
+}
+
+fn tick<R: Runtime>(app: &AppHandle<R>, clock: &impl Clock) {
+    let state = app.state::<AppState>();
+    let db_path = state.db_path.lock().unwrap().clone();
+    let now = clock.now();
+
+    let mut payload = TimeUpdatePayload {
+        time: "--:--:--".to_string(),
+        project_type: "Project".to_string(),
+        project_id: "".to_string(),
+        target_name: None,
+    };
+    let mut icon = (IconState::LoggedOut, None);
+
+    if let Ok(conn) = Connection::open(&db_path) {
+        let user = db::get_user(&conn).ok().flatten();
+        let session = db::get_global_active_session(&conn).ok().flatten();
+        let project_id = user.as_ref().and_then(|u| u.current_project_id.clone());
+        let project = user
+            .as_ref()
+            .zip(project_id.as_ref())
+            .and_then(|(u, id)| u.projects.iter().find(|p| &p.id == id));
+        let today_secs = project_id
+            .as_ref()
+            .and_then(|id| db::get_today_total_time(&conn, id).ok())
+            .unwrap_or(0);
+        let week_secs = project
+            .filter(|p| p.weekly_limit_hours.is_some())
+            .and_then(|p| get_week_total(&conn, p, week::locale_week_start(), &now).ok())
+            .unwrap_or(0);
+
+        let snapshot = Snapshot {
+            logged_in: user.is_some(),
+            project_id: project_id.as_deref(),
+            project,
+            session: session.as_ref(),
+            today_secs,
+            week_secs,
+            chunk_policy: chunk_policy(&conn, project),
+            idle_prompt: *state.current_idle_time.lock().unwrap(),
+        };
+        let tick = step(&snapshot, now);
+
+        if let Some(project_id) = &project_id {
+            payload.project_id = project_id.clone();
+        }
+        if let Some(session) = &session {
+            payload.project_type = session.project_type.clone();
+            payload.project_id = session.project_id.clone();
+            payload.target_name = session.target_name.clone();
+        }
+        payload.time = tick.display;
+        icon = (icon_state(snapshot.logged_in, &tick.state), tick.progress);
+
+        let email = user.as_ref().map(|u| u.email.as_str()).unwrap_or_default();
+        for effect in tick.effects {
+            apply(app, &conn, effect, email);
+        }
+
+        let changed = {
+            let mut current = state.timer_state.lock().unwrap();
+            let changed = *current != tick.state;
+            *current = tick.state.clone();
+            changed
+        };
+        if changed {
+            let _ = app.emit("timer-state-changed", &tick.state);
+            // Menu items and per-project totals follow the timer state
+            crate::refresh_tray(app);
+        }
+    }
+
+    if let Some(tray) = app.tray_by_id("main") {
+        let (state, progress) = icon;
+        let spec = TrayIconSpec {
+            text: &payload.time,
+            state,
+            progress,
+            theme: tray_theme(app),
+        };
+        if let Some(icon) = tray_generator::generate_tray_icon(&spec) {
+            let _ = tray.set_icon(Some(icon));
+        }
+    }
+
+    // Emit time update to Vite app
+    let _ = app.emit("time-update", &payload);
+}
+
+fn apply<R: Runtime>(app: &AppHandle<R>, conn: &Connection, effect: Effect, email: &str) {
+    let state = app.state::<AppState>();
+    match effect {
+        Effect::SplitSession { session_uuid } => {
+            if let Err(e) = db::split_session(conn, &session_uuid) {
+                eprintln!("Timer: Failed to split session {}: {}", session_uuid, e);
+            }
+
+            // Reset activity counts for the new session
+            state.idle_state.keyboard_count.store(0, Ordering::Relaxed);
+            state.idle_state.mouse_count.store(0, Ordering::Relaxed);
+        }
+        Effect::Heartbeat => {
+            if let Ok(Some(session)) = db::get_global_active_session(conn) {
+                if let Some(id) = session.id {
+                    let k_count = state.idle_state.keyboard_count.load(Ordering::Relaxed) as i64;
+                    let m_count = state.idle_state.mouse_count.load(Ordering::Relaxed) as i64;
+                    let _ = db::update_session_heartbeat(conn, id, k_count, m_count);
+                }
+            }
+        }
+        Effect::EndBreak => {
+            let _ = db::stop_all_active_sessions(conn);
+            let _ = app.emit("timer-active", false);
+            // update tray to project state
+            update_tray(app, true, email);
+        }
+        Effect::StopAtLimit(limit) => {
+            let _ = db::stop_all_active_sessions(conn);
+            let _ = app.emit("timer-active", false);
+            let _ = app.emit("limit-reached", limit);
+            update_tray(app, true, email);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::America::New_York;
+    use chrono_tz::Tz;
+    use std::sync::Mutex;
+
+    /// A clock the test moves by hand. It reads New York time, whatever the machine's
+    /// zone, so the DST cases below always have a DST change to cross.
+    struct FakeClock(Mutex<DateTime<Tz>>);
+
+    impl FakeClock {
+        fn at(now: DateTime<Tz>) -> Self {
+            FakeClock(Mutex::new(now))
+        }
+
+        fn advance(&self, seconds: i64) {
+            let mut now = self.0.lock().unwrap();
+            *now += TimeDelta::seconds(seconds);
+        }
+    }
+
+    impl Clock for FakeClock {
+        type Tz = Tz;
+
+        fn now(&self) -> DateTime<Tz> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn local(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> DateTime<Tz> {
+        New_York
+            .with_ymd_and_hms(y, mo, d, h, mi, s)
+            .earliest()
+            .expect("local time exists")
+    }
+
+    /// For local times that occur twice, which a UTC time pins down.
+    fn from_utc(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> DateTime<Tz> {
+        chrono::Utc
+            .with_ymd_and_hms(y, mo, d, h, mi, s)
+            .unwrap()
+            .with_timezone(&New_York)
+    }
+
+    fn project() -> Project {
+        Project {
+            id: "project-1".to_string(),
+            name: "Project".to_string(),
+            weekly_limit_hours: None,
+            daily_limit_hours: None,
+            screenshots_enabled: true,
+            total_hours_this_week: None,
+            chunk_policy: None,
+            monitor_layout: None,
+            capture_schedule: None,
+            blur_level: None,
+            encoding_profile: None,
+        }
+    }
+
+    fn session(project_type: &str, start: DateTime<Tz>, duration_minutes: i64) -> Session {
+        Session {
+            id: Some(1),
+            uuid: "session-1".to_string(),
+            project_id: "project-1".to_string(),
+            project_type: project_type.to_string(),
+            duration_minutes,
+            target_name: None,
+            start_time: start.timestamp_millis(),
+            end_time: None,
+            is_active: true,
+            idle_seconds: 0,
+            deducted_seconds: 0,
+            status: "pending".to_string(),
+            keyboard_events: 0,
+            mouse_events: 0,
+            parent_run_uuid: None,
+            paused_seconds: 0,
+            paused_at: None,
+        }
+    }
+
+    fn snapshot<'a>(
+        project: &'a Project,
+        session: Option<&'a Session>,
+        policy: ChunkPolicy,
+    ) -> Snapshot<'a> {
+        Snapshot {
+            logged_in: true,
+            project_id: Some(&project.id),
+            project: Some(project),
+            session,
+            today_secs: 0,
+            week_secs: 0,
+            chunk_policy: policy,
+            idle_prompt: None,
+        }
+    }
+
+    fn splits(tick: &Tick) -> bool {
+        tick.effects
+            .iter()
+            .any(|e| matches!(e, Effect::SplitSession { .. }))
+    }
+
+    const HOURLY: ChunkPolicy = ChunkPolicy {
+        chunk_minutes: None,
+        split_at_hour: true,
+    };
+
+    fn every(minutes: u32) -> ChunkPolicy {
+        ChunkPolicy {
+            chunk_minutes: Some(minutes),
+            split_at_hour: false,
+        }
+    }
+
+    #[test]
+    fn tracking_splits_at_local_midnight() {
+        let clock = FakeClock::at(local(2026, 3, 5, 23, 30, 0));
+        let project = project();
+        let session = session("Project", clock.now(), 0);
+        let snapshot = snapshot(&project, Some(&session), every(60));
+
+        clock.advance(29 * 60 + 59);
+        let tick = step(&snapshot, clock.now());
+        assert_eq!(
+            tick.state,
+            TimerState::Tracking {
+                project_id: "project-1".to_string()
+            }
+        );
+        assert!(!splits(&tick));
+
+        clock.advance(1);
+        assert_eq!(clock.now().date_naive().to_string(), "2026-03-06");
+        assert!(splits(&step(&snapshot, clock.now())));
+    }
+
+    #[test]
+    fn spring_forward_jumps_to_a_new_hour() {
+        // 2026-03-08: 01:59:59 EST is followed by 03:00:00 EDT
+        let clock = FakeClock::at(local(2026, 3, 8, 1, 50, 0));
+        let project = project();
+        let session = session("Project", clock.now(), 0);
+        let snapshot = snapshot(&project, Some(&session), HOURLY);
+
+        clock.advance(9 * 60 + 59);
+        assert!(!splits(&step(&snapshot, clock.now())));
+        clock.advance(1);
+        assert_eq!(clock.now().hour(), 3);
+        assert!(splits(&step(&snapshot, clock.now())));
+    }
+
+    #[test]
+    fn spring_forward_chunk_length_is_real_time() {
+        let clock = FakeClock::at(local(2026, 3, 8, 1, 30, 0));
+        let project = project();
+        let session = session("Project", clock.now(), 0);
+        let snapshot = snapshot(&project, Some(&session), every(60));
+
+        // 03:29:59 on the wall clock, but only 59:59 tracked
+        clock.advance(59 * 60 + 59);
+        assert_eq!(clock.now().hour(), 3);
+        assert!(!splits(&step(&snapshot, clock.now())));
+        clock.advance(1);
+        assert!(splits(&step(&snapshot, clock.now())));
+    }
+
+    #[test]
+    fn fall_back_repeated_hour_is_a_new_hour() {
+        // 2026-11-01: 01:59:59 EDT is followed by 01:00:00 EST; start at 01:10 EDT
+        let clock = FakeClock::at(from_utc(2026, 11, 1, 5, 10, 0));
+        assert_eq!(clock.now().hour(), 1);
+        let project = project();
+        let session = session("Project", clock.now(), 0);
+        let snapshot = snapshot(&project, Some(&session), HOURLY);
+
+        clock.advance(49 * 60 + 59);
+        assert!(!splits(&step(&snapshot, clock.now())));
+        clock.advance(1);
+        assert_eq!(clock.now().hour(), 1);
+        assert!(splits(&step(&snapshot, clock.now())));
+    }
+
+    #[test]
+    fn fall_back_chunk_length_is_real_time() {
+        // 01:45 EDT
+        let start = from_utc(2026, 11, 1, 5, 45, 0);
+        let clock = FakeClock::at(start);
+        let project = project();
+        let session = session("Project", clock.now(), 0);
+        let snapshot = snapshot(&project, Some(&session), every(30));
+
+        // 01:14:59 EST on the wall clock reads earlier than the start, yet 29:59 passed
+        clock.advance(29 * 60 + 59);
+        assert!(clock.now().naive_local() < start.naive_local());
+        assert!(!splits(&step(&snapshot, clock.now())));
+        clock.advance(1);
+        assert!(splits(&step(&snapshot, clock.now())));
+    }
+
+    #[test]
+    fn break_counts_down_and_ends_itself() {
+        let clock = FakeClock::at(local(2026, 6, 1, 12, 0, 0));
+        let project = project();
+        let session = session(BREAK_TYPE, clock.now(), 5);
+        let snapshot = snapshot(&project, Some(&session), ChunkPolicy::default());
+        let ends_at = session.start_time + 5 * 60 * 1000;
+
+        clock.advance(60);
+        let tick = step(&snapshot, clock.now());
+        assert_eq!(
+            tick.state,
+            TimerState::OnBreak {
+                policy_id: "project-1".to_string(),
+                ends_at
+            }
+        );
+        assert_eq!(tick.display, "00:04:00");
+        assert_eq!(tick.progress, Some(0.8));
+        assert!(!tick.effects.iter().any(|e| matches!(e, Effect::EndBreak)));
+
+        clock.advance(239);
+        assert_eq!(step(&snapshot, clock.now()).display, "00:00:01");
+
+        clock.advance(1);
+        let tick = step(&snapshot, clock.now());
+        assert_eq!(tick.state, TimerState::Idle);
+        assert_eq!(tick.display, "00:00:00");
+        assert!(tick.effects.iter().any(|e| matches!(e, Effect::EndBreak)));
+    }
+
+    #[test]
+    fn tracking_stops_at_the_daily_limit() {
+        let clock = FakeClock::at(local(2026, 6, 1, 9, 0, 0));
+        let mut project = project();
+        project.daily_limit_hours = Some(2.0);
+        let session = session("Project", clock.now(), 0);
+        let mut snapshot = snapshot(&project, Some(&session), ChunkPolicy::default());
+
+        snapshot.today_secs = 2 * 3600 - 1;
+        let tick = step(&snapshot, clock.now());
+        assert!(matches!(tick.state, TimerState::Tracking { .. }));
+        assert!(tick.progress.unwrap() < 1.0);
+
+        snapshot.today_secs = 2 * 3600;
+        let tick = step(&snapshot, clock.now());
+        assert_eq!(
+            tick.state,
+            TimerState::LimitReached {
+                project_id: "project-1".to_string(),
+                limit: LimitKind::Daily
+            }
+        );
+        assert_eq!(tick.progress, Some(1.0));
+        assert!(matches!(
+            tick.effects.as_slice(),
+            [Effect::StopAtLimit(WatchtowerError::LimitReached {
+                limit: LimitKind::Daily,
+                ..
+            })]
+        ));
+
+        // Once stopped, the state stays put and nothing else is triggered
+        snapshot.session = None;
+        let tick = step(&snapshot, clock.now());
+        assert!(matches!(tick.state, TimerState::LimitReached { .. }));
+        assert!(tick.effects.is_empty());
+    }
+
+    #[test]
+    fn tracking_stops_at_the_weekly_limit() {
+        let clock = FakeClock::at(local(2026, 6, 1, 9, 0, 0));
+        let mut project = project();
+        project.weekly_limit_hours = Some(10.0);
+        let session = session("Project", clock.now(), 0);
+        let mut snapshot = snapshot(&project, Some(&session), ChunkPolicy::default());
+        snapshot.week_secs = 10 * 3600;
+
+        let tick = step(&snapshot, clock.now());
+        assert_eq!(
+            tick.state,
+            TimerState::LimitReached {
+                project_id: "project-1".to_string(),
+                limit: LimitKind::Weekly
+            }
+        );
+        assert!(matches!(tick.effects.as_slice(), [Effect::StopAtLimit(_)]));
+    }
+
+    #[test]
+    fn paused_session_neither_splits_nor_heartbeats() {
+        let clock = FakeClock::at(local(2026, 6, 1, 9, 55, 0));
+        let project = project();
+        let mut session = session("Project", clock.now(), 0);
+        session.paused_at = Some(clock.now().timestamp_millis());
+        let snapshot = snapshot(&project, Some(&session), HOURLY);
+
+        clock.advance(3600);
+        let tick = step(&snapshot, clock.now());
+        assert!(matches!(tick.state, TimerState::Paused { .. }));
+        assert!(tick.effects.is_empty());
+    }
+
+    #[test]
+    fn resuming_past_the_chunk_boundary_splits_on_the_next_tick() {
+        let clock = FakeClock::at(local(2026, 6, 1, 9, 55, 0));
+        let project = project();
+        let mut session = session("Project", clock.now(), 0);
+        clock.advance(60);
+        session.paused_at = Some(clock.now().timestamp_millis());
+
+        // Paused across the hour: nothing happens until the resume
+        clock.advance(30 * 60);
+        let tick = step(&snapshot(&project, Some(&session), HOURLY), clock.now());
+        assert!(!splits(&tick));
+
+        session.paused_at = None;
+        session.paused_seconds = 30 * 60;
+        let tick = step(&snapshot(&project, Some(&session), HOURLY), clock.now());
+        assert_eq!(
+            tick.state,
+            TimerState::Tracking {
+                project_id: "project-1".to_string()
+            }
+        );
+        assert!(splits(&tick));
+    }
+
+    fn test_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::run(&mut conn).unwrap();
+        conn
+    }
+
+    /// Records a finished project session of `minutes` from `start`.
+    fn tracked(conn: &Connection, uuid: &str, start: DateTime<Tz>, minutes: i64) {
+        let start = start.timestamp_millis();
+        conn.execute(
+            "INSERT INTO sessions (uuid, project_id, project_type, start_time, end_time, is_active, idle_seconds, deducted_seconds, status, keyboard_events, mouse_events)
+             VALUES (?1, 'project-1', 'Project', ?2, ?3, 0, 0, 0, 'pending', 0, 0)",
+            (uuid, start, start + minutes * 60 * 1000),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn week_total_starts_on_the_locale_week_start() {
+        let conn = test_db();
+        tracked(&conn, "saturday", local(2026, 6, 6, 10, 0, 0), 120);
+        tracked(&conn, "sunday", local(2026, 6, 7, 10, 0, 0), 60);
+        tracked(&conn, "monday", local(2026, 6, 8, 10, 0, 0), 30);
+
+        // Tuesday
+        let now = local(2026, 6, 9, 12, 0, 0);
+        let project = project();
+        assert_eq!(
+            get_week_total(&conn, &project, Weekday::Sun, &now).unwrap(),
+            90 * 60
+        );
+        assert_eq!(
+            get_week_total(&conn, &project, Weekday::Mon, &now).unwrap(),
+            30 * 60
+        );
+        assert_eq!(
+            get_week_total(&conn, &project, Weekday::Sat, &now).unwrap(),
+            210 * 60
+        );
+    }
+
+    #[test]
+    fn week_total_spans_a_dst_change() {
+        let conn = test_db();
+        // 23:30 EST on the Saturday before clocks go forward: 04:30 UTC, which a week
+        // start taken at today's EDT offset would wrongly include
+        tracked(&conn, "before", local(2026, 3, 7, 23, 30, 0), 20);
+        tracked(&conn, "est", local(2026, 3, 8, 0, 30, 0), 60);
+        tracked(&conn, "edt", local(2026, 3, 9, 9, 0, 0), 60);
+
+        let now = local(2026, 3, 10, 12, 0, 0);
+        let total = get_week_total(&conn, &project(), Weekday::Sun, &now).unwrap();
+        assert_eq!(total, 2 * 3600);
+    }
+
+    #[test]
+    fn session_crossing_the_week_start_counts_toward_the_week_it_started_in() {
+        let conn = test_db();
+        // Sunday 23:30 to Monday 00:30
+        tracked(&conn, "crossing", local(2026, 6, 7, 23, 30, 0), 60);
+        tracked(&conn, "monday", local(2026, 6, 8, 9, 0, 0), 30);
+
+        let now = local(2026, 6, 8, 12, 0, 0);
+        let project = project();
+        assert_eq!(
+            get_week_total(&conn, &project, Weekday::Mon, &now).unwrap(),
+            30 * 60
+        );
+        assert_eq!(
+            get_week_total(&conn, &project, Weekday::Sun, &now).unwrap(),
+            90 * 60
+        );
+    }
+
+    #[test]
+    fn synced_session_is_not_counted_twice() {
+        let conn = test_db();
+        let now = local(2026, 6, 10, 12, 0, 0);
+        tracked(&conn, "synced", local(2026, 6, 8, 9, 0, 0), 120);
+        let mut project = project();
+        assert_eq!(
+            get_week_total(&conn, &project, Weekday::Mon, &now).unwrap(),
+            2 * 3600
+        );
+
+        // Sent to the server, then the projects fetched: the server's total now includes
+        // those two hours, next to one hour tracked on another device
+        let sent = db::get_session_by_uuid(&conn, "synced").unwrap().unwrap();
+        db::mark_session_synced(&conn, &sent).unwrap();
+        let synced = db::get_synced_times(&conn).unwrap();
+        project.total_hours_this_week = Some(3.0);
+        db::set_baseline_times(&conn, &synced).unwrap();
+        assert_eq!(
+            get_week_total(&conn, &project, Weekday::Mon, &now).unwrap(),
+            3 * 3600
+        );
+
+        // Time the fetched total doesn't include yet is added on top
+        tracked(&conn, "unsynced", local(2026, 6, 10, 9, 0, 0), 30);
+        assert_eq!(
+            get_week_total(&conn, &project, Weekday::Mon, &now).unwrap(),
+            3 * 3600 + 30 * 60
+        );
+    }
+
+    #[test]
+    fn totals_fetched_last_week_are_ignored() {
+        let conn = test_db();
+        let now = local(2026, 6, 10, 12, 0, 0);
+        tracked(&conn, "monday", local(2026, 6, 8, 9, 0, 0), 60);
+        let last_week = local(2026, 6, 5, 12, 0, 0).timestamp_millis();
+        db::set_setting(
+            &conn,
+            db::WEEKLY_TOTALS_FETCHED_SETTING,
+            &last_week.to_string(),
+        )
+        .unwrap();
+
+        let mut project = project();
+        project.total_hours_this_week = Some(5.0);
+        assert_eq!(
+            get_week_total(&conn, &project, Weekday::Mon, &now).unwrap(),
+            3600
+        );
+    }
+}
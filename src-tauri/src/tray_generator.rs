@@ -1,7 +1,10 @@
-use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
 use image::imageops::{resize, FilterType};
-use image::{ImageBuffer, Rgba};
-use imageproc::drawing::{draw_text_mut, text_size};
+use image::{ImageBuffer, Rgba, RgbaImage};
+use imageproc::drawing::draw_filled_circle_mut;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::{Mutex, OnceLock};
 use tauri::image::Image;
 
 #[cfg(target_os = "windows")]
@@ -12,98 +15,373 @@ const BASE_HEIGHT: u32 = 40;
 
 const FONT_DATA: &[u8] = include_bytes!("Roboto-Regular.ttf");
 const ICON_DATA: &[u8] = include_bytes!("../icons/32x32.png");
-pub fn generate_tray_icon(text: &str) -> Option<Image<'static>> {
-    let font = FontRef::try_from_slice(FONT_DATA).expect("Error constructing Font");
 
-    // We want a high-res render to ensure smooth text and rounded corners
-    let scale_factor = 4; // Render at 4x resolution
+/// What the timer is doing, as far as the icon is concerned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IconState {
+    LoggedOut,
+    Idle,
+    Tracking,
+    Paused,
+    OnBreak,
+    IdlePrompt,
+    LimitReached,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+pub struct TrayIconSpec<'a> {
+    pub text: &'a str,
+    pub state: IconState,
+    /// Drawn as an arc around the logo: daily limit used, or break time left (0.0 to 1.0).
+    pub progress: Option<f32>,
+    pub theme: Theme,
+}
 
-    // "Bigger" text
-    #[cfg(target_os = "windows")]
-    let base_text_size = 42.0;
+struct Palette {
+    background: Rgba<u8>,
+    text: Rgba<u8>,
+    /// Badge and progress arc; `None` for states without a badge.
+    accent: Option<Rgba<u8>>,
+    /// The unfilled part of the progress ring.
+    track: Rgba<u8>,
+}
 
-    #[cfg(not(target_os = "windows"))]
-    let base_text_size = 32.0;
-    let render_scale = PxScale {
-        x: base_text_size * scale_factor as f32,
-        y: base_text_size * scale_factor as f32,
+fn palette(state: IconState, theme: Theme) -> Palette {
+    let (background, text, track) = match theme {
+        Theme::Light => (
+            Rgba([255, 255, 255, 255]),
+            Rgba([10, 10, 10, 255]),
+            Rgba([220, 220, 220, 255]),
+        ),
+        Theme::Dark => (
+            Rgba([38, 38, 42, 255]),
+            Rgba([240, 240, 240, 255]),
+            Rgba([80, 80, 86, 255]),
+        ),
+    };
+    let accent = match state {
+        IconState::LoggedOut | IconState::Idle => None,
+        IconState::Tracking => Some(Rgba([34, 197, 94, 255])),
+        IconState::Paused => Some(Rgba([245, 158, 11, 255])),
+        IconState::OnBreak => Some(Rgba([59, 130, 246, 255])),
+        IconState::IdlePrompt => Some(Rgba([168, 85, 247, 255])),
+        IconState::LimitReached => Some(Rgba([239, 68, 68, 255])),
+    };
+    // Blocked is the one state worth shouting about
+    let text = match (state, accent) {
+        (IconState::LimitReached, Some(red)) => red,
+        _ => text,
     };
+    Palette {
+        background,
+        text,
+        accent,
+        track,
+    }
+}
 
-    let scaled_font = font.as_scaled(render_scale);
-    let ascent = scaled_font.ascent();
-    let (text_w, _) = text_size(render_scale, &font, text);
+/// Builds the tray image for `spec`. Called every second, so everything that doesn't
+/// change from one second to the next is cached in `RENDERER`.
+pub fn generate_tray_icon(spec: &TrayIconSpec) -> Option<Image<'static>> {
+    let mut renderer = RENDERER
+        .get_or_init(|| Mutex::new(TrayRenderer::new()))
+        .lock()
+        .unwrap();
+    let (pixels, width, height) = renderer.render(spec);
+    Some(Image::new_owned(pixels, width, height))
+}
 
-    // "Less packed" -> More padding
-    let base_icon_size = BASE_HEIGHT; // New size to match base_height
-    let render_icon_size = base_icon_size * scale_factor;
+// Rendering
+//
+// The logo square (logo, progress ring, badge) is drawn at 4x and downscaled, like the
+// whole icon used to be, but only when its state, theme or progress step changes. The
+// time is composed at final resolution from per-character coverage masks that are
+// rasterized once; Roboto's digits share one advance, so the pill keeps its width as
+// the clock runs.
+//
+// The text is not pixel-identical to the old path, which drew it at 4x and let the
+// Lanczos downscale smooth it: edges are now plain coverage, slightly crisper and
+// without the faint ringing. The old "thicker text" loop drew a single pass (its
+// offset, 0.1 * 4, truncated to 0), so nothing is lost there. The snapshot tests
+// below pin the current output.
+
+// We want a high-res render to ensure smooth rounded shapes
+const SCALE_FACTOR: u32 = 4;
+
+// "Bigger" text
+#[cfg(target_os = "windows")]
+const TEXT_SIZE: f32 = 42.0;
 
-    #[cfg(target_os = "windows")]
-    let base_padding_h = 32;
+#[cfg(not(target_os = "windows"))]
+const TEXT_SIZE: f32 = 32.0;
 
-    #[cfg(not(target_os = "windows"))]
-    let base_padding_h = 24;
-    let render_padding_h = base_padding_h * scale_factor;
+// "Less packed" -> More padding
+#[cfg(target_os = "windows")]
+const PADDING_H: u32 = 32;
 
-    // "Bigger" overall -> Taller height
-    let base_height = BASE_HEIGHT;
-    let render_height = base_height * scale_factor;
+#[cfg(not(target_os = "windows"))]
+const PADDING_H: u32 = 24;
 
-    let render_width = render_icon_size + render_padding_h + text_w as u32 + render_padding_h;
+#[cfg(target_os = "windows")]
+const CORNER_RADIUS: f32 = 8.0;
 
-    let mut canvas =
-        ImageBuffer::from_pixel(render_width, render_height, Rgba([255, 255, 255, 255]));
+#[cfg(not(target_os = "windows"))]
+const CORNER_RADIUS: f32 = 4.0;
 
-    // 1. Draw Icon
-    if let Ok(icon_img) = image::load_from_memory(ICON_DATA) {
-        let icon_resized = resize(
-            &icon_img.to_rgba8(),
-            render_icon_size,
-            render_icon_size,
-            FilterType::Lanczos3,
-        );
-        image::imageops::overlay(&mut canvas, &icon_resized, 0, 0);
-    }
+/// The ring is redrawn in steps of 1/120th of a turn (3°), not every second.
+const PROGRESS_STEPS: f32 = 120.0;
 
-    // 2. Centering Logic (Adjusted)
-    let text_x = (render_icon_size + render_padding_h) as i32;
+static RENDERER: OnceLock<Mutex<TrayRenderer>> = OnceLock::new();
 
-    // OPTICAL ADJUSTMENT:
-    // We take the center of the canvas and subtract half the ascent,
-    // then subtract a bit more (15% of render_height) to push it "up".
-    let optical_offset = (render_height as f32 * 0.10) as i32;
-    let text_y = ((render_height as f32 / 2.0 - ascent / 2.0) as i32) - optical_offset;
+/// A character's anti-aliased coverage, relative to the pen position on the baseline.
+struct Glyph {
+    left: i32,
+    top: i32,
+    width: u32,
+    coverage: Vec<f32>,
+    advance: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct ChromeKey {
+    state: IconState,
+    theme: Theme,
+    progress_step: Option<u32>,
+}
+
+struct TrayRenderer {
+    font: FontRef<'static>,
+    scale: PxScale,
+    logo: Option<RgbaImage>,
+    /// The logo resized for each size it has been drawn at.
+    logos: HashMap<u32, RgbaImage>,
+    glyphs: HashMap<char, Glyph>,
+    /// The last logo square drawn, at final resolution.
+    chrome: Option<(ChromeKey, RgbaImage)>,
+}
 
-    let text_color = Rgba([10, 10, 10, 255]);
+impl TrayRenderer {
+    fn new() -> Self {
+        let font = FontRef::try_from_slice(FONT_DATA).expect("Error constructing Font");
+        let logo = image::load_from_memory(ICON_DATA)
+            .map(|img| img.to_rgba8())
+            .map_err(|e| eprintln!("Failed to decode tray logo: {}", e))
+            .ok();
+        TrayRenderer {
+            font,
+            scale: PxScale::from(TEXT_SIZE),
+            logo,
+            logos: HashMap::new(),
+            glyphs: HashMap::new(),
+            chrome: None,
+        }
+    }
+
+    fn render(&mut self, spec: &TrayIconSpec) -> (Vec<u8>, u32, u32) {
+        let palette = palette(spec.state, spec.theme);
+        let icon_size = BASE_HEIGHT;
+        let height = BASE_HEIGHT;
+
+        let text_w = spec
+            .text
+            .chars()
+            .map(|c| self.glyph(c).advance)
+            .sum::<f32>()
+            .ceil() as u32;
+        let width = icon_size + PADDING_H + text_w + PADDING_H;
+
+        let mut canvas = ImageBuffer::from_pixel(width, height, palette.background);
+
+        // 1. Logo square
+        let key = ChromeKey {
+            state: spec.state,
+            theme: spec.theme,
+            progress_step: spec
+                .progress
+                .map(|p| (p.clamp(0.0, 1.0) * PROGRESS_STEPS).round() as u32),
+        };
+        if self.chrome.as_ref().map(|(k, _)| *k) != Some(key) {
+            let chrome = self.draw_chrome(key, &palette);
+            self.chrome = Some((key, chrome));
+        }
+        if let Some((_, chrome)) = &self.chrome {
+            image::imageops::replace(&mut canvas, chrome, 0, 0);
+        }
 
-    // 3. Draw Thicker Text (Increased thickness multiplier)
-    let thickness = (0.1 * scale_factor as f32) as i32;
-    for dx in 0..=thickness {
-        for dy in 0..=thickness {
-            draw_text_mut(
+        // 2. Centering Logic
+        //
+        // OPTICAL ADJUSTMENT:
+        // We take the center of the canvas and subtract half the ascent,
+        // then subtract a bit more (10% of height) to push it "up".
+        let ascent = self.font.as_scaled(self.scale).ascent();
+        let optical_offset = (height as f32 * 0.10) as i32;
+        let text_top = ((height as f32 / 2.0 - ascent / 2.0) as i32) - optical_offset;
+        let baseline = text_top + ascent.round() as i32;
+
+        // 3. Text, from cached glyphs
+        let mut pen_x = (icon_size + PADDING_H) as f32;
+        for c in spec.text.chars() {
+            let glyph = self.glyph(c);
+            blend_glyph(
                 &mut canvas,
-                text_color,
-                text_x + dx,
-                text_y + dy,
-                render_scale,
-                &font,
-                text,
+                glyph,
+                pen_x.round() as i32,
+                baseline,
+                palette.text,
             );
+            pen_x += glyph.advance;
         }
+
+        // 4. Corners
+        apply_rounded_corners(&mut canvas, CORNER_RADIUS);
+
+        (canvas.into_raw(), width, height)
+    }
+
+    fn glyph(&mut self, c: char) -> &Glyph {
+        let font = &self.font;
+        let scale = self.scale;
+        self.glyphs.entry(c).or_insert_with(|| {
+            let scaled = font.as_scaled(scale);
+            let id = font.glyph_id(c);
+            let advance = scaled.h_advance(id);
+            let glyph = id.with_scale_and_position(scale, point(0.0, 0.0));
+            match font.outline_glyph(glyph) {
+                Some(outline) => {
+                    let bounds = outline.px_bounds();
+                    let width = bounds.width() as u32;
+                    let height = bounds.height() as u32;
+                    let mut coverage = vec![0.0; (width * height) as usize];
+                    outline.draw(|x, y, c| coverage[(y * width + x) as usize] = c);
+                    Glyph {
+                        left: bounds.min.x as i32,
+                        top: bounds.min.y as i32,
+                        width,
+                        coverage,
+                        advance,
+                    }
+                }
+                // Whitespace and anything Roboto can't draw
+                None => Glyph {
+                    left: 0,
+                    top: 0,
+                    width: 0,
+                    coverage: Vec::new(),
+                    advance,
+                },
+            }
+        })
     }
 
-    // 4. Downscale & Corners
-    let target_width = render_width / scale_factor;
-    let target_height = render_height / scale_factor;
-    let mut small = resize(&canvas, target_width, target_height, FilterType::Lanczos3);
+    /// Logo, progress ring and badge on the pill background, drawn at 4x and downscaled
+    /// to the icon height.
+    fn draw_chrome(&mut self, key: ChromeKey, palette: &Palette) -> RgbaImage {
+        let render_icon_size = BASE_HEIGHT * SCALE_FACTOR;
+        let mut square =
+            ImageBuffer::from_pixel(render_icon_size, render_icon_size, palette.background);
+
+        // Inset the logo to make room for the progress ring when there is one
+        let progress = key.progress_step.map(|step| step as f32 / PROGRESS_STEPS);
+        let logo_size = if progress.is_some() {
+            render_icon_size * 5 / 8
+        } else {
+            render_icon_size
+        };
+        let logo_offset = ((render_icon_size - logo_size) / 2) as i64;
+        if let Some(logo) = &self.logo {
+            let resized = self
+                .logos
+                .entry(logo_size)
+                .or_insert_with(|| resize(logo, logo_size, logo_size, FilterType::Lanczos3));
+            image::imageops::overlay(&mut square, resized, logo_offset, logo_offset);
+        }
+
+        let center = render_icon_size as f32 / 2.0;
+        if let Some(progress) = progress {
+            let outer = center - SCALE_FACTOR as f32;
+            let thickness = render_icon_size as f32 * 0.11;
+            let fill = palette.accent.unwrap_or(palette.text);
+            draw_arc(&mut square, center, outer, thickness, 1.0, palette.track);
+            draw_arc(&mut square, center, outer, thickness, progress, fill);
+        }
+        if let Some(accent) = palette.accent {
+            let radius = (render_icon_size as f32 * 0.16) as i32;
+            let at = (
+                render_icon_size as i32 - radius - SCALE_FACTOR as i32,
+                render_icon_size as i32 - radius - SCALE_FACTOR as i32,
+            );
+            // Ringed in the background color so it reads against the logo
+            draw_filled_circle_mut(
+                &mut square,
+                at,
+                radius + 2 * SCALE_FACTOR as i32,
+                palette.background,
+            );
+            draw_filled_circle_mut(&mut square, at, radius, accent);
+        }
 
-    #[cfg(target_os = "windows")]
-    apply_rounded_corners(&mut small, 8.0);
+        resize(&square, BASE_HEIGHT, BASE_HEIGHT, FilterType::Lanczos3)
+    }
+}
 
-    #[cfg(not(target_os = "windows"))]
-    apply_rounded_corners(&mut small, 4.0);
+/// Mixes `color` into the canvas by the glyph's coverage, with the pen at `(x, baseline)`.
+fn blend_glyph(canvas: &mut RgbaImage, glyph: &Glyph, x: i32, baseline: i32, color: Rgba<u8>) {
+    let (w, h) = canvas.dimensions();
+    for (i, &alpha) in glyph.coverage.iter().enumerate() {
+        if alpha <= 0.0 {
+            continue;
+        }
+        let px = x + glyph.left + (i as u32 % glyph.width) as i32;
+        let py = baseline + glyph.top + (i as u32 / glyph.width) as i32;
+        if px < 0 || py < 0 || px as u32 >= w || py as u32 >= h {
+            continue;
+        }
+        let alpha = alpha.min(1.0);
+        let dst = canvas.get_pixel_mut(px as u32, py as u32);
+        for channel in 0..3 {
+            dst[channel] =
+                (dst[channel] as f32 * (1.0 - alpha) + color[channel] as f32 * alpha).round() as u8;
+        }
+    }
+}
 
-    let raw_pixels = small.into_raw();
-    Some(Image::new_owned(raw_pixels, target_width, target_height))
+/// Paints a ring segment centered in the logo square, clockwise from 12 o'clock, covering
+/// `fraction` of the full circle.
+fn draw_arc(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    center: f32,
+    outer_radius: f32,
+    thickness: f32,
+    fraction: f32,
+    color: Rgba<u8>,
+) {
+    if fraction <= 0.0 {
+        return;
+    }
+    let inner_radius = outer_radius - thickness;
+    let sweep = fraction * 2.0 * PI;
+    let extent = (center + outer_radius).ceil() as u32;
+
+    for y in 0..extent.min(img.height()) {
+        for x in 0..extent.min(img.width()) {
+            let dx = x as f32 + 0.5 - center;
+            let dy = y as f32 + 0.5 - center;
+            let dist = (dx * dx + dy * dy).sqrt();
+            if dist < inner_radius || dist > outer_radius {
+                continue;
+            }
+            // Angle measured clockwise from straight up, in 0..2π
+            let angle = dx.atan2(-dy).rem_euclid(2.0 * PI);
+            if angle <= sweep {
+                img.put_pixel(x, y, color);
+            }
+        }
+    }
 }
 
 fn apply_rounded_corners(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, radius: f32) {
@@ -175,3 +453,168 @@ fn apply_rounded_corners(img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, radius: f32)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha256};
+
+    const STATES: [IconState; 7] = [
+        IconState::LoggedOut,
+        IconState::Idle,
+        IconState::Tracking,
+        IconState::Paused,
+        IconState::OnBreak,
+        IconState::IdlePrompt,
+        IconState::LimitReached,
+    ];
+
+    fn render(renderer: &mut TrayRenderer, spec: &TrayIconSpec) -> String {
+        let (pixels, width, height) = renderer.render(spec);
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+        format!("{}x{} {:x}", width, height, Sha256::digest(&pixels))
+    }
+
+    fn spec(state: IconState, theme: Theme, progress: Option<f32>) -> TrayIconSpec<'static> {
+        TrayIconSpec {
+            text: "1:23:45",
+            state,
+            progress,
+            theme,
+        }
+    }
+
+    /// Compares every rendering with its checked-in hash and lists all the mismatches
+    /// at once, so an intended change can be copied back in one go.
+    fn assert_snapshots(cases: &[(TrayIconSpec, &str)]) {
+        let mut renderer = TrayRenderer::new();
+        let mismatches: Vec<String> = cases
+            .iter()
+            .filter_map(|(spec, expected)| {
+                let actual = render(&mut renderer, spec);
+                (actual != *expected).then(|| {
+                    format!(
+                        "{:?} {:?} {:?}: \"{}\"",
+                        spec.state, spec.theme, spec.progress, actual
+                    )
+                })
+            })
+            .collect();
+        assert!(mismatches.is_empty(), "\n{}", mismatches.join("\n"));
+    }
+
+    // Hashes are of the Linux build: Windows draws at another height, and the ring's
+    // atan2 comes from the platform's libm.
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn every_state_in_both_themes_matches_its_snapshot() {
+        let expected = [
+            // Light, in the order of `STATES`
+            "168x40 a14e379b32bf330c9fd85e57fb3d25abfeb5ecab515ac976031712a2da22df55",
+            "168x40 a14e379b32bf330c9fd85e57fb3d25abfeb5ecab515ac976031712a2da22df55",
+            "168x40 a9a16e04adac1add257d7c89c608a4c5f7ef52266ced15e115ca3f577b049a9f",
+            "168x40 1a19ee3a42293b8dd2a6efdeece0b6b9202c6b72d9923a91f9ab662d408959ec",
+            "168x40 442bd5396b5b2ae3dbb4706333f6096930b45aeae24d4ded4b02b3f282465e30",
+            "168x40 8772180ccbd76f212e806ed183477d43eccd6e9a8aed5724e8b80e6c259a644c",
+            "168x40 5bce1801ffdd476655d352c90dd30a490a80bacc18bf98b6a9a88bb5353d1fc9",
+            // Dark
+            "168x40 fc7e78ccd4e958e54d92cdc6e314a9151ebd5d51fc7a2519b7289d56988d9a41",
+            "168x40 fc7e78ccd4e958e54d92cdc6e314a9151ebd5d51fc7a2519b7289d56988d9a41",
+            "168x40 eff1fc0d91113adbdfd771ffd3e7f47e58ea640a84afc3d40ffc62d63fc8d045",
+            "168x40 7920cb74c3d88ce5e34dd478428af5ec85b55f8fae8ce6a03fe26dbe6220f446",
+            "168x40 981e848b040b256156c4e59ea509fd32978bff9d6a02de8e65c469e12f8a79c4",
+            "168x40 4a4eb97845691b26ff54f781e9c08eef4d084c5b6f547d8a81f3e7faf9902348",
+            "168x40 65b79d6e532b8c1b82ea6dae2372c40a06d2d2e5528af1038ec245238755c485",
+        ];
+        let cases: Vec<_> = [Theme::Light, Theme::Dark]
+            .iter()
+            .flat_map(|&theme| STATES.iter().map(move |&state| spec(state, theme, None)))
+            .zip(expected)
+            .collect();
+        assert_snapshots(&cases);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn progress_ring_matches_its_snapshot() {
+        assert_snapshots(&[
+            (
+                spec(IconState::Tracking, Theme::Light, Some(0.0)),
+                "168x40 12745fd5f0fe6ac457e6c8846479230801536b99c496144f1b1aef7c8a24a5c4",
+            ),
+            (
+                spec(IconState::Tracking, Theme::Light, Some(0.37)),
+                "168x40 fd4991a7d6ef0382bd39afac4dfa7e97a67f74a0f8dd72a9e1752a548d993583",
+            ),
+            (
+                spec(IconState::Tracking, Theme::Dark, Some(0.37)),
+                "168x40 9a2511cc96a75873779823060a8f11e37cacdc03f32361c83440035f946a89b6",
+            ),
+            (
+                spec(IconState::OnBreak, Theme::Light, Some(0.75)),
+                "168x40 809ce9067d8207aba1bd9852cf553a14211710e9be9ff6161908fd906dfb9a6c",
+            ),
+            (
+                spec(IconState::LimitReached, Theme::Dark, Some(1.0)),
+                "168x40 bdc556804f873e58a94722c94e3a724b054fe2033bc350ae1d45dfa0a20c0f29",
+            ),
+        ]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn text_matches_its_snapshot() {
+        let text = |text| TrayIconSpec {
+            text,
+            state: IconState::Idle,
+            progress: None,
+            theme: Theme::Light,
+        };
+        assert_snapshots(&[
+            (
+                text("0:00:00"),
+                "168x40 689f05f7e536f0c6aafde5edf0c2d69fecb1a6096c2198d967d5f019c656e31c",
+            ),
+            (
+                text("12:34:56"),
+                "182x40 46e85611faab05560e615c9600d3f14f7882f449b7a8da24467abd7faadc8a7a",
+            ),
+            (
+                text("Login"),
+                "148x40 6c622e546ff28af6c59297d286274d4a7b2415071490fbdb993b8b85cbb90c23",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn cached_render_matches_a_fresh_one() {
+        let mut warm = TrayRenderer::new();
+        for state in STATES {
+            render(&mut warm, &spec(state, Theme::Dark, Some(0.5)));
+        }
+        for state in STATES {
+            for progress in [None, Some(0.2)] {
+                let spec = spec(state, Theme::Light, progress);
+                assert_eq!(
+                    render(&mut warm, &spec),
+                    render(&mut TrayRenderer::new(), &spec)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn progress_within_one_step_reuses_the_ring() {
+        let mut renderer = TrayRenderer::new();
+        let a = render(
+            &mut renderer,
+            &spec(IconState::OnBreak, Theme::Light, Some(0.500)),
+        );
+        let b = render(
+            &mut renderer,
+            &spec(IconState::OnBreak, Theme::Light, Some(0.502)),
+        );
+        assert_eq!(a, b);
+    }
+}
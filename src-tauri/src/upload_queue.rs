@@ -0,0 +1,553 @@
+use crate::api;
+use crate::db;
+use crate::encoder;
+use crate::environment::ApiEnvironment;
+use crate::error::WatchtowerError;
+use crate::models::PendingScreenshot;
+use crate::AppState;
+use reqwest::multipart::{Form, Part};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{async_runtime, AppHandle, Emitter, Manager, Runtime};
+use tokio::sync::Semaphore;
+
+// Screenshot Upload Queue
+//
+// Sends pending screenshots as multipart uploads with at most `concurrency` requests in
+// flight. Transient failures are retried a few times within a pass; items that still fail
+// are deferred in the database with an exponential delay so later passes skip them.
+
+pub const CONCURRENCY_SETTING: &str = "upload_concurrency";
+pub const DEFAULT_CONCURRENCY: usize = 3;
+pub const MAX_CONCURRENCY: usize = 8;
+
+/// How long (seconds) a screenshot is held back for review before it may be uploaded.
+pub const REVIEW_DELAY_SETTING: &str = "screenshot_review_seconds";
+pub const MAX_REVIEW_DELAY_SECS: i64 = 24 * 60 * 60;
+
+const MAX_ATTEMPTS_PER_PASS: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const MAX_DEFERRAL_MS: i64 = 60 * 60 * 1000;
+
+pub struct UploadQueue {
+    queued: AtomicUsize,
+    in_flight: AtomicUsize,
+    failed: AtomicUsize,
+    is_draining: AtomicBool,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadQueueStatus {
+    pub queued: usize,
+    pub in_flight: usize,
+    pub failed: usize,
+}
+
+impl UploadQueue {
+    pub fn new() -> Self {
+        Self {
+            queued: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            is_draining: AtomicBool::new(false),
+        }
+    }
+
+    pub fn status(&self) -> UploadQueueStatus {
+        UploadQueueStatus {
+            queued: self.queued.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub fn concurrency(conn: &Connection) -> usize {
+    db::get_setting(conn, CONCURRENCY_SETTING)
+        .ok()
+        .flatten()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .map(|n| n.min(MAX_CONCURRENCY))
+        .unwrap_or(DEFAULT_CONCURRENCY)
+}
+
+pub fn review_delay_secs(conn: &Connection) -> i64 {
+    db::get_setting(conn, REVIEW_DELAY_SETTING)
+        .ok()
+        .flatten()
+        .and_then(|v| v.parse::<i64>().ok())
+        .map(|secs| secs.clamp(0, MAX_REVIEW_DELAY_SECS))
+        .unwrap_or(0)
+}
+
+enum Outcome {
+    Uploaded,
+    /// Network errors, 5xx and 429: worth trying again.
+    Retryable(String),
+    /// Any other rejection; retrying within this pass won't help.
+    Rejected(String),
+    /// The image file is gone, so the row can never be uploaded.
+    Missing,
+}
+
+/// Uploads `items` and returns the ids that are finished with (uploaded, or whose file is
+/// gone) and can be removed. Each row is claimed before it is sent; finished rows stay
+/// claimed until the caller removes them. Returns nothing if another pass is still running.
+pub async fn drain<R: Runtime>(
+    app: &AppHandle<R>,
+    items: Vec<PendingScreenshot>,
+    environment: &ApiEnvironment,
+    store_dir: PathBuf,
+    db_path: PathBuf,
+    concurrency: usize,
+) -> Vec<i64> {
+    let queue = app.state::<AppState>().upload_queue.clone();
+
+    if queue.is_draining.swap(true, Ordering::SeqCst) {
+        println!("Upload: Previous pass still running, skipping");
+        return Vec::new();
+    }
+
+    println!(
+        "Upload: Sending {} screenshots, {} at a time",
+        items.len(),
+        concurrency
+    );
+    queue.queued.store(items.len(), Ordering::Relaxed);
+    emit_status(app, &queue);
+
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut handles = Vec::new();
+
+    for item in items {
+        let app_inner = app.clone();
+        let queue_inner = queue.clone();
+        let semaphore = semaphore.clone();
+        let store_dir = store_dir.clone();
+        let db_path = db_path.clone();
+        let environment = environment.clone();
+
+        handles.push(async_runtime::spawn(async move {
+            let _permit = semaphore.acquire_owned().await.ok()?;
+            queue_inner.queued.fetch_sub(1, Ordering::Relaxed);
+
+            // Review may have deleted it while it waited, or be deleting it right now
+            let id = item.id;
+            let claim_path = db_path.clone();
+            let claimed = async_runtime::spawn_blocking(move || {
+                Connection::open(&claim_path).and_then(|conn| db::claim_screenshot(&conn, id))
+            })
+            .await;
+            if !matches!(claimed, Ok(Ok(true))) {
+                println!("Upload: {} was taken back on review, skipping", id);
+                emit_status(&app_inner, &queue_inner);
+                return None;
+            }
+            queue_inner.in_flight.fetch_add(1, Ordering::Relaxed);
+            emit_status(&app_inner, &queue_inner);
+
+            let result = upload_with_retry(&item, || {
+                upload_one(&app_inner, &item, &environment, &store_dir)
+            })
+            .await;
+
+            queue_inner.in_flight.fetch_sub(1, Ordering::Relaxed);
+            let done = match result {
+                Ok(()) => Some(item.id),
+                Err(e) => {
+                    eprintln!("Upload: Giving up on {} for now: {}", item.id, e);
+                    let id = item.id;
+                    let _ = async_runtime::spawn_blocking(move || {
+                        if let Ok(conn) = Connection::open(&db_path) {
+                            let now = chrono::Local::now().timestamp_millis();
+                            let _ = db::defer_screenshot(&conn, id, &e, now, MAX_DEFERRAL_MS);
+                        }
+                    })
+                    .await;
+                    None
+                }
+            };
+            emit_status(&app_inner, &queue_inner);
+            done
+        }));
+    }
+
+    let mut finished = Vec::new();
+    for handle in handles {
+        if let Ok(Some(id)) = handle.await {
+            finished.push(id);
+        }
+    }
+
+    // Failed = everything currently waiting out a deferral, not just this pass
+    let deferred = async_runtime::spawn_blocking(move || {
+        Connection::open(&db_path)
+            .and_then(|conn| db::count_deferred_screenshots(&conn))
+            .unwrap_or(0)
+    })
+    .await
+    .unwrap_or(0);
+    queue
+        .failed
+        .store(deferred.max(0) as usize, Ordering::Relaxed);
+    queue.queued.store(0, Ordering::Relaxed);
+    queue.is_draining.store(false, Ordering::SeqCst);
+    emit_status(app, &queue);
+
+    finished
+}
+
+/// Runs `upload` until it succeeds, retrying transient failures a few times with a growing
+/// delay. An error means the caller should defer the item to a later pass.
+async fn upload_with_retry<F, Fut>(item: &PendingScreenshot, mut upload: F) -> Result<(), String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Outcome>,
+{
+    let mut delay = RETRY_BASE_DELAY;
+    let mut attempt = 1;
+
+    loop {
+        match upload().await {
+            Outcome::Uploaded => {
+                println!("Upload: Success for {}", item.id);
+                return Ok(());
+            }
+            Outcome::Missing => {
+                eprintln!("Upload: File for {} is missing, dropping it", item.id);
+                return Ok(());
+            }
+            Outcome::Rejected(e) => return Err(e),
+            Outcome::Retryable(e) if attempt >= MAX_ATTEMPTS_PER_PASS => return Err(e),
+            Outcome::Retryable(e) => {
+                eprintln!(
+                    "Upload: Attempt {} for {} failed ({}), retrying in {:?}",
+                    attempt, item.id, e, delay
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+async fn upload_one<R: Runtime>(
+    app: &AppHandle<R>,
+    item: &PendingScreenshot,
+    environment: &ApiEnvironment,
+    store_dir: &Path,
+) -> Outcome {
+    let path = store_dir.join(&item.file_path);
+    if is_missing(item, &path) {
+        return Outcome::Missing;
+    }
+    // The row belongs to the database of the environment the pass started in
+    if let Err(e) = api::ensure_environment(app, environment) {
+        return Outcome::Rejected(e.to_string());
+    }
+
+    match api::request_multipart(app, "/desktop/screenshots", || screenshot_form(item, &path)).await
+    {
+        Ok(response) => {
+            let status = response.status();
+            if status.is_success() {
+                Outcome::Uploaded
+            } else if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                Outcome::Retryable(format!("Status {}", status))
+            } else {
+                Outcome::Rejected(format!("Status {}", status))
+            }
+        }
+        Err(e @ WatchtowerError::AuthExpired { .. }) | Err(e @ WatchtowerError::Storage { .. }) => {
+            Outcome::Rejected(e.to_string())
+        }
+        Err(e) => Outcome::Retryable(e.to_string()),
+    }
+}
+
+/// Whether the image a row would upload is gone. Markers carry no image, so the earlier
+/// file they repeat may well be gone by now without that mattering.
+fn is_missing(item: &PendingScreenshot, path: &Path) -> bool {
+    !item.unchanged && !path.exists()
+}
+
+/// Builds the upload form, streaming the image straight from the store. Unchanged
+/// markers send the fields only, with `contentHash` naming the image they repeat.
+fn screenshot_form(item: &PendingScreenshot, path: &Path) -> Result<Form, WatchtowerError> {
+    let mut form = Form::new();
+    for (name, value) in form_fields(item) {
+        form = form.text(name, value);
+    }
+    Ok(match image_part(item, path)? {
+        Some(image) => form.part("image", image),
+        None => form,
+    })
+}
+
+/// The store names files `<hash>.<ext>` after the codec they were encoded with.
+fn file_ext(item: &PendingScreenshot) -> &str {
+    Path::new(&item.file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("webp")
+}
+
+fn form_fields(item: &PendingScreenshot) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("sessionUuid", item.session_uuid.clone()),
+        ("projectId", item.project_id.clone()),
+        ("timestamp", item.timestamp.to_string()),
+        ("contentHash", item.content_hash.clone()),
+        ("fileExt", file_ext(item).to_string()),
+        (
+            "monitors",
+            serde_json::to_string(&item.monitors).unwrap_or_else(|_| "[]".to_string()),
+        ),
+    ];
+    if let Some(perceptual_hash) = &item.perceptual_hash {
+        fields.push(("perceptualHash", perceptual_hash.clone()));
+    }
+    for (name, value) in [
+        ("appName", &item.context.app_name),
+        ("windowTitle", &item.context.window_title),
+        ("url", &item.context.url),
+    ] {
+        if let Some(value) = value {
+            fields.push((name, value.clone()));
+        }
+    }
+    fields.push(("keyboardEvents", item.context.keyboard_events.to_string()));
+    fields.push(("mouseEvents", item.context.mouse_events.to_string()));
+    if item.unchanged {
+        fields.push(("unchanged", "true".to_string()));
+    }
+    fields
+}
+
+/// The image itself, or `None` for a marker.
+fn image_part(item: &PendingScreenshot, path: &Path) -> Result<Option<Part>, WatchtowerError> {
+    if item.unchanged {
+        return Ok(None);
+    }
+    let file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let body = reqwest::Body::from(tokio::fs::File::from_std(file));
+
+    let image = Part::stream_with_length(body, len)
+        .file_name(item.file_path.clone())
+        .mime_str(encoder::mime_type(file_ext(item)))?;
+    Ok(Some(image))
+}
+
+fn emit_status<R: Runtime>(app: &AppHandle<R>, queue: &UploadQueue) {
+    let _ = app.emit("upload-status", queue.status());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrations;
+    use crate::models::{CaptureContext, MonitorInfo};
+    use std::cell::Cell;
+
+    fn test_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run(&mut conn).unwrap();
+        conn
+    }
+
+    fn item(file_path: &str, unchanged: bool) -> PendingScreenshot {
+        PendingScreenshot {
+            id: 7,
+            session_uuid: "session-1".to_string(),
+            project_id: "project-1".to_string(),
+            timestamp: 1_750_000_000_000,
+            file_path: file_path.to_string(),
+            file_size: 100,
+            content_hash: "abc123".to_string(),
+            monitors: Vec::new(),
+            perceptual_hash: None,
+            unchanged,
+            context: CaptureContext::default(),
+        }
+    }
+
+    /// Runs the retry loop against canned outcomes and returns its result and how many
+    /// attempts it made.
+    async fn retried(outcomes: Vec<Outcome>) -> (Result<(), String>, usize) {
+        let attempts = Cell::new(0);
+        let mut outcomes = outcomes.into_iter();
+        let result = upload_with_retry(&item("a.webp", false), || {
+            attempts.set(attempts.get() + 1);
+            let outcome = outcomes.next().expect("no more attempts expected");
+            async move { outcome }
+        })
+        .await;
+        (result, attempts.get())
+    }
+
+    fn retryable() -> Outcome {
+        Outcome::Retryable("Status 503".to_string())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn transient_failures_are_retried_then_given_up() {
+        let (result, attempts) = retried(vec![retryable(), Outcome::Uploaded]).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(attempts, 2);
+
+        let started = tokio::time::Instant::now();
+        let (result, attempts) = retried(vec![retryable(), retryable(), retryable()]).await;
+        assert_eq!(result, Err("Status 503".to_string()));
+        assert_eq!(attempts, MAX_ATTEMPTS_PER_PASS as usize);
+        // Two waits in between: 2s, then 4s
+        assert_eq!(started.elapsed(), Duration::from_secs(6));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rejection_is_given_up_without_retrying() {
+        let (result, attempts) = retried(vec![Outcome::Rejected("Status 400".to_string())]).await;
+        assert_eq!(result, Err("Status 400".to_string()));
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn missing_file_is_finished_with() {
+        let (result, attempts) = retried(vec![Outcome::Missing]).await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn only_images_can_go_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.webp");
+        assert!(is_missing(&item("a.webp", false), &path));
+        // A marker's earlier image may be gone; it doesn't send it
+        assert!(!is_missing(&item("a.webp", true), &path));
+
+        std::fs::write(&path, b"image").unwrap();
+        assert!(!is_missing(&item("a.webp", false), &path));
+    }
+
+    #[test]
+    fn deferral_doubles_up_to_the_cap() {
+        let conn = test_db();
+        conn.execute(
+            "INSERT INTO pending_screenshots (id, session_uuid, project_id, timestamp, file_path) VALUES (7, 'session-1', 'project-1', 0, 'a.webp')",
+            [],
+        )
+        .unwrap();
+        db::claim_screenshot(&conn, 7).unwrap();
+
+        let mut delays = Vec::new();
+        for _ in 0..8 {
+            db::defer_screenshot(&conn, 7, "Status 503", 0, MAX_DEFERRAL_MS).unwrap();
+            let next: i64 = conn
+                .query_row(
+                    "SELECT next_attempt_at FROM pending_screenshots WHERE id = 7",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            delays.push(next / 60_000);
+        }
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 32, 60, 60]);
+        // The claim is given back so a later pass can pick it up
+        assert!(db::claim_screenshot(&conn, 7).unwrap());
+    }
+
+    fn field<'a>(fields: &'a [(&str, String)], name: &str) -> Option<&'a str> {
+        fields
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn form_fields_describe_the_screenshot() {
+        let mut screenshot = item("abc123.jpg", false);
+        screenshot.perceptual_hash = Some("00ff00ff00ff00ff".to_string());
+        screenshot.monitors = vec![MonitorInfo {
+            name: "DP-1".to_string(),
+            x: -1920,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            is_primary: false,
+        }];
+        screenshot.context.app_name = Some("Editor".to_string());
+        screenshot.context.keyboard_events = 12;
+
+        let fields = form_fields(&screenshot);
+        assert_eq!(field(&fields, "fileExt"), Some("jpg"));
+        assert_eq!(field(&fields, "contentHash"), Some("abc123"));
+        assert_eq!(field(&fields, "perceptualHash"), Some("00ff00ff00ff00ff"));
+        assert_eq!(field(&fields, "appName"), Some("Editor"));
+        assert_eq!(field(&fields, "windowTitle"), None);
+        assert_eq!(field(&fields, "keyboardEvents"), Some("12"));
+        assert_eq!(field(&fields, "unchanged"), None);
+        let monitors: Vec<MonitorInfo> =
+            serde_json::from_str(field(&fields, "monitors").unwrap()).unwrap();
+        assert_eq!(monitors[0].x, -1920);
+
+        // Rows from before hashing send no perceptual hash; old names default to webp
+        let fields = form_fields(&item("legacy", false));
+        assert_eq!(field(&fields, "perceptualHash"), None);
+        assert_eq!(field(&fields, "fileExt"), Some("webp"));
+    }
+
+    #[test]
+    fn marker_form_carries_no_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc123.webp");
+        let marker = item("abc123.webp", true);
+        assert_eq!(field(&form_fields(&marker), "unchanged"), Some("true"));
+        // Not even looked for; the file it repeats may be gone
+        assert!(image_part(&marker, &path).unwrap().is_none());
+
+        std::fs::write(&path, b"image").unwrap();
+        assert!(image_part(&item("abc123.webp", false), &path)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn concurrency_setting_is_kept_in_range() {
+        let conn = test_db();
+        assert_eq!(concurrency(&conn), DEFAULT_CONCURRENCY);
+        for (value, expected) in [
+            ("5", 5),
+            ("0", DEFAULT_CONCURRENCY),
+            ("-1", DEFAULT_CONCURRENCY),
+            ("many", DEFAULT_CONCURRENCY),
+            ("100", MAX_CONCURRENCY),
+        ] {
+            db::set_setting(&conn, CONCURRENCY_SETTING, value).unwrap();
+            assert_eq!(concurrency(&conn), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn review_delay_setting_is_kept_in_range() {
+        let conn = test_db();
+        assert_eq!(review_delay_secs(&conn), 0);
+        for (value, expected) in [
+            ("300", 300),
+            ("-5", 0),
+            ("soon", 0),
+            ("999999", MAX_REVIEW_DELAY_SECS),
+        ] {
+            db::set_setting(&conn, REVIEW_DELAY_SETTING, value).unwrap();
+            assert_eq!(review_delay_secs(&conn), expected, "{}", value);
+        }
+    }
+}
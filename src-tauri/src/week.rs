@@ -0,0 +1,128 @@
+use chrono::{DateTime, Datelike, Days, NaiveTime, TimeDelta, TimeZone, Weekday};
+
+// Week Boundaries
+//
+// Weekly limits count from the first day of the week as the user's locale defines it
+// (Monday for most regions, Sunday for e.g. the US, Saturday for parts of the Middle
+// East), following CLDR's `firstDay` data.
+
+const SUNDAY_REGIONS: &[&str] = &[
+    "AG", "AS", "BD", "BR", "BS", "BT", "BW", "BZ", "CA", "CN", "CO", "DM", "DO", "ET", "GT", "GU",
+    "HK", "HN", "ID", "IL", "IN", "JM", "JP", "KE", "KH", "KR", "LA", "MH", "MM", "MO", "MT", "MX",
+    "MZ", "NI", "NP", "PA", "PE", "PH", "PK", "PR", "PT", "PY", "SA", "SG", "SV", "TH", "TT", "TW",
+    "UM", "US", "VE", "VI", "WS", "YE", "ZA", "ZW",
+];
+const SATURDAY_REGIONS: &[&str] = &[
+    "AE", "AF", "BH", "DJ", "DZ", "EG", "IQ", "IR", "JO", "KW", "LY", "OM", "QA", "SD", "SY",
+];
+
+/// First day of the week for the system locale.
+pub fn locale_week_start() -> Weekday {
+    sys_locale::get_locale()
+        .map(|locale| week_start_for(&locale))
+        .unwrap_or(Weekday::Mon)
+}
+
+/// First day of the week for a locale tag such as `en-US`, `en_US.UTF-8` or `pt-Latn-BR`.
+/// Tags without a region fall back to Monday.
+pub fn week_start_for(locale: &str) -> Weekday {
+    let tag = locale.split(['.', '@']).next().unwrap_or_default();
+    let region = tag
+        .split(['-', '_'])
+        .skip(1)
+        .find(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(|part| part.to_ascii_uppercase());
+
+    match region.as_deref() {
+        Some(r) if SUNDAY_REGIONS.contains(&r) => Weekday::Sun,
+        Some(r) if SATURDAY_REGIONS.contains(&r) => Weekday::Sat,
+        _ => Weekday::Mon,
+    }
+}
+
+/// Midnight at the start of the week containing `now`, in `now`'s zone, as epoch
+/// milliseconds.
+pub fn start_of_week<Tz: TimeZone>(now: &DateTime<Tz>, week_start: Weekday) -> i64 {
+    let days_back = now.weekday().days_since(week_start) as u64;
+    let first_day = now.date_naive() - Days::new(days_back);
+    let midnight = first_day.and_time(NaiveTime::MIN);
+    // Midnight can fall in a DST gap; the hour after it always exists
+    midnight
+        .and_local_timezone(now.timezone())
+        .earliest()
+        .or_else(|| {
+            (midnight + TimeDelta::hours(1))
+                .and_local_timezone(now.timezone())
+                .earliest()
+        })
+        .map(|start| start.timestamp_millis())
+        .unwrap_or_else(|| now.timestamp_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::America::{New_York, Sao_Paulo};
+    use chrono_tz::Tz;
+
+    fn at(tz: Tz, y: i32, mo: u32, d: u32, h: u32) -> DateTime<Tz> {
+        tz.with_ymd_and_hms(y, mo, d, h, 0, 0).earliest().unwrap()
+    }
+
+    fn utc_ms(y: i32, mo: u32, d: u32, h: u32) -> i64 {
+        chrono::Utc
+            .with_ymd_and_hms(y, mo, d, h, 0, 0)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn week_start_follows_the_locale_region() {
+        assert_eq!(week_start_for("en-US"), Weekday::Sun);
+        assert_eq!(week_start_for("en_US.UTF-8"), Weekday::Sun);
+        assert_eq!(week_start_for("pt-Latn-BR"), Weekday::Sun);
+        assert_eq!(week_start_for("de-DE"), Weekday::Mon);
+        assert_eq!(week_start_for("en-GB"), Weekday::Mon);
+        assert_eq!(week_start_for("ar_EG"), Weekday::Sat);
+        assert_eq!(week_start_for("en"), Weekday::Mon);
+        assert_eq!(week_start_for(""), Weekday::Mon);
+    }
+
+    #[test]
+    fn sunday_and_monday_weeks_start_on_their_own_day() {
+        // Wednesday 2026-06-10
+        let now = at(New_York, 2026, 6, 10, 15);
+        assert_eq!(start_of_week(&now, Weekday::Sun), utc_ms(2026, 6, 7, 4));
+        assert_eq!(start_of_week(&now, Weekday::Mon), utc_ms(2026, 6, 8, 4));
+
+        // On the first day itself, the week starts that midnight
+        let sunday = at(New_York, 2026, 6, 7, 0);
+        assert_eq!(
+            start_of_week(&sunday, Weekday::Sun),
+            sunday.timestamp_millis()
+        );
+        assert_eq!(start_of_week(&sunday, Weekday::Mon), utc_ms(2026, 6, 1, 4));
+    }
+
+    #[test]
+    fn week_across_a_dst_change_starts_at_midnight_before_it() {
+        // Clocks went forward on Sunday 2026-03-08; the week began at midnight EST
+        let now = at(New_York, 2026, 3, 11, 12);
+        assert_eq!(start_of_week(&now, Weekday::Mon), utc_ms(2026, 3, 9, 4));
+        assert_eq!(start_of_week(&now, Weekday::Sun), utc_ms(2026, 3, 8, 5));
+
+        // And back on Sunday 2026-11-01, from EDT
+        let now = at(New_York, 2026, 11, 4, 12);
+        assert_eq!(start_of_week(&now, Weekday::Sun), utc_ms(2026, 11, 1, 4));
+    }
+
+    #[test]
+    fn week_starting_in_a_dst_gap_starts_an_hour_later() {
+        // Brazil moved clocks from midnight to 01:00 on Sunday 2018-11-04
+        let now = at(Sao_Paulo, 2018, 11, 6, 12);
+        let start = start_of_week(&now, Weekday::Sun);
+        assert_eq!(start, utc_ms(2018, 11, 4, 3));
+        let start = Sao_Paulo.timestamp_millis_opt(start).unwrap();
+        assert_eq!(start.time(), NaiveTime::from_hms_opt(1, 0, 0).unwrap());
+    }
+}
@@ -4,7 +4,8 @@ import { listen } from "@tauri-apps/api/event";
 import { getVersion } from "@tauri-apps/api/app";
 import { Routes, Route } from "react-router-dom";
 import Login from "./Login";
-import { fetchProjects } from "./services/auth";
+import { fetchProjects, logoutUser, Project, User } from "./services/auth";
+import { describeError, isWatchtowerError, WatchtowerError } from "./services/errors";
 import "./App.css";
 import { IdleWindow } from "./components/IdleWindow";
 import { QuitWindow } from "./components/QuitWindow";
@@ -13,23 +14,12 @@ import { BreakModal } from "./components/BreakModal";
 import { check } from "@tauri-apps/plugin-updater";
 import { relaunch } from "@tauri-apps/plugin-process";
 
-interface Project {
-  id: string;
-  name: string;
-  weeklyLimitHours: number | null;
-  dailyLimitHours: number | null;
-  screenshotsEnabled: boolean;
-  totalHoursThisWeek: number | null;
-}
-
-interface User {
+interface RecoveredSession {
   uuid: string;
-  name: string;
-  email: string;
-  token: string;
-  refresh_token?: string;
-  projects: Project[];
-  current_project_id?: string;
+  projectId: string;
+  projectType: string;
+  startTime: number;
+  endTime: number | null;
 }
 
 interface TimeUpdatePayload {
@@ -140,6 +130,17 @@ function MainWindow() {
     }
     const unlistenLogin = listen("request-login", () => checkAuth());
     const unlistenLogout = listen("logout-user", () => checkAuth());
+    // Limits and weekly totals, refreshed in the background
+    const unlistenProjects = listen<Project[]>("projects-updated", async () => {
+      const updated = await invoke<User | null>("check_auth");
+      if (updated) setUser(updated);
+    });
+    // Project switched from the tray
+    const unlistenProjectChanged = listen<string>("project-changed", (event) => {
+      setUser((current) => current && { ...current, current_project_id: event.payload });
+    });
+    // Each environment has its own user and sessions; start over from a clean state
+    const unlistenEnvironment = listen("environment-changed", () => window.location.reload());
     const unlistenTime = listen<TimeUpdatePayload>("time-update", (event) => {
       setSessionTime(event.payload.time);
       setSessionType(event.payload.projectType);
@@ -150,41 +151,51 @@ function MainWindow() {
       setIsActive(event.payload);
     });
 
-    const unlistenLimit = listen<string>("limit-reached", (event) => {
-      alert(event.payload);
+    const unlistenLimit = listen<WatchtowerError>("limit-reached", (event) => {
+      alert(describeError(event.payload));
     });
 
+    // Sessions closed at their last heartbeat after a crash. The event can fire before
+    // this listener exists, so the list is drained via command on both paths.
+    showRecoveredSessions();
+    const unlistenRecovered = listen("sessions-recovered", () => showRecoveredSessions());
+
     return () => {
       unlistenLogin.then(f => f());
+      unlistenProjectChanged.then(f => f());
       unlistenLogout.then(f => f());
+      unlistenEnvironment.then(f => f());
+      unlistenProjects.then(f => f());
       unlistenTime.then(f => f());
       unlistenActive.then(f => f());
       unlistenLimit.then(f => f());
+      unlistenRecovered.then(f => f());
     };
   }, []);
 
+  async function showRecoveredSessions() {
+    try {
+      const recovered = await invoke<RecoveredSession[]>("get_recovered_sessions");
+      if (recovered.length === 0) return;
+      const lines = recovered.map(s => {
+        const start = new Date(s.startTime).toLocaleTimeString();
+        const end = s.endTime ? new Date(s.endTime).toLocaleTimeString() : start;
+        return `${start} - ${end}`;
+      });
+      alert(`Watchtower closed unexpectedly. ${recovered.length} session(s) were stopped at their last recorded activity:\n${lines.join("\n")}`);
+    } catch (e) {
+      console.error("Failed to fetch recovered sessions", e);
+    }
+  }
+
   async function checkAuth() {
     try {
       let user = await invoke<User | null>("check_auth");
-      if (user && user.token) {
+      if (user) {
         try {
-          // Fetch fresh projects
-          console.log(user.token)
-          const fetchedProjects = await fetchProjects(user.token);
-          const mappedProjects: Project[] = fetchedProjects.map(p => ({
-            id: p.id,
-            name: p.name,
-            weeklyLimitHours: p.weeklyLimitHours,
-            dailyLimitHours: p.dailyLimitHours,
-            screenshotsEnabled: p.screenshotsEnabled,
-            totalHoursThisWeek: p.totalHoursThisWeek
-          }));
-
-          // Update user object
-          user = { ...user, projects: mappedProjects };
-
-          // Persist valid projects to DB
-          await invoke("login", { user });
+          // Fetch fresh projects; the backend stores them and may re-pick the current one
+          await fetchProjects();
+          user = await invoke<User | null>("check_auth");
         } catch (e) {
           console.error("Failed to refresh projects in background", e);
         }
@@ -206,7 +217,7 @@ function MainWindow() {
   }
 
   async function handleLogout() {
-    await invoke("logout");
+    await logoutUser();
     checkAuth();
   }
 
@@ -223,6 +234,12 @@ function MainWindow() {
       }
     } catch (err) {
       console.error("Failed to set project", err);
+      // Switching with the timer running checks the new project's limits and permissions
+      if (isWatchtowerError(err) && err.kind === "limitReached") {
+        alert(describeError(err));
+      } else if (isWatchtowerError(err) && err.kind === "permissionDenied") {
+        setShowPermissions(true);
+      }
     }
   }
 
@@ -242,10 +259,12 @@ function MainWindow() {
       }
     } catch (err) {
       console.error("Failed to toggle timer", err);
-      if (typeof err === "string" && err.includes("limit reached")) {
-        alert(err);
+      if (isWatchtowerError(err) && err.kind === "limitReached") {
+        alert(describeError(err));
+      } else if (isWatchtowerError(err) && err.kind === "permissionDenied") {
+        setShowPermissions(true);
       } else {
-        alert("Failed to toggle timer: " + err);
+        alert("Failed to toggle timer: " + describeError(err));
       }
     }
   }
@@ -277,7 +296,6 @@ function MainWindow() {
       {showBreakModal && user?.current_project_id && (
         <BreakModal
           projectId={user.current_project_id}
-          token={user.token}
           onClose={() => setShowBreakModal(false)}
           onStartBreak={() => setIsActive(true)}
         />
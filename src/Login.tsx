@@ -1,9 +1,8 @@
 import React, { useEffect, useState } from "react";
-import { invoke } from "@tauri-apps/api/core";
-
 import LoginLayout from "./layouts/LoginLayout";
 import { fadeInBackgroundElements } from "./utils/layoutFunctions";
 import { authenticateUser } from "./services/auth";
+import { describeError } from "./services/errors";
 
 export default function Login({ onLogin }: { onLogin: (user: any) => void }) {
     const [email, setEmail] = useState("");
@@ -23,14 +22,11 @@ export default function Login({ onLogin }: { onLogin: (user: any) => void }) {
         setError(null);
 
         try {
-            const userPayload = await authenticateUser(email, password);
-            console.log(userPayload)
-            await invoke("login", { user: userPayload });
-            onLogin(userPayload);
+            const user = await authenticateUser(email, password);
+            onLogin(user);
         } catch (err) {
             console.error("Login failed", err);
-            const message = err instanceof Error ? err.message : "Invalid email or password";
-            setError(message);
+            setError(describeError(err));
         } finally {
             setIsLoading(false);
         }
@@ -4,12 +4,11 @@ import { fetchWorkBreakPolicies, WorkBreakPolicy } from "../services/auth";
 
 interface BreakModalProps {
     projectId: string;
-    token: string;
     onClose: () => void;
     onStartBreak: () => void;
 }
 
-export function BreakModal({ projectId, token, onClose, onStartBreak }: BreakModalProps) {
+export function BreakModal({ projectId, onClose, onStartBreak }: BreakModalProps) {
     const [policies, setPolicies] = useState<WorkBreakPolicy[]>([]);
     const [loading, setLoading] = useState(true);
     const [selectedPolicyId, setSelectedPolicyId] = useState<string>("");
@@ -20,7 +19,7 @@ export function BreakModal({ projectId, token, onClose, onStartBreak }: BreakMod
         async function loadPolicies() {
             try {
                 const [data, usedIds] = await Promise.all([
-                    fetchWorkBreakPolicies(token, projectId),
+                    fetchWorkBreakPolicies(projectId),
                     invoke<string[]>("get_used_break_ids")
                 ]);
 
@@ -38,7 +37,7 @@ export function BreakModal({ projectId, token, onClose, onStartBreak }: BreakMod
             }
         }
         loadPolicies();
-    }, [projectId, token]);
+    }, [projectId]);
 
     const handleStartBreak = async () => {
         const selectedPolicy = policies.find(p => p.id === selectedPolicyId);
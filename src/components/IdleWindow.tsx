@@ -11,7 +11,6 @@ interface User {
     uuid: string;
     name: string;
     email: string;
-    token: string;
     projects: Project[];
     current_project_id?: string;
 }
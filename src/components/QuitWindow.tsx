@@ -1,14 +1,30 @@
-import { useEffect } from "react";
+import { useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { getCurrentWindow } from "@tauri-apps/api/window";
 
+interface UploadStatus {
+    queued: number;
+    inFlight: number;
+    failed: number;
+}
+
 export function QuitWindow() {
+    const [status, setStatus] = useState<UploadStatus | null>(null);
 
     useEffect(() => {
         // Show window on mount
         const win = getCurrentWindow();
         win.show();
         win.setFocus();
+
+        invoke<UploadStatus>("get_upload_status").then(setStatus);
+        const unlistenStatus = listen<UploadStatus>("upload-status", (event) => {
+            setStatus(event.payload);
+        });
+        return () => {
+            unlistenStatus.then(f => f());
+        };
     }, []);
 
     async function handleConfirm() {
@@ -32,6 +48,11 @@ export function QuitWindow() {
             <p className="text-gray-600 mb-8 max-w-xs mx-auto">
                 You have screenshots waiting to be uploaded. We will upload them before quitting.
             </p>
+            {status && (status.queued > 0 || status.inFlight > 0 || status.failed > 0) && (
+                <p className="text-xs text-gray-500 -mt-6 mb-6">
+                    {status.queued} queued · {status.inFlight} uploading · {status.failed} failed
+                </p>
+            )}
 
             <div className="flex gap-3 justify-center w-full">
                 <button
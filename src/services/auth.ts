@@ -1,76 +1,52 @@
-import axios from 'axios';
+import { invoke } from '@tauri-apps/api/core';
 
-const API_URL = import.meta.env.VITE_API_URL || 'https://watchtower.staging-api.nykon.cloud';
-
-const api = axios.create({
-    baseURL: API_URL,
-});
-
-export interface User {
-    id: string;
-    name: string;
-    email: string;
-    createdAt: string;
-    updatedAt: string;
-}
+// Login, logout and project data all go through Rust commands (see auth.rs); tokens
+// never reach the webview.
 
 export interface Project {
     id: string;
     name: string;
-    organizationId: string;
-    createdAt: string;
-    updatedAt: string;
     weeklyLimitHours: number | null;
     dailyLimitHours: number | null;
     screenshotsEnabled: boolean;
     totalHoursThisWeek: number | null;
+    chunkPolicy: ChunkPolicy | null;
+    monitorLayout: MonitorLayout | null;
+    captureSchedule: CaptureSchedule | null;
+    blurLevel: BlurLevel | null;
+    encodingProfile: EncodingProfile | null;
 }
 
-interface LoginResponse {
-    account: User;
-    credentials: {
-        access: {
-            token: string;
-            expires: string;
-        };
-        refresh: {
-            token: string;
-            expires: string;
-        };
-    };
+/** Screenshot size and codec; `null` means the user's choice or `standard`. */
+export interface EncodingProfile {
+    preset: 'thumbnail' | 'standard' | 'highDetail';
+    codec?: 'webp' | 'webpLossless' | 'jpeg' | 'png' | null;
 }
 
-export const authenticateUser = async (email: string, password: string) => {
-    // 1. Call to base_url/v1/auth/login
-    const authResponse = await api.post<LoginResponse>('/v1/auth/login', {
-        email,
-        password
-    });
+/** Whole-screenshot blur set by the organization; `null` means none. */
+export type BlurLevel = 'none' | 'light' | 'strong';
 
-    const { account, credentials } = authResponse.data;
-    const token = credentials.access.token;
+/** When screenshots are taken; `null` means every 120 seconds. */
+export type CaptureSchedule =
+    | { mode: 'fixed'; intervalSeconds: number }
+    | { mode: 'random'; perWindow: number; windowMinutes: number };
 
-    // 2. Call to base_url/v1/projects using the token
-    const projects = await fetchProjects(token);
-    const project = projects[0];
+/** How screenshots cover multiple displays; `null` means `composite`. */
+export type MonitorLayout = 'composite' | 'perMonitor';
 
-    // 3. Construct the payload
-    // We combine the user data, the token, and the projects list.
-    // We also map 'id' to 'uuid' if the backend expects it, but we'll keep the spread clean.
-    const payload = {
-        name: account.name,
-        email: account.email,
-        token,
-        refresh_token: credentials.refresh.token,
-        projects: projects,
-        // We can add a derived field for existing logic if needed, 
-        // but for now we basically merge the info.
-        uuid: account.id, // For compatibility with typical frontend usage if it expects uuid
-        current_project_id: project?.id ?? null,
-    };
+/** How running sessions are cut into chunks; `chunkMinutes: null` splits at the hour only. */
+export interface ChunkPolicy {
+    chunkMinutes: number | null;
+    splitAtHour: boolean;
+}
 
-    return payload;
-};
+export interface User {
+    uuid: string;
+    name: string;
+    email: string;
+    projects: Project[];
+    current_project_id?: string | null;
+}
 
 export interface WorkBreakPolicy {
     id: string;
@@ -83,23 +59,12 @@ export interface WorkBreakPolicy {
     projectIds: string[];
 }
 
-export const fetchProjects = async (token: string) => {
-    const projectsResponse = await api.get<{ results: Project[] }>(`/v1/desktop/projects?projectType=watchtower`, {
-        headers: {
-            Authorization: `Bearer ${token}`,
-            'x-app-source': 'desktop'
-        }
-    });
-    console.log(projectsResponse.data.results)
-    return projectsResponse.data.results;
-};
+export const authenticateUser = (email: string, password: string) =>
+    invoke<User>('login', { email, password });
+
+export const logoutUser = () => invoke('logout');
+
+export const fetchProjects = () => invoke<Project[]>('refresh_projects');
 
-export const fetchWorkBreakPolicies = async (token: string, projectId: string) => {
-    const response = await api.get<WorkBreakPolicy[]>(`/v1/desktop/projects/${projectId}/work-break-policy`, {
-        headers: {
-            Authorization: `Bearer ${token}`,
-            'x-app-source': 'desktop'
-        }
-    });
-    return response.data;
-};
+export const fetchWorkBreakPolicies = (projectId: string) =>
+    invoke<WorkBreakPolicy[]>('get_work_break_policies', { projectId });
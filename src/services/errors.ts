@@ -0,0 +1,45 @@
+// Mirrors `WatchtowerError` in src-tauri/src/error.rs. Commands reject with one of these
+// objects, so branch on `kind` rather than matching the message text.
+
+export type LimitKind = 'daily' | 'weekly';
+
+export type WatchtowerError =
+    | { kind: 'limitReached'; projectId: string; limit: LimitKind; limitHours: number; trackedHours: number }
+    | { kind: 'authExpired'; reason: string }
+    | { kind: 'notAuthenticated' }
+    | { kind: 'environmentChanged' }
+    | { kind: 'network'; message: string; status: number | null }
+    | { kind: 'database'; message: string }
+    | { kind: 'permissionDenied'; permission: string }
+    | { kind: 'capture'; message: string }
+    | { kind: 'storage'; message: string }
+    | { kind: 'invalidInput'; message: string }
+    | { kind: 'internal'; message: string };
+
+export function isWatchtowerError(err: unknown): err is WatchtowerError {
+    return typeof err === 'object' && err !== null && typeof (err as { kind?: unknown }).kind === 'string';
+}
+
+export function describeError(err: unknown): string {
+    if (!isWatchtowerError(err)) {
+        return String(err);
+    }
+    switch (err.kind) {
+        case 'limitReached': {
+            const label = err.limit === 'daily' ? 'Daily' : 'Weekly';
+            return `${label} limit of ${err.limitHours}h reached for this project`;
+        }
+        case 'authExpired':
+            return 'Your session has expired. Please log in again.';
+        case 'notAuthenticated':
+            return 'You are not logged in.';
+        case 'environmentChanged':
+            return 'The API environment was changed. Please try again.';
+        case 'permissionDenied':
+            return `Permission not granted: ${err.permission}`;
+        case 'network':
+            return err.status ? `Request failed (${err.status}): ${err.message}` : `Network error: ${err.message}`;
+        default:
+            return err.message;
+    }
+}